@@ -11,7 +11,7 @@ use lazy_static::lazy_static;
 use log::info;
 use spin::{Mutex};
 
-use crate::{BlockDeviceTrait, VfsOps, blueosfs::BlueosFileSystem, root::{self, RootFileSystem}, vfs::{NodeType, VfsError, VfsNodeOps}};
+use crate::{BlockDeviceTrait, VfsOps, blueosfs::BlueosFileSystem, root::{self, RootFileSystem}, vfs::{FormatOptions, DEFAULT_FS_BLOCK_SIZE, NodeType, VfsError, VfsNodeOps}};
 
 ///初始化全局根文件系统
 lazy_static!{
@@ -24,18 +24,26 @@ fn get_rootfs()->Result<Arc<RootFileSystem>,VfsError>{
 }
 
 /// 文件系统初始化（内部函数）
-fn initial_file_system(block_device:Arc<dyn BlockDeviceTrait>){
+fn initial_file_system(block_device:Arc<dyn BlockDeviceTrait>,options:FormatOptions){
     let root_fs_guard = ROOT_FS.lock();
     let main_fs = root_fs_guard.as_ref().expect("No File System").main_fs();
     drop(root_fs_guard);
     
-    main_fs.initial_file_systeam(block_device).expect("File system initialization failed");
+    main_fs.initial_file_systeam(block_device,options).expect("File system initialization failed");
 }
 
 ///文件系统初始化（完整初始化，包括格式化检查）
 /// 注意：块设备必须已经通过 set_global_block_device 设置
+/// 未格式化时按设备大小格式化
 pub fn initial_root_filesystem(){
     use crate::vfs::get_block_device;
+    let block_device = get_block_device().expect("Block device not initialized. Call set_global_block_device first.");
+    initial_root_filesystem_with(FormatOptions::for_device(&block_device, DEFAULT_FS_BLOCK_SIZE));
+}
+
+///文件系统初始化 未格式化时按options格式化，已经格式化的按超级块里的布局挂载
+pub fn initial_root_filesystem_with(options:FormatOptions){
+    use crate::vfs::get_block_device;
     
     // 创建主文件系统
     let main_fs = BlueosFileSystem::new();
@@ -54,9 +62,14 @@ pub fn initial_root_filesystem(){
     if !main_fs.verify_file_system() {
         // 文件系统未格式化，需要初始化
         info!("File system not formatted, initializing...");
-        initial_file_system(block_device);
+        initial_file_system(block_device,options);
     } else {
         info!("File system already formatted");
+        // 挂载时读超级块得到布局，把位图读入内存，之后分配回收都在内存里做
+        let layout = crate::blueosfs::mount_layout(&block_device);
+        info!("Mount BlueosFS: {} blocks of {} bytes, {} inodes", layout.total_blocks(), layout.block_size, layout.inode_count);
+        let fs_device = crate::blueosfs::fs_device().expect("No File System device");
+        crate::bitmap::load_bitmaps(&fs_device);
     }
     
     info!("RootFileSystem Initial complete!");
@@ -194,6 +207,14 @@ impl FileDescriptor {
     pub fn size(&self)->usize{
         self.node.get_attribute().size
     }
+    ///获取文件节点
+    pub fn node(&self)->Arc<dyn VfsNodeOps>{
+        self.node.clone()
+    }
+    ///获取打开标志
+    pub fn flags(&self)->FileFlags{
+        self.flags
+    }
 }
 
 
@@ -311,3 +332,10 @@ pub fn is_dir(path:&str)->bool{
         Err(_)=>{return false;}
     }
 }
+
+///把块设备上缓存的脏块全部写回
+pub fn sync(){
+    if let Some(block_device)=crate::vfs::get_block_device(){
+        block_device.sync();
+    }
+}
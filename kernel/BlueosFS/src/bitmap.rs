@@ -1,9 +1,10 @@
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{collections::BTreeSet, sync::Arc, vec, vec::Vec};
 
 use crate::{BlockDeviceTrait, vfs::BLOCK_SIZE};
-use crate::{BlueosFileSystem, DATABITMAP_COUNT, INODEBITMAP_COUNT};
-use alloc::vec;
-pub struct inode_index(pub u8);
+use crate::blueosfs::FsLayout;
+use lazy_static::lazy_static;
+use spin::Mutex;
+pub struct inode_index(pub u32);
 pub struct data_index(pub u32);  // 改为 u32 以支持更多数据块
 
 ///位图分配单元 一个inode n个datamap
@@ -80,7 +81,150 @@ pub struct SuperBlock{
     pub magic:u32,
     pub inode_bitmap_block_count:u32,
     pub data_bitmap_block_count:u32,
-    pub pad_:[u8;(BLOCK_SIZE - 4*3) as usize]
+    ///块大小 旧格式为0，按BLOCK_SIZE和位图块数推算布局
+    pub block_size:u32,
+    pub inode_count:u32,
+    pub data_block_count:u32,
+    pub pad_:[u8;(BLOCK_SIZE - 4*6) as usize]
+}
+
+
+///磁盘位图在内存中的副本 挂载时整块读入，分配和回收只改内存，改过的位图块再写回
+///第i位在第i/8字节的第i%8位，和磁盘格式一致
+pub struct DiskBitmap{
+    ///位图在磁盘上的起始块号
+    start_block:usize,
+    ///文件系统块大小
+    block_size:usize,
+    bits:Vec<u64>,
+    ///总位数
+    total:usize,
+    free:usize,
+    ///next-fit 下一次从这个字开始找
+    hint:usize,
+    ///改过还没写回的位图块(相对start_block)
+    dirty:BTreeSet<usize>,
+}
+
+impl DiskBitmap {
+    ///从磁盘读入block_count个位图块 只有前total位可用，最后一块多出来的位在内存里当作已用
+    pub fn load(block_device:&Arc<dyn BlockDeviceTrait>,block_size:usize,start_block:usize,block_count:usize,total:usize)->Self{
+        let mut bits=Vec::with_capacity(block_count*block_size/8);
+        let mut block=vec![0u8;block_size];
+        for i in 0..block_count{
+            block_device.read_block(start_block+i, &mut block);
+            bits.extend(block.chunks(8).map(|word|u64::from_le_bytes(word.try_into().unwrap())));
+        }
+        for bit in total..bits.len()*64{
+            bits[bit/64]|=1<<(bit%64);
+        }
+        let used=bits.iter().map(|word|word.count_ones() as usize).sum::<usize>();
+        DiskBitmap { start_block, block_size, free: bits.len()*64-used, bits, total, hint: 0, dirty: BTreeSet::new() }
+    }
+
+    pub fn free(&self)->usize{
+        self.free
+    }
+
+    pub fn is_set(&self,bit:usize)->bool{
+        bit<self.total && self.bits[bit/64] & (1<<(bit%64))!=0
+    }
+
+    fn mark_dirty(&mut self,bit:usize){
+        self.dirty.insert(bit/(self.block_size*8));
+    }
+
+    ///标记一位为已用 已经是已用返回false
+    pub fn set(&mut self,bit:usize)->bool{
+        if self.is_set(bit) || bit>=self.total{
+            return false;
+        }
+        self.bits[bit/64]|=1<<(bit%64);
+        self.free-=1;
+        self.mark_dirty(bit);
+        true
+    }
+
+    ///从hint开始找一个空闲位，找到后hint停在这个字上
+    pub fn alloc(&mut self)->Option<usize>{
+        if self.free==0{
+            return None;
+        }
+        let words=self.bits.len();
+        let word_index=(0..words).map(|i|(self.hint+i)%words).find(|&i|self.bits[i]!=u64::MAX)?;
+        let bit=word_index*64+self.bits[word_index].trailing_ones() as usize;
+        self.hint=word_index;
+        self.set(bit);
+        Some(bit)
+    }
+
+    ///回收一位 本来就是空闲返回false
+    pub fn dealloc(&mut self,bit:usize)->bool{
+        if !self.is_set(bit){
+            return false;
+        }
+        self.bits[bit/64]&=!(1<<(bit%64));
+        self.free+=1;
+        self.mark_dirty(bit);
+        true
+    }
+
+    ///只写回改过的位图块
+    pub fn flush(&mut self,block_device:&Arc<dyn BlockDeviceTrait>){
+        let words_per_block=self.block_size/8;
+        let mut block=vec![0u8;self.block_size];
+        for index in core::mem::take(&mut self.dirty){
+            let words=&self.bits[index*words_per_block..(index+1)*words_per_block];
+            for (chunk,word) in block.chunks_mut(8).zip(words){
+                chunk.copy_from_slice(&word.to_le_bytes());
+            }
+            block_device.write_block(self.start_block+index, &block);
+        }
+    }
+}
+
+///文件系统的inode位图和data位图
+pub struct FsBitmaps{
+    pub inode:DiskBitmap,
+    pub data:DiskBitmap,
+}
+
+impl FsBitmaps {
+    ///按挂载的布局读入inode位图和data位图
+    pub fn load(block_device:&Arc<dyn BlockDeviceTrait>,layout:&FsLayout)->Self{
+        FsBitmaps {
+            inode: DiskBitmap::load(block_device, layout.block_size, layout.inode_bitmap_start, layout.inode_bitmap_blocks, layout.inode_count),
+            data: DiskBitmap::load(block_device, layout.block_size, layout.data_bitmap_start, layout.data_bitmap_blocks, layout.data_block_count),
+        }
+    }
+
+    pub fn flush(&mut self,block_device:&Arc<dyn BlockDeviceTrait>){
+        self.inode.flush(block_device);
+        self.data.flush(block_device);
+    }
+}
+
+lazy_static!{
+    static ref FS_BITMAPS:Mutex<Option<FsBitmaps>> = Mutex::new(None);
+}
+
+///挂载时读入位图 格式化之后也要重新读入 block_device按文件系统块读写
+pub fn load_bitmaps(block_device:&Arc<dyn BlockDeviceTrait>){
+    *FS_BITMAPS.lock()=Some(FsBitmaps::load(block_device,&crate::blueosfs::fs_layout()));
+}
+
+///在内存位图上操作，结束后写回改过的位图块 还没读入时先读入
+pub fn with_bitmaps<T>(block_device:&Arc<dyn BlockDeviceTrait>,f:impl FnOnce(&mut FsBitmaps)->T)->T{
+    let mut bitmaps=FS_BITMAPS.lock();
+    let bitmaps=bitmaps.get_or_insert_with(||FsBitmaps::load(block_device,&crate::blueosfs::fs_layout()));
+    let ret=f(bitmaps);
+    bitmaps.flush(block_device);
+    ret
+}
+
+///(空闲inode数,空闲数据块数) 位图还没读入时返回None
+pub fn bitmap_free_counts()->Option<(usize,usize)>{
+    FS_BITMAPS.lock().as_ref().map(|bitmaps|(bitmaps.inode.free(),bitmaps.data.free()))
 }
 
 
@@ -92,196 +236,145 @@ pub trait BitMapAlloctorTrait {
     fn alloc_datamap(count:usize,block_device:Arc<dyn BlockDeviceTrait>)->Option<Bitmap_AllocUnit>;
     ///回收函数
     fn dealloc_datamap(unit:Bitmap_AllocUnit,block_device:Arc<dyn BlockDeviceTrait>)->bool;
+    ///只分配count个数据块，不分配inode
+    fn alloc_data(count:usize,block_device:&Arc<dyn BlockDeviceTrait>)->Option<Vec<data_index>>;
+    ///只回收数据块 已经空闲的块跳过
+    fn dealloc_data(datanode:&[data_index],block_device:&Arc<dyn BlockDeviceTrait>);
 }
 
-
+///在data位图上分配count块 空闲块不够时什么都不分配
+fn alloc_data_blocks(bitmaps:&mut FsBitmaps,count:usize)->Option<Vec<data_index>>{
+    if bitmaps.data.free()<count{
+        return None;
+    }
+    Some((0..count).map(|_|data_index(bitmaps.data.alloc().unwrap() as u32)).collect())
+}
 
 
 impl BitMapAlloctorTrait for  BitMapAlloctor {
     fn alloc_datamap(count:usize,block_device:Arc<dyn BlockDeviceTrait>)->Option<Bitmap_AllocUnit> {
-        use log::{debug, error, warn};
+        use log::{debug, error};
         debug!("[BitMapAlloctor::alloc_datamap] Start: count={}", count);
-        
-        let inode_bitmap_count:usize=INODEBITMAP_COUNT as usize;
-        let data_bitmap_count:usize= DATABITMAP_COUNT as usize;
-        debug!("[BitMapAlloctor::alloc_datamap] inode_bitmap_count={}, data_bitmap_count={}", 
-               inode_bitmap_count, data_bitmap_count);
-        
-        ///inode位图从1号块开始（块0是超级块）
-        let inode_bitmap_start_blockid = 1;
-        let inode_bitmap_end_blockid = 1 + inode_bitmap_count - 1;
-        /// data位图从inode位图块结束之后开始
-        let data_bitmap_start_blockid = inode_bitmap_end_blockid + 1;
-        let data_bitmap_end_blockid = data_bitmap_start_blockid + data_bitmap_count - 1;
-        debug!("[BitMapAlloctor::alloc_datamap] inode_bitmap: blocks {}..={}", 
-               inode_bitmap_start_blockid, inode_bitmap_end_blockid);
-        debug!("[BitMapAlloctor::alloc_datamap] data_bitmap: blocks {}..={}", 
-               data_bitmap_start_blockid, data_bitmap_end_blockid);
-        
-        ///inode bitmap 缓存
-        debug!("[BitMapAlloctor::alloc_datamap] Reading inode bitmap from disk...");
-        let mut inodemap_buffer:Vec<u8> = vec![0; BLOCK_SIZE * inode_bitmap_count];
-        for (i, chunk) in inodemap_buffer.chunks_mut(BLOCK_SIZE).enumerate() {
-            block_device.read_block(inode_bitmap_start_blockid + i, chunk);
-        }
-        debug!("[BitMapAlloctor::alloc_datamap] Inode bitmap read, size={} bytes", inodemap_buffer.len());
-        
-        ///datanode bitmap 缓存
-        debug!("[BitMapAlloctor::alloc_datamap] Reading data bitmap from disk...");
-        let mut datamap_buffer:Vec<u8> = vec![0; BLOCK_SIZE * data_bitmap_count];
-        for (i, chunk) in datamap_buffer.chunks_mut(BLOCK_SIZE).enumerate() {
-            block_device.read_block(data_bitmap_start_blockid + i, chunk);
-        }
-        debug!("[BitMapAlloctor::alloc_datamap] Data bitmap read, size={} bytes", datamap_buffer.len());
-
-        /// 查找空闲的 inode（按位查找）
-        debug!("[BitMapAlloctor::alloc_datamap] Searching for free inode...");
-        let mut inode_number: Option<u32> = None;
-        let max_inode_bits = inode_bitmap_count * BLOCK_SIZE * 8;
-        debug!("[BitMapAlloctor::alloc_datamap] max_inode_bits={}", max_inode_bits);
-        for bit_idx in 0..max_inode_bits {
-            let byte_idx = bit_idx / 8;
-            let bit_offset = bit_idx % 8;
-            let byte = inodemap_buffer[byte_idx];
-            if (byte & (1 << bit_offset)) == 0 {
-                // 找到空闲 inode，标记为已分配
-                debug!("[BitMapAlloctor::alloc_datamap] Found free inode at bit_idx={}", bit_idx);
-                inodemap_buffer[byte_idx] |= 1 << bit_offset;
-                inode_number = Some(bit_idx as u32);
-                // 写回对应的位图块
-                let block_idx = byte_idx / BLOCK_SIZE;
-                let block_start = block_idx * BLOCK_SIZE;
-                let block_data = &inodemap_buffer[block_start..block_start + BLOCK_SIZE];
-                block_device.write_block(inode_bitmap_start_blockid + block_idx, block_data);
-                debug!("[BitMapAlloctor::alloc_datamap] Inode bitmap updated and written to block {}", 
-                       inode_bitmap_start_blockid + block_idx);
-                break;
-            }
-        }
-        
-        let inode_number = match inode_number {
-            Some(n) if n <= u8::MAX as u32 => {
-                debug!("[BitMapAlloctor::alloc_datamap] Allocated inode: {}", n);
-                n as u8
-            }
-            Some(n) => {
-                error!("[BitMapAlloctor::alloc_datamap] ERROR: inode_number {} exceeds u8::MAX", n);
+        with_bitmaps(&block_device, |bitmaps|{
+            if bitmaps.data.free()<count{
+                error!("[BitMapAlloctor::alloc_datamap] ERROR: need {} data blocks, only {} free", count, bitmaps.data.free());
                 return None;
             }
-            None => {
-                error!("[BitMapAlloctor::alloc_datamap] ERROR: No free inode found! max_inode_bits={}", max_inode_bits);
-                return None; // 没有空闲 inode
+            let inode_number=bitmaps.inode.alloc().or_else(||{
+                error!("[BitMapAlloctor::alloc_datamap] ERROR: No free inode found!");
+                None
+            })?;
+            let datanode=alloc_data_blocks(bitmaps, count)?;
+            debug!("[BitMapAlloctor::alloc_datamap] Success: inode_id={}, data_blocks={}", inode_number, datanode.len());
+            Some(Bitmap_AllocUnit { inode: inode_index(inode_number as u32), datanode })
+        })
+    }
+    fn dealloc_datamap(unit:Bitmap_AllocUnit,block_device:Arc<dyn BlockDeviceTrait>)->bool {
+        with_bitmaps(&block_device, |bitmaps|{
+            //该 inode 未被分配，返回错误
+            if !bitmaps.inode.dealloc(unit.inode.0 as usize){
+                return false;
             }
-        };
-
-        /// 查找空闲的 data 块（按位查找）
-        debug!("[BitMapAlloctor::alloc_datamap] Searching for {} free data blocks...", count);
-        let mut datanode_number: Vec<data_index> = Vec::new();
-        let max_data_bits = data_bitmap_count * BLOCK_SIZE * 8;
-        debug!("[BitMapAlloctor::alloc_datamap] max_data_bits={}", max_data_bits);
-        let mut allocated_count = 0;
-        
-        for bit_idx in 0..max_data_bits {
-            if allocated_count >= count {
-                break;
+            //data 块未被分配的跳过（可能已经被回收）
+            for data_idx in &unit.datanode{
+                bitmaps.data.dealloc(data_idx.0 as usize);
             }
-            let byte_idx = bit_idx / 8;
-            let bit_offset = bit_idx % 8;
-            let byte = datamap_buffer[byte_idx];
-            if (byte & (1 << bit_offset)) == 0 {
-                // 找到空闲 data 块，标记为已分配
-                debug!("[BitMapAlloctor::alloc_datamap] Found free data block at bit_idx={}", bit_idx);
-                datamap_buffer[byte_idx] |= 1 << bit_offset;
-                datanode_number.push(data_index(bit_idx as u32));
-                allocated_count += 1;
-                // 写回对应的位图块
-                let block_idx = byte_idx / BLOCK_SIZE;
-                let block_start = block_idx * BLOCK_SIZE;
-                let block_data = &datamap_buffer[block_start..block_start + BLOCK_SIZE];
-                block_device.write_block(data_bitmap_start_blockid + block_idx, block_data);
+            true
+        })
+    }
+    fn alloc_data(count:usize,block_device:&Arc<dyn BlockDeviceTrait>)->Option<Vec<data_index>> {
+        with_bitmaps(block_device, |bitmaps|alloc_data_blocks(bitmaps, count))
+    }
+    fn dealloc_data(datanode:&[data_index],block_device:&Arc<dyn BlockDeviceTrait>) {
+        with_bitmaps(block_device, |bitmaps|{
+            for data_idx in datanode{
+                bitmaps.data.dealloc(data_idx.0 as usize);
             }
-        }
+        })
+    }
+}
 
-        debug!("[BitMapAlloctor::alloc_datamap] Allocated {}/{} data blocks", datanode_number.len(), count);
-        if datanode_number.len() < count {
-            // 如果分配的数据块不足，需要回滚已分配的 inode
-            error!("[BitMapAlloctor::alloc_datamap] ERROR: Failed to allocate enough data blocks! Got {}, needed {}", 
-                   datanode_number.len(), count);
-            debug!("[BitMapAlloctor::alloc_datamap] Rolling back inode allocation...");
-            let byte_idx = inode_number as usize / 8;
-            let bit_offset = inode_number as usize % 8;
-            let block_idx = byte_idx / BLOCK_SIZE;
-            let mut rollback_block = vec![0u8; BLOCK_SIZE];
-            block_device.read_block(inode_bitmap_start_blockid + block_idx, &mut rollback_block);
-            rollback_block[byte_idx % BLOCK_SIZE] &= !(1 << bit_offset);
-            block_device.write_block(inode_bitmap_start_blockid + block_idx, &rollback_block);
-            debug!("[BitMapAlloctor::alloc_datamap] Inode rollback completed");
-            return None;
+#[cfg(test)]
+mod test {
+    use alloc::{sync::Arc, vec, vec::Vec};
+    use spin::Mutex;
+
+    use super::DiskBitmap;
+    use crate::{BlockDeviceTrait, vfs::BLOCK_SIZE};
+
+    ///记录写了哪些块的内存盘
+    struct RamDisk{
+        blocks:Mutex<Vec<[u8;BLOCK_SIZE]>>,
+        writes:Mutex<Vec<usize>>,
+    }
+
+    impl BlockDeviceTrait for RamDisk {
+        fn read_block(&self,block_id:usize,read_buffer:&mut [u8]) {
+            read_buffer.copy_from_slice(&self.blocks.lock()[block_id]);
         }
 
-        let result = Bitmap_AllocUnit{
-            inode:inode_index(inode_number),
-            datanode:datanode_number
-        };
-        debug!("[BitMapAlloctor::alloc_datamap] Success: inode_id={}, data_blocks={}", 
-               result.inode.0, result.datanode.len());
-        Some(result)
-        
+        fn write_block(&self,block_id:usize,write_buffer:&[u8]) {
+            self.blocks.lock()[block_id].copy_from_slice(write_buffer);
+            self.writes.lock().push(block_id);
+        }
     }
-    fn dealloc_datamap(unit:Bitmap_AllocUnit,block_device:Arc<dyn BlockDeviceTrait>)->bool {
-        let inode_bitmap_count:usize=INODEBITMAP_COUNT as usize;
-        let data_bitmap_count:usize= DATABITMAP_COUNT as usize;
-        ///inode位图从1号块开始
-        let inode_bitmap_start_blockid = 1;
-        let inode_bitmap_end_blockid = 1 + inode_bitmap_count - 1;
-        /// data位图从inode位图块结束之后开始
-        let data_bitmap_start_blockid = inode_bitmap_end_blockid + 1;
-        let data_bitmap_end_blockid = data_bitmap_start_blockid + data_bitmap_count - 1;
-
-        /// 回收 inode：按位操作
-        let inode_bit_idx = unit.inode.0 as usize;
-        let inode_byte_idx = inode_bit_idx / 8;
-        let inode_bit_offset = inode_bit_idx % 8;
-        let inode_block_idx = inode_byte_idx / BLOCK_SIZE;
-        
-        // 读取对应的位图块
-        let mut inode_block = vec![0u8; BLOCK_SIZE];
-        block_device.read_block(inode_bitmap_start_blockid + inode_block_idx, &mut inode_block);
-        
-        // 检查该位是否已分配
-        let byte_in_block = inode_byte_idx % BLOCK_SIZE;
-        if (inode_block[byte_in_block] & (1 << inode_bit_offset)) == 0 {
-            // 该 inode 未被分配，返回错误
-            return false;
+
+    ///两个位图块放在1、2号块 只有前4100位可用
+    const START:usize=1;
+    const TOTAL:usize=4100;
+
+    #[test]
+    fn next_fit_wraps_and_flushes_dirty_blocks() {
+        let disk=Arc::new(RamDisk { blocks: Mutex::new(vec![[0u8;BLOCK_SIZE];3]), writes: Mutex::new(Vec::new()) });
+        disk.blocks.lock()[START][0]=0b1;
+        let device:Arc<dyn BlockDeviceTrait>=disk.clone();
+
+        //第二块末尾多出来的位当作已用，不算空闲
+        let mut bitmap=DiskBitmap::load(&device, BLOCK_SIZE, START, 2, TOTAL);
+        assert_eq!(bitmap.free(), TOTAL-1);
+        assert!(bitmap.is_set(0));
+        assert!(!bitmap.is_set(TOTAL));
+
+        //next-fit 回收前面的位后继续从hint所在的字往后分配
+        for bit in 1..=70{
+            assert_eq!(bitmap.alloc(), Some(bit));
         }
-        
-        // 清除位图标记
-        inode_block[byte_in_block] &= !(1 << inode_bit_offset);
-        block_device.write_block(inode_bitmap_start_blockid + inode_block_idx, &inode_block);
-
-        /// 回收 data 块：按位操作
-        for data_idx in &unit.datanode {
-            let data_bit_idx = data_idx.0 as usize;
-            let data_byte_idx = data_bit_idx / 8;
-            let data_bit_offset = data_bit_idx % 8;
-            let data_block_idx = data_byte_idx / BLOCK_SIZE;
-            
-            // 读取对应的位图块
-            let mut data_block = vec![0u8; BLOCK_SIZE];
-            block_device.read_block(data_bitmap_start_blockid + data_block_idx, &mut data_block);
-            
-            // 检查该位是否已分配
-            let byte_in_block = data_byte_idx % BLOCK_SIZE;
-            if (data_block[byte_in_block] & (1 << data_bit_offset)) == 0 {
-                // 该 data 块未被分配，跳过（可能已经被回收）
-                continue;
-            }
-            
-            // 清除位图标记
-            data_block[byte_in_block] &= !(1 << data_bit_offset);
-            block_device.write_block(data_bitmap_start_blockid + data_block_idx, &data_block);
+        assert!(bitmap.dealloc(1));
+        assert_eq!(bitmap.alloc(), Some(71));
+
+        //分配到最后一个可用位为止，不会分配到多出来的位
+        let mut last=71;
+        while bitmap.free()>1{
+            let bit=bitmap.alloc().unwrap();
+            assert!(bit>last);
+            last=bit;
         }
+        assert_eq!(last, TOTAL-1);
+        assert_eq!(bitmap.free(), 1);
 
-        true
+        //hint在末尾，绕回开头找到1
+        assert_eq!(bitmap.alloc(), Some(1));
+        assert_eq!(bitmap.free(), 0);
+        assert_eq!(bitmap.alloc(), None);
+
+        bitmap.flush(&device);
+        disk.writes.lock().clear();
+
+        //没改过不写
+        bitmap.flush(&device);
+        assert!(disk.writes.lock().is_empty());
+
+        //只写回改过的第二块
+        assert!(bitmap.dealloc(TOTAL-1));
+        assert!(!bitmap.dealloc(TOTAL-1));
+        assert_eq!(bitmap.free(), 1);
+        bitmap.flush(&device);
+        assert_eq!(*disk.writes.lock(), vec![START+1]);
+
+        //重新读入和内存里一致
+        let reloaded=DiskBitmap::load(&device, BLOCK_SIZE, START, 2, TOTAL);
+        assert_eq!(reloaded.free(), 1);
+        assert!(!reloaded.is_set(TOTAL-1));
+        assert!(reloaded.is_set(TOTAL-2));
     }
-}
\ No newline at end of file
+}
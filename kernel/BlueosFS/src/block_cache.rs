@@ -0,0 +1,126 @@
+///
+/// 块缓存
+/// 包在任意BlockDeviceTrait外面，按块号缓存最近使用的块
+/// 写只改缓存并标脏，淘汰或者sync时才写回设备
+
+use alloc::boxed::Box;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::sync::Arc;
+use spin::Mutex;
+use crate::vfs::{BlockDeviceTrait, BLOCK_SIZE};
+
+struct CacheEntry{
+    data:Box<[u8;BLOCK_SIZE]>,
+    dirty:bool,
+    ///最近一次访问的时间戳 越小越久没用
+    stamp:u64,
+}
+
+struct BlockCacheInner{
+    capacity:usize,
+    ///块号->缓存块
+    entries:BTreeMap<usize,CacheEntry>,
+    ///时间戳->块号 第一个就是最久没用的块
+    lru:BTreeMap<u64,usize>,
+    clock:u64,
+}
+
+///LRU块缓存
+pub struct BlockCache{
+    device:Arc<dyn BlockDeviceTrait>,
+    inner:Mutex<BlockCacheInner>,
+}
+
+impl BlockCacheInner {
+    ///把块移到LRU队尾
+    fn touch(&mut self,block_id:usize){
+        let entry=self.entries.get_mut(&block_id).unwrap();
+        self.lru.remove(&entry.stamp);
+        self.clock+=1;
+        entry.stamp=self.clock;
+        self.lru.insert(self.clock, block_id);
+    }
+
+    ///淘汰最久没用的块 脏块先写回
+    fn evict(&mut self,device:&Arc<dyn BlockDeviceTrait>){
+        if let Some((_,block_id))=self.lru.pop_first(){
+            let entry=self.entries.remove(&block_id).unwrap();
+            if entry.dirty{
+                device.write_block(block_id, entry.data.as_ref());
+            }
+        }
+    }
+
+    ///取出块 不在缓存里时从设备读入(load为false时不读，调用者会整块覆盖)
+    fn get(&mut self,device:&Arc<dyn BlockDeviceTrait>,block_id:usize,load:bool)->&mut CacheEntry{
+        if self.entries.contains_key(&block_id){
+            self.touch(block_id);
+        }else{
+            while self.entries.len()>=self.capacity{
+                self.evict(device);
+            }
+            let mut data=Box::new([0u8;BLOCK_SIZE]);
+            if load{
+                device.read_block(block_id, data.as_mut());
+            }
+            self.clock+=1;
+            self.lru.insert(self.clock, block_id);
+            self.entries.insert(block_id, CacheEntry { data, dirty: false, stamp: self.clock });
+        }
+        self.entries.get_mut(&block_id).unwrap()
+    }
+}
+
+impl BlockCache {
+    ///capacity为最多缓存的块数，至少为1
+    pub fn new(device:Arc<dyn BlockDeviceTrait>,capacity:usize)->Self{
+        BlockCache {
+            device,
+            inner: Mutex::new(BlockCacheInner {
+                capacity: capacity.max(1),
+                entries: BTreeMap::new(),
+                lru: BTreeMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    ///(已缓存块数,脏块数)
+    pub fn usage(&self)->(usize,usize){
+        let inner=self.inner.lock();
+        (inner.entries.len(),inner.entries.values().filter(|entry|entry.dirty).count())
+    }
+}
+
+impl BlockDeviceTrait for BlockCache {
+    fn read_block(&self,block_id:usize,read_buffer:&mut [u8]) {
+        let mut inner=self.inner.lock();
+        let entry=inner.get(&self.device, block_id, true);
+        let len=read_buffer.len().min(BLOCK_SIZE);
+        read_buffer[..len].copy_from_slice(&entry.data[..len]);
+    }
+
+    fn write_block(&self,block_id:usize,write_buffer:&[u8]) {
+        let mut inner=self.inner.lock();
+        let len=write_buffer.len().min(BLOCK_SIZE);
+        //只写一部分时要先读出原来的内容
+        let entry=inner.get(&self.device, block_id, len<BLOCK_SIZE);
+        entry.data[..len].copy_from_slice(&write_buffer[..len]);
+        entry.dirty=true;
+    }
+
+    ///写回所有脏块 块仍然留在缓存里
+    fn sync(&self) {
+        let mut inner=self.inner.lock();
+        for (&block_id,entry) in inner.entries.iter_mut().filter(|(_,entry)|entry.dirty){
+            self.device.write_block(block_id, entry.data.as_ref());
+            entry.dirty=false;
+        }
+        drop(inner);
+        self.device.sync();
+    }
+
+    fn block_count(&self)->Option<usize> {
+        self.device.block_count()
+    }
+}
@@ -1,38 +1,157 @@
 use core::mem;
 
 use alloc::{string::ToString, sync::Arc,vec::Vec};
-use crate::{bitmap::{SuperBlock, DiskInode, DiskInodeType, BitMapAlloctor, BitMapAlloctorTrait, Bitmap_AllocUnit, DirEntry}, vfs::*};
+use crate::{bitmap::{SuperBlock, DiskInode, DiskInodeType, BitMapAlloctor, BitMapAlloctorTrait, Bitmap_AllocUnit, DirEntry, load_bitmaps, with_bitmaps}, vfs::*};
 use alloc::string::String;
 use alloc::vec;
 use log::{debug, error, warn};
+use lazy_static::lazy_static;
+use spin::RwLock;
+use crate::scaled_device::ScaledBlockDevice;
 
 const BlueOSFileSystemMagic:u32 = 0x79614000;
-pub const INODEBITMAP_COUNT:u32 =100;
-pub const DATABITMAP_COUNT:u32  =1000;
+/// 访问时间的更新间隔(秒)
+const ATIME_UPDATE_INTERVAL: u32 = 60;
 
-/// 计算 inode 区域起始块号
-fn get_inode_area_start_block() -> usize {
-    1 + INODEBITMAP_COUNT as usize + DATABITMAP_COUNT as usize
+/// 磁盘布局：超级块 | inode位图 | data位图 | inode区 | 数据区
+/// 格式化时由FormatOptions算出并记录在超级块里，挂载后所有块号都从这里算
+#[derive(Debug,Clone,Copy)]
+pub struct FsLayout{
+    pub block_size:usize,
+    pub inode_count:usize,
+    pub data_block_count:usize,
+    pub inode_bitmap_start:usize,
+    pub inode_bitmap_blocks:usize,
+    pub data_bitmap_start:usize,
+    pub data_bitmap_blocks:usize,
+    pub inode_area_start:usize,
+    pub data_area_start:usize,
+}
+
+impl FsLayout {
+    fn inodes_per_block(block_size:usize)->usize{
+        block_size / core::mem::size_of::<DiskInode>()
+    }
+
+    fn new(block_size:usize,inode_count:usize,inode_bitmap_blocks:usize,data_block_count:usize,data_bitmap_blocks:usize)->Self{
+        let inodes_per_block = Self::inodes_per_block(block_size);
+        let inode_area_blocks = (inode_count + inodes_per_block - 1) / inodes_per_block;
+        let data_bitmap_start = 1 + inode_bitmap_blocks;
+        let inode_area_start = data_bitmap_start + data_bitmap_blocks;
+        FsLayout {
+            block_size,
+            inode_count,
+            data_block_count,
+            inode_bitmap_start: 1,
+            inode_bitmap_blocks,
+            data_bitmap_start,
+            data_bitmap_blocks,
+            inode_area_start,
+            data_area_start: inode_area_start + inode_area_blocks,
+        }
+    }
+
+    ///按格式化参数划分磁盘 放不下时返回None
+    pub fn from_options(options:&FormatOptions)->Option<Self>{
+        let bits_per_block = options.block_size * 8;
+        let inodes_per_block = Self::inodes_per_block(options.block_size);
+        let inode_bitmap_blocks = (options.inode_count + bits_per_block - 1) / bits_per_block;
+        let inode_area_blocks = (options.inode_count + inodes_per_block - 1) / inodes_per_block;
+        // 剩下的块分给data位图和数据区，每个位图块管bits_per_block个数据块
+        let remaining = options.block_count.checked_sub(1 + inode_bitmap_blocks + inode_area_blocks)?;
+        let data_bitmap_blocks = (remaining + bits_per_block) / (bits_per_block + 1);
+        let data_block_count = remaining - data_bitmap_blocks;
+        if options.inode_count == 0 || data_block_count < 2 {
+            return None;
+        }
+        Some(Self::new(options.block_size, options.inode_count, inode_bitmap_blocks, data_block_count, data_bitmap_blocks))
+    }
+
+    ///从超级块恢复布局 旧格式的超级块只有两个位图块数，位图里每一位都可用
+    pub fn from_super_block(super_block:&SuperBlock)->Self{
+        let inode_bitmap_blocks = super_block.inode_bitmap_block_count as usize;
+        let data_bitmap_blocks = super_block.data_bitmap_block_count as usize;
+        if super_block.block_size == 0 {
+            return Self::new(BLOCK_SIZE, inode_bitmap_blocks * BLOCK_SIZE * 8, inode_bitmap_blocks, data_bitmap_blocks * BLOCK_SIZE * 8, data_bitmap_blocks);
+        }
+        Self::new(
+            super_block.block_size as usize,
+            super_block.inode_count as usize,
+            inode_bitmap_blocks,
+            super_block.data_block_count as usize,
+            data_bitmap_blocks,
+        )
+    }
+
+    pub fn total_blocks(&self)->usize{
+        self.data_area_start + self.data_block_count
+    }
+}
+
+///挂载的文件系统 布局和按块大小转换后的设备
+struct MountedFs{
+    layout:FsLayout,
+    device:Arc<dyn BlockDeviceTrait>,
+}
+
+lazy_static! {
+    ///当前挂载的文件系统
+    static ref MOUNTED_FS: RwLock<Option<MountedFs>> = RwLock::new(None);
+}
+
+///记录布局 之后文件系统都通过按块大小转换后的设备访问
+fn set_mounted(block_device:&Arc<dyn BlockDeviceTrait>,layout:FsLayout){
+    let device = ScaledBlockDevice::wrap(block_device.clone(), layout.block_size);
+    *MOUNTED_FS.write() = Some(MountedFs { layout, device });
+}
+
+///挂载：读超级块得到布局 超级块在设备第一个扇区
+pub fn mount_layout(block_device:&Arc<dyn BlockDeviceTrait>)->FsLayout{
+    let mut read_buffer = [0u8;BLOCK_SIZE];
+    block_device.read_block(0, &mut read_buffer);
+    let super_block = unsafe{*(read_buffer.as_ref() as *const _ as *const SuperBlock)};
+    let layout = FsLayout::from_super_block(&super_block);
+    set_mounted(block_device, layout);
+    layout
+}
+
+///还没挂载时从全局块设备的超级块读
+fn ensure_mounted(){
+    if MOUNTED_FS.read().is_none() {
+        let block_device = crate::vfs::get_block_device().expect("Block device not initialized");
+        mount_layout(&block_device);
+    }
+}
+
+///当前挂载的布局
+pub fn fs_layout()->FsLayout{
+    ensure_mounted();
+    MOUNTED_FS.read().as_ref().unwrap().layout
+}
+
+///文件系统块大小
+pub fn fs_block_size()->usize{
+    fs_layout().block_size
+}
+
+///以文件系统块为单位读写的设备 没有块设备时返回None
+pub fn fs_device()->Option<Arc<dyn BlockDeviceTrait>>{
+    crate::vfs::get_block_device()?;
+    ensure_mounted();
+    MOUNTED_FS.read().as_ref().map(|mounted| mounted.device.clone())
 }
 
 /// 计算数据区域起始块号
 fn get_data_area_start_block() -> usize {
-    let inode_area_start = get_inode_area_start_block();
-    // 假设每个 inode 需要 64 字节，每个块 512 字节，可以存储 8 个 inode
-    // 最大 inode 数 = INODEBITMAP_COUNT * BLOCK_SIZE * 8
-    let max_inodes = INODEBITMAP_COUNT as usize * BLOCK_SIZE * 8;
-    let inode_size = core::mem::size_of::<DiskInode>();
-    let inodes_per_block = BLOCK_SIZE / inode_size;
-    let inode_area_blocks = (max_inodes + inodes_per_block - 1) / inodes_per_block;
-    inode_area_start + inode_area_blocks
+    fs_layout().data_area_start
 }
 
 /// 根据 inode_id 计算 inode 在磁盘上的位置
 pub fn get_inode_block_and_offset(inode_id: usize) -> (usize, usize) {
-    let inode_area_start = get_inode_area_start_block();
+    let layout = fs_layout();
     let inode_size = core::mem::size_of::<DiskInode>();
-    let inodes_per_block = BLOCK_SIZE / inode_size;
-    let block_id = inode_area_start + (inode_id / inodes_per_block);
+    let inodes_per_block = FsLayout::inodes_per_block(layout.block_size);
+    let block_id = layout.inode_area_start + (inode_id / inodes_per_block);
     let offset = (inode_id % inodes_per_block) * inode_size;
     (block_id, offset)
 }
@@ -42,17 +161,20 @@ pub fn get_data_block_id(data_index: usize) -> usize {
     get_data_area_start_block() + data_index
 }
 
-/// 间接块：每个块存储 128 个块指针（512 字节 / 4 字节）
-const POINTERS_PER_BLOCK: usize = BLOCK_SIZE / 4;
+/// 间接块：每个块存储 块大小/4 个块指针（512 字节的块 128 个，4KiB 的块 1024 个）
+fn pointers_per_block() -> usize {
+    fs_block_size() / 4
+}
 
 /// 读取间接块中的块指针（返回的是 data_index，需要转换为绝对块号）
 /// 返回完整的指针数组，包括零值
 fn read_indirect_block(block_device: &Arc<dyn BlockDeviceTrait>, indirect_block_id: usize) -> Vec<u32> {
-    let mut block = [0u8; BLOCK_SIZE];
+    let mut block = vec![0u8; fs_block_size()];
     let absolute_block_id = get_data_block_id(indirect_block_id);
     block_device.read_block(absolute_block_id, &mut block);
-    let mut pointers = Vec::with_capacity(POINTERS_PER_BLOCK);
-    for i in 0..POINTERS_PER_BLOCK {
+    let per_block = pointers_per_block();
+    let mut pointers = Vec::with_capacity(per_block);
+    for i in 0..per_block {
         let ptr = u32::from_le_bytes([
             block[i * 4],
             block[i * 4 + 1],
@@ -66,8 +188,8 @@ fn read_indirect_block(block_device: &Arc<dyn BlockDeviceTrait>, indirect_block_
 
 /// 写入间接块
 fn write_indirect_block(block_device: &Arc<dyn BlockDeviceTrait>, indirect_block_id: usize, pointers: &[u32]) {
-    let mut block = [0u8; BLOCK_SIZE];
-    for (i, &ptr) in pointers.iter().enumerate().take(POINTERS_PER_BLOCK) {
+    let mut block = vec![0u8; fs_block_size()];
+    for (i, &ptr) in pointers.iter().enumerate().take(pointers_per_block()) {
         let bytes = ptr.to_le_bytes();
         block[i * 4..i * 4 + 4].copy_from_slice(&bytes);
     }
@@ -134,17 +256,153 @@ fn get_all_data_blocks(block_device: &Arc<dyn BlockDeviceTrait>, disk_inode: &Di
     blocks
 }
 
+/// 直接块个数
+const DIRECT_BLOCKS: usize = 12;
+
+/// 文件内块号对应的根指针：(间接层级, 该根指针下第一个文件块号)
+/// 层级0为直接块，1~3为一到三级间接块，超出三级间接块范围返回None
+fn block_root(file_block: usize, per_block: usize) -> Option<(usize, usize)> {
+    if file_block < DIRECT_BLOCKS {
+        return Some((0, file_block));
+    }
+    let mut base = DIRECT_BLOCKS;
+    let mut span = per_block;
+    for level in 1..=3 {
+        if file_block < base + span {
+            return Some((level, base));
+        }
+        base += span;
+        span *= per_block;
+    }
+    None
+}
+
+/// inode中对应层级的根指针
+fn root_slot(disk_inode: &mut DiskInode, level: usize, file_block: usize) -> &mut u32 {
+    match level {
+        0 => &mut disk_inode.direct_blocks[file_block],
+        1 => &mut disk_inode.indirect_block,
+        2 => &mut disk_inode.double_indirect,
+        _ => &mut disk_inode.triple_indirect,
+    }
+}
+
+/// 分配一个块并清零 新的间接块全为空指针，新的数据块在文件洞里读出来是0
+fn alloc_zeroed_block(block_device: &Arc<dyn BlockDeviceTrait>) -> Result<u32, VfsError> {
+    let datanode = BitMapAlloctor::alloc_data(1, block_device).ok_or(VfsError::InvalidOperation)?;
+    let data_index = datanode[0].0;
+    block_device.write_block(get_data_block_id(data_index as usize), &vec![0u8; fs_block_size()]);
+    Ok(data_index)
+}
+
+/// 从level层的指针往下找offset处的数据块 途中遇到空指针说明是洞，返回0
+fn lookup_in(block_device: &Arc<dyn BlockDeviceTrait>, ptr: u32, level: usize, offset: usize, per_block: usize) -> u32 {
+    if ptr == 0 || level == 0 {
+        return ptr;
+    }
+    let span = per_block.pow(level as u32 - 1);
+    let pointers = read_indirect_block(block_device, ptr as usize);
+    lookup_in(block_device, pointers[offset / span], level - 1, offset % span, per_block)
+}
+
+/// 文件第file_block块的data_index 洞返回0
+fn lookup_block(block_device: &Arc<dyn BlockDeviceTrait>, disk_inode: &DiskInode, file_block: usize) -> u32 {
+    let per_block = pointers_per_block();
+    let mut disk_inode = *disk_inode;
+    match block_root(file_block, per_block) {
+        Some((level, base)) => {
+            let ptr = *root_slot(&mut disk_inode, level, file_block);
+            lookup_in(block_device, ptr, level, file_block - base, per_block)
+        }
+        None => 0,
+    }
+}
+
+/// 保证slot往下offset处的数据块存在，缺的间接块和数据块都分配
+/// 下面的分配失败时释放这一层新分配的间接块，slot恢复为空指针
+fn ensure_in(block_device: &Arc<dyn BlockDeviceTrait>, slot: &mut u32, level: usize, offset: usize, per_block: usize) -> Result<u32, VfsError> {
+    let allocated = *slot == 0;
+    if allocated {
+        *slot = alloc_zeroed_block(block_device)?;
+    }
+    if level == 0 {
+        return Ok(*slot);
+    }
+    let span = per_block.pow(level as u32 - 1);
+    let mut pointers = read_indirect_block(block_device, *slot as usize);
+    let index = offset / span;
+    let old = pointers[index];
+    let data_index = match ensure_in(block_device, &mut pointers[index], level - 1, offset % span, per_block) {
+        Ok(data_index) => data_index,
+        Err(err) => {
+            if allocated {
+                BitMapAlloctor::dealloc_data(&[crate::bitmap::data_index(*slot)], block_device);
+                *slot = 0;
+            }
+            return Err(err);
+        }
+    };
+    if pointers[index] != old {
+        write_indirect_block(block_device, *slot as usize, &pointers);
+    }
+    Ok(data_index)
+}
+
+/// 文件第file_block块的data_index 是洞时分配，改动的指针写进disk_inode，由调用者写回
+fn ensure_block(block_device: &Arc<dyn BlockDeviceTrait>, disk_inode: &mut DiskInode, file_block: usize) -> Result<u32, VfsError> {
+    let per_block = pointers_per_block();
+    let (level, base) = block_root(file_block, per_block).ok_or(VfsError::InvalidOperation)?;
+    ensure_in(block_device, root_slot(disk_inode, level, file_block), level, file_block - base, per_block)
+}
+
+/// 释放slot下文件块号不小于keep的块 base为slot下第一个文件块号
+/// 间接块里的指针全空后连间接块一起释放，被释放的块放进freed
+fn prune_in(block_device: &Arc<dyn BlockDeviceTrait>, slot: &mut u32, level: usize, base: usize, keep: usize, per_block: usize, freed: &mut Vec<crate::bitmap::data_index>) {
+    if *slot == 0 || base + per_block.pow(level as u32) <= keep {
+        return;
+    }
+    if level > 0 {
+        let span = per_block.pow(level as u32 - 1);
+        let mut pointers = read_indirect_block(block_device, *slot as usize);
+        for (index, pointer) in pointers.iter_mut().enumerate() {
+            prune_in(block_device, pointer, level - 1, base + index * span, keep, per_block, freed);
+        }
+        if pointers.iter().any(|&pointer| pointer != 0) {
+            write_indirect_block(block_device, *slot as usize, &pointers);
+            return;
+        }
+    }
+    freed.push(crate::bitmap::data_index(*slot));
+    *slot = 0;
+}
+
+/// 释放文件第keep块及之后的所有数据块和变空的间接块，返回被释放的块
+fn release_blocks_from(block_device: &Arc<dyn BlockDeviceTrait>, disk_inode: &mut DiskInode, keep: usize) -> Vec<crate::bitmap::data_index> {
+    let per_block = pointers_per_block();
+    let mut freed = Vec::new();
+    for index in 0..DIRECT_BLOCKS {
+        prune_in(block_device, &mut disk_inode.direct_blocks[index], 0, index, keep, per_block, &mut freed);
+    }
+    let mut base = DIRECT_BLOCKS;
+    for level in 1..=3 {
+        prune_in(block_device, root_slot(disk_inode, level, 0), level, base, keep, per_block, &mut freed);
+        base += per_block.pow(level as u32);
+    }
+    freed
+}
+
 /// 从目录的数据块中读取所有目录项
 fn read_dir_entries(block_device: &Arc<dyn BlockDeviceTrait>, disk_inode: &DiskInode) -> Vec<DirEntry> {
     let mut entries = Vec::new();
     let data_blocks = get_all_data_blocks(block_device, disk_inode);
     
+    let block_size = fs_block_size();
     for block_id in data_blocks {
-        let mut block = [0u8; BLOCK_SIZE];
+        let mut block = vec![0u8; block_size];
         block_device.read_block(block_id, &mut block);
         
         let mut offset = 0;
-        while offset + DirEntry::SIZE <= BLOCK_SIZE {
+        while offset + DirEntry::SIZE <= block_size {
             let entry_bytes = &block[offset..offset + DirEntry::SIZE];
             let entry = unsafe { &*(entry_bytes.as_ptr() as *const DirEntry) };
             
@@ -166,12 +424,13 @@ fn add_dir_entry(block_device: &Arc<dyn BlockDeviceTrait>, disk_inode: &mut Disk
     let data_blocks = get_all_data_blocks(block_device, disk_inode);
     
     // 尝试在现有块中添加
+    let block_size = fs_block_size();
     for &block_id in &data_blocks {
-        let mut block = [0u8; BLOCK_SIZE];
+        let mut block = vec![0u8; block_size];
         block_device.read_block(block_id, &mut block);
         
         let mut offset = 0;
-        while offset + DirEntry::SIZE <= BLOCK_SIZE {
+        while offset + DirEntry::SIZE <= block_size {
             let entry_bytes = &block[offset..offset + DirEntry::SIZE];
             let existing_entry = unsafe { &*(entry_bytes.as_ptr() as *const DirEntry) };
             
@@ -195,17 +454,13 @@ fn add_dir_entry(block_device: &Arc<dyn BlockDeviceTrait>, disk_inode: &mut Disk
     for i in 0..12 {
         if disk_inode.direct_blocks[i] == 0 {
             // 分配新块
-            let alloc_unit = BitMapAlloctor::alloc_datamap(1, Arc::clone(block_device))
+            let datanode = BitMapAlloctor::alloc_data(1, block_device)
                 .ok_or(VfsError::InvalidOperation)?;
-            if alloc_unit.datanode.is_empty() {
-                return Err(VfsError::InvalidOperation);
-            }
-            
-            let new_block_id = alloc_unit.datanode[0].0 as u32;
+            let new_block_id = datanode[0].0 as u32;
             disk_inode.direct_blocks[i] = new_block_id;
             
             // 写入目录项到新块
-            let mut block = [0u8; BLOCK_SIZE];
+            let mut block = vec![0u8; block_size];
             let entry_bytes = unsafe {
                 core::slice::from_raw_parts(&entry as *const DirEntry as *const u8, DirEntry::SIZE)
             };
@@ -253,42 +508,57 @@ impl VfsOps for BlueosFileSystem {
         super_block.magic == BlueOSFileSystemMagic
     }
     
-    fn initial_file_systeam(&self,block_device:Arc<dyn BlockDeviceTrait>)->Result<(),VfsError> {
+    fn initial_file_systeam(&self,raw_device:Arc<dyn BlockDeviceTrait>,options:FormatOptions)->Result<(),VfsError> {
+        if !options.block_size_valid() {
+            error!("Unsupported block size {}", options.block_size);
+            return Err(VfsError::InvalidOperation);
+        }
+        let layout = FsLayout::from_options(&options).ok_or(VfsError::InvalidOperation)?;
+        debug!("Format BlueosFS: {:?}", layout);
+        // 之后都按文件系统块读写
+        set_mounted(&raw_device, layout);
+        let block_device = fs_device().ok_or(VfsError::InvalidOperation)?;
+        let block_size = layout.block_size;
         // 超级块初始化
         let super_block = SuperBlock{
             magic: BlueOSFileSystemMagic,
-            inode_bitmap_block_count:INODEBITMAP_COUNT,
-            data_bitmap_block_count:DATABITMAP_COUNT,
-            pad_:[0u8;500]
+            inode_bitmap_block_count:layout.inode_bitmap_blocks as u32,
+            data_bitmap_block_count:layout.data_bitmap_blocks as u32,
+            block_size:layout.block_size as u32,
+            inode_count:layout.inode_count as u32,
+            data_block_count:layout.data_block_count as u32,
+            pad_:[0u8;BLOCK_SIZE - 4*6]
         };
-        let super_block_sz:[u8;512] = unsafe{ mem::transmute(super_block)};
-        block_device.write_block(0,&super_block_sz);
+        let super_block_sz:[u8;BLOCK_SIZE] = unsafe{ mem::transmute(super_block)};
+        // 超级块在0号块开头，块里其余部分为0
+        let mut super_block_buf = vec![0u8; block_size];
+        super_block_buf[..BLOCK_SIZE].copy_from_slice(&super_block_sz);
+        block_device.write_block(0,&super_block_buf);
         
         // 位图初始化
-        let empty_bitmap = [0u8;BLOCK_SIZE];
-        for i in 1..=INODEBITMAP_COUNT as usize {
-            block_device.write_block(i, &empty_bitmap);
+        let empty_bitmap = vec![0u8; block_size];
+        for i in 0..layout.inode_bitmap_blocks {
+            block_device.write_block(layout.inode_bitmap_start + i, &empty_bitmap);
         }
-        let data_bitmap_start = 1 + INODEBITMAP_COUNT as usize;
-        for i in 0..DATABITMAP_COUNT as usize {
-            block_device.write_block(data_bitmap_start + i, &empty_bitmap);
+        for i in 0..layout.data_bitmap_blocks {
+            block_device.write_block(layout.data_bitmap_start + i, &empty_bitmap);
         }
         
-        // 分配根目录 inode (inode_id = 0)
-        let mut root_inode_bitmap = [0u8; BLOCK_SIZE];
-        block_device.read_block(1, &mut root_inode_bitmap);
-        root_inode_bitmap[0] |= 1;
-        block_device.write_block(1, &root_inode_bitmap);
+        // 位图清空后重新读入内存
+        load_bitmaps(&block_device);
+        // 分配根目录 inode (inode_id = 0)，0号数据块保留，块指针为0表示未分配
+        with_bitmaps(&block_device, |bitmaps| {
+            bitmaps.inode.set(0);
+            bitmaps.data.set(0);
+        });
         
         // 初始化根目录的 DiskInode
         // 根目录需要至少一个数据块来存储 "." 和 ".." 目录项
-        let root_data_alloc = BitMapAlloctor::alloc_datamap(1, Arc::clone(&block_device))
+        let root_data_alloc = BitMapAlloctor::alloc_data(1, &block_device)
             .ok_or(VfsError::InvalidOperation)?;
-        if root_data_alloc.datanode.is_empty() {
-            return Err(VfsError::InvalidOperation);
-        }
         
-        let root_data_index = root_data_alloc.datanode[0].0 as u32;
+        let root_data_index = root_data_alloc[0].0 as u32;
+        let now = current_time();
         let mut root_inode = DiskInode {
             file_size: 0,
             direct_blocks: {
@@ -301,15 +571,15 @@ impl VfsOps for BlueosFileSystem {
             triple_indirect: 0,
             file_type: DiskInodeType::Dir,
             permission: 0o755,
-            create_time: 0,
-            access_time: 0,
-            modify_time: 0,
+            create_time: now,
+            access_time: now,
+            modify_time: now,
             pad: [0; 2],
         };
         
         // 写入根目录的 DiskInode
         let (block_id, offset) = get_inode_block_and_offset(0);
-        let mut inode_block = [0u8; BLOCK_SIZE];
+        let mut inode_block = vec![0u8; block_size];
         block_device.read_block(block_id, &mut inode_block);
         let inode_bytes = unsafe {
             core::slice::from_raw_parts(
@@ -325,7 +595,7 @@ impl VfsOps for BlueosFileSystem {
         let dotdot_entry = DirEntry::new(0, "..", DiskInodeType::Dir).unwrap();
         
         let root_data_block_id = get_data_block_id(root_data_index as usize);
-        let mut root_data_block = [0u8; BLOCK_SIZE];
+        let mut root_data_block = vec![0u8; block_size];
         
         // 写入 "." 目录项
         let dot_bytes = unsafe {
@@ -343,7 +613,7 @@ impl VfsOps for BlueosFileSystem {
         root_inode.file_size = (DirEntry::SIZE * 2) as u32;
         
         // 更新根目录的 DiskInode（更新 file_size）
-        let mut inode_block2 = [0u8; BLOCK_SIZE];
+        let mut inode_block2 = vec![0u8; block_size];
         block_device.read_block(block_id, &mut inode_block2);
         let inode_bytes2 = unsafe {
             core::slice::from_raw_parts(
@@ -356,7 +626,7 @@ impl VfsOps for BlueosFileSystem {
         
         // 验证超级块
         let mut read_buffer = [0u8;BLOCK_SIZE];
-        block_device.read_block(0, &mut read_buffer);
+        raw_device.read_block(0, &mut read_buffer);
         let magic = unsafe{&*(read_buffer.as_ref() as *const _ as *const SuperBlock)}.magic;
         assert_eq!(magic,BlueOSFileSystemMagic,"BlueosFileSystem initialed failed");
         Ok(())
@@ -393,7 +663,7 @@ impl VfsNodeOps for DirNode {
 
     ///创建文件或者目录（基于磁盘）
     fn create(&self,name:&str,tp:NodeType)->Result<Arc<dyn VfsNodeOps>,VfsError> {
-        let block_device = crate::vfs::get_block_device().ok_or(VfsError::InvalidOperation)?;
+        let block_device = fs_device().ok_or(VfsError::InvalidOperation)?;
         
         let mut children = self.children.lock();
         if children.contains_key(name){
@@ -416,6 +686,7 @@ impl VfsNodeOps for DirNode {
         };
         
         // 创建 DiskInode
+        let now = current_time();
         let mut disk_inode = DiskInode {
             file_size: 0,
             direct_blocks: {
@@ -430,15 +701,15 @@ impl VfsNodeOps for DirNode {
             triple_indirect: 0,
             file_type,
             permission: 0o644,
-            create_time: 0, // TODO: 获取当前时间
-            access_time: 0,
-            modify_time: 0,
+            create_time: now,
+            access_time: now,
+            modify_time: now,
             pad: [0; 2],
         };
         
         // 写入 DiskInode 到磁盘
         let (block_id, offset) = get_inode_block_and_offset(inode_id);
-        let mut inode_block = [0u8; BLOCK_SIZE];
+        let mut inode_block = vec![0u8; fs_block_size()];
         block_device.read_block(block_id, &mut inode_block);
         let inode_bytes = unsafe {
             core::slice::from_raw_parts(&disk_inode as *const DiskInode as *const u8, core::mem::size_of::<DiskInode>())
@@ -473,6 +744,7 @@ impl VfsNodeOps for DirNode {
         
         // 添加目录项到父目录
         add_dir_entry(&block_device, &mut parent_disk_inode, dir_entry)?;
+        parent_disk_inode.modify_time = now;
         
         // 写回更新后的父目录 DiskInode
         self.write_disk_inode(&parent_disk_inode)?;
@@ -491,7 +763,7 @@ impl VfsNodeOps for DirNode {
         }
         
         // 内存中没有，从磁盘加载
-        let block_device = crate::vfs::get_block_device()?;
+        let block_device = fs_device()?;
         let disk_inode = self.read_disk_inode()?;
         let entries = read_dir_entries(&block_device, &disk_inode);
         
@@ -535,16 +807,16 @@ impl VfsNodeOps for DirNode {
     }
     fn get_attribute(&self)->FileAttribute {
         let attribute=self.metadata.lock();
-        // 从磁盘读取文件大小
-        let size = self.read_disk_inode()
-            .map(|di| di.file_size as usize)
-            .unwrap_or(0);
+        // 从磁盘读取文件大小和时间戳
+        let (size, create_time, modify_time) = self.read_disk_inode()
+            .map(|di| (di.file_size as usize, di.create_time as u64, di.modify_time as u64))
+            .unwrap_or((0, attribute.create_time, attribute.modify_time));
         FileAttribute { 
             tp:NodeType::Dir, 
             size, 
             permission: attribute.permission,
-            create_time: attribute.create_time, 
-            modify_time: attribute.modify_time,
+            create_time, 
+            modify_time,
          }
     }
     fn get_parent(&self)->Option<Arc<dyn VfsNodeOps>> {
@@ -563,7 +835,7 @@ impl VfsNodeOps for DirNode {
         Err(VfsError::NotAFile)
     }
     fn remove(&self,path:&str)->Result<(),VfsError> {
-        let block_device = crate::vfs::get_block_device().ok_or(VfsError::InvalidOperation)?;
+        let block_device = fs_device().ok_or(VfsError::InvalidOperation)?;
         let mut children = self.children.lock();
         let target = children.get(path).ok_or(VfsError::NotFound)?;
         
@@ -583,15 +855,9 @@ impl VfsNodeOps for DirNode {
                         return Err(VfsError::InvalidOperation);
                     }
                     let dir_ref = &*raw;
-                    let disk_inode = dir_ref.read_disk_inode().ok_or(VfsError::InvalidOperation)?;
-                    let data_indices: Vec<_> = get_all_data_blocks(&block_device, &disk_inode)
-                        .iter()
-                        .map(|&abs_block_id| {
-                            // 将绝对块号转换回 data_index
-                            let data_index = abs_block_id - get_data_area_start_block();
-                            crate::bitmap::data_index(data_index as u32)
-                        })
-                        .collect();
+                    let mut disk_inode = dir_ref.read_disk_inode().ok_or(VfsError::InvalidOperation)?;
+                    // 数据块和间接块一起回收
+                    let data_indices = release_blocks_from(&block_device, &mut disk_inode, 0);
                     (dir_ref.inode_id, data_indices)
                 }
             }
@@ -602,15 +868,9 @@ impl VfsNodeOps for DirNode {
                         return Err(VfsError::InvalidOperation);
                     }
                     let file_ref = &*raw;
-                    let disk_inode = file_ref.read_disk_inode().ok_or(VfsError::InvalidOperation)?;
-                    let data_indices: Vec<_> = get_all_data_blocks(&block_device, &disk_inode)
-                        .iter()
-                        .map(|&abs_block_id| {
-                            // 将绝对块号转换回 data_index
-                            let data_index = abs_block_id - get_data_area_start_block();
-                            crate::bitmap::data_index(data_index as u32)
-                        })
-                        .collect();
+                    let mut disk_inode = file_ref.read_disk_inode().ok_or(VfsError::InvalidOperation)?;
+                    // 数据块和间接块一起回收
+                    let data_indices = release_blocks_from(&block_device, &mut disk_inode, 0);
                     (file_ref.inode_id, data_indices)
                 }
             }
@@ -618,7 +878,7 @@ impl VfsNodeOps for DirNode {
         
         // 回收资源
         let alloc_unit = Bitmap_AllocUnit {
-            inode: crate::bitmap::inode_index(inode_id as u8),
+            inode: crate::bitmap::inode_index(inode_id as u32),
             datanode: data_indices,
         };
         BitMapAlloctor::dealloc_datamap(alloc_unit, block_device.clone());
@@ -693,6 +953,9 @@ impl VfsNodeOps for DirNode {
 
 ///为文件node实现抽象
 impl VfsNodeOps for FileNode {
+    fn get_inode_id(&self)->Option<usize> {
+        Some(self.inode_id)
+    }
     fn create(&self,path:&str,tp:NodeType)->Result<Arc<dyn VfsNodeOps>,VfsError> {
         Err(VfsError::NotADir)
     }
@@ -701,16 +964,16 @@ impl VfsNodeOps for FileNode {
     }
     fn get_attribute(&self)->FileAttribute {
         let metadata = self.metadata.lock();
-        // 从磁盘读取文件大小
-        let size = self.read_disk_inode()
-            .map(|di| di.file_size as usize)
-            .unwrap_or(0);
+        // 从磁盘读取文件大小和时间戳
+        let (size, create_time, modify_time) = self.read_disk_inode()
+            .map(|di| (di.file_size as usize, di.create_time as u64, di.modify_time as u64))
+            .unwrap_or((0, metadata.create_time, metadata.modify_time));
         FileAttribute { 
             tp: NodeType::File, 
             size,
             permission: metadata.permission, 
-            create_time: metadata.create_time, 
-            modify_time: metadata.modify_time
+            create_time, 
+            modify_time
          }
     }
     fn get_parent(&self)->Option<Arc<dyn VfsNodeOps>> {
@@ -728,7 +991,7 @@ impl VfsNodeOps for FileNode {
     }
     fn read_at(&self,offset:usize,buf:&mut [u8])->Result<usize,VfsError> {
         // 从磁盘读取 DiskInode
-        let disk_inode = self.read_disk_inode().ok_or(VfsError::InvalidOperation)?;
+        let mut disk_inode = self.read_disk_inode().ok_or(VfsError::InvalidOperation)?;
         let file_size = disk_inode.file_size as usize;
         
         //检查偏移有效性
@@ -742,28 +1005,35 @@ impl VfsNodeOps for FileNode {
         let mut current_offset = offset;
         
         // 从数据块读取数据
-        let block_device = crate::vfs::get_block_device().ok_or(VfsError::InvalidOperation)?;
-        let data_blocks = get_all_data_blocks(&block_device, &disk_inode);
+        let block_device = fs_device().ok_or(VfsError::InvalidOperation)?;
+        let block_size = fs_block_size();
         
         while bytes_read < read_len && current_offset < file_size {
-            let block_idx = current_offset / BLOCK_SIZE;
-            if block_idx >= data_blocks.len() {
-                break; // 超出已分配块范围
-            }
-            
-            let block_offset = current_offset % BLOCK_SIZE;
-            let remaining_in_block = BLOCK_SIZE - block_offset;
+            let block_idx = current_offset / block_size;
+            let block_offset = current_offset % block_size;
+            let remaining_in_block = block_size - block_offset;
             let to_read = (read_len - bytes_read).min(remaining_in_block);
             
-            let block_id = data_blocks[block_idx];
-            let mut block = [0u8; BLOCK_SIZE];
-            block_device.read_block(block_id, &mut block);
+            // 文件洞没有数据块，读出来是0
+            let data_index = lookup_block(&block_device, &disk_inode, block_idx);
+            let mut block = vec![0u8; block_size];
+            if data_index != 0 {
+                block_device.read_block(get_data_block_id(data_index as usize), &mut block);
+            }
             
             buf[bytes_read..bytes_read + to_read].copy_from_slice(&block[block_offset..block_offset + to_read]);
             bytes_read += to_read;
             current_offset += to_read;
         }
         
+        // 访问时间过期超过ATIME_UPDATE_INTERVAL才写回，避免每次读都写inode 写失败不影响读
+        let now = current_time();
+        if now.saturating_sub(disk_inode.access_time) >= ATIME_UPDATE_INTERVAL {
+            disk_inode.access_time = now;
+            if self.write_disk_inode(&disk_inode).is_err() {
+                warn!("update access time of inode {} failed", self.inode_id);
+            }
+        }
         Ok(bytes_read)
     }
     fn remove(&self,path:&str)->Result<(),VfsError> {
@@ -810,257 +1080,71 @@ impl VfsNodeOps for FileNode {
     fn truncate(&self,new_size:usize)->Result<(),VfsError> {
         let mut disk_inode = self.read_disk_inode().ok_or(VfsError::InvalidOperation)?;
         let current_size = disk_inode.file_size as usize;
+        let block_size = fs_block_size();
+        if new_size > u32::MAX as usize {
+            return Err(VfsError::InvalidOperation);
+        }
         
+        // 扩展只改文件大小，多出来的部分是洞
         if new_size < current_size {
-            // 截断：释放不需要的块
-            let old_blocks = (current_size + BLOCK_SIZE - 1) / BLOCK_SIZE;
-            let new_blocks = (new_size + BLOCK_SIZE - 1) / BLOCK_SIZE;
-            
-            if new_blocks < old_blocks {
-                let block_device = crate::vfs::get_block_device().ok_or(VfsError::InvalidOperation)?;
-                let data_blocks = get_all_data_blocks(&block_device, &disk_inode);
-                // 回收不需要的块
-                let mut dealloc_indices = Vec::new();
-                for i in new_blocks..old_blocks.min(data_blocks.len()) {
-                    let abs_block_id = data_blocks[i];
-                    let data_index = abs_block_id - get_data_area_start_block();
-                    dealloc_indices.push(crate::bitmap::data_index(data_index as u32));
-                    // 清除直接块指针（简化处理，只处理直接块）
-                    if i < 12 {
-                        disk_inode.direct_blocks[i] = 0;
-                    }
-                }
-                
-                if !dealloc_indices.is_empty() {
-                    let alloc_unit = Bitmap_AllocUnit {
-                        inode: crate::bitmap::inode_index(0), // 不回收 inode
-                        datanode: dealloc_indices,
-                    };
-                    // 只回收数据块，不回收 inode
-                    for data_idx in &alloc_unit.datanode {
-                        let data_bit_idx = data_idx.0 as usize;
-                        let data_byte_idx = data_bit_idx / 8;
-                        let data_bit_offset = data_bit_idx % 8;
-                        let data_block_idx = data_byte_idx / BLOCK_SIZE;
-                        let data_bitmap_start = 1 + INODEBITMAP_COUNT as usize;
-                        
-                        let mut data_block = vec![0u8; BLOCK_SIZE];
-                        block_device.read_block(data_bitmap_start + data_block_idx, &mut data_block);
-                        let byte_in_block = data_byte_idx % BLOCK_SIZE;
-                        data_block[byte_in_block] &= !(1 << data_bit_offset);
-                        block_device.write_block(data_bitmap_start + data_block_idx, &data_block);
-                    }
-                }
+            let block_device = fs_device().ok_or(VfsError::InvalidOperation)?;
+            // 释放新大小之后的数据块和变空的间接块
+            let keep_blocks = (new_size + block_size - 1) / block_size;
+            let freed = release_blocks_from(&block_device, &mut disk_inode, keep_blocks);
+            BitMapAlloctor::dealloc_data(&freed, &block_device);
+            // 最后一块里新大小之后的部分清零，以后再扩展时读出来是0
+            let tail = new_size % block_size;
+            let last = if tail != 0 { lookup_block(&block_device, &disk_inode, new_size / block_size) } else { 0 };
+            if last != 0 {
+                let block_id = get_data_block_id(last as usize);
+                let mut block = vec![0u8; block_size];
+                block_device.read_block(block_id, &mut block);
+                block[tail..].fill(0);
+                block_device.write_block(block_id, &block);
             }
         }
         
         disk_inode.file_size = new_size as u32;
+        disk_inode.modify_time = current_time();
         self.write_disk_inode(&disk_inode)?;
         Ok(())
     }
     fn write_at(&self,offset:usize,buf:&[u8])->Result<usize,VfsError> {
-        let block_device = crate::vfs::get_block_device().ok_or(VfsError::InvalidOperation)?;
+        let block_device = fs_device().ok_or(VfsError::InvalidOperation)?;
         
         // 读取当前的 DiskInode
         let mut disk_inode = self.read_disk_inode().ok_or(VfsError::InvalidOperation)?;
         let current_size = disk_inode.file_size as usize;
-        let new_size = (offset + buf.len()).max(current_size);
-        
-        // 计算需要的块数
-        let blocks_needed = (new_size + BLOCK_SIZE - 1) / BLOCK_SIZE;
-        let current_blocks = (current_size + BLOCK_SIZE - 1) / BLOCK_SIZE;
-        
-        // 如果需要更多块，分配新的数据块
-        if blocks_needed > current_blocks {
-            let additional_blocks = blocks_needed - current_blocks;
-            
-            // 分配新的数据块
-            let alloc_unit = BitMapAlloctor::alloc_datamap(additional_blocks, block_device.clone())
-                .ok_or(VfsError::InvalidOperation)?;
-            
-            if alloc_unit.datanode.len() < additional_blocks {
-                return Err(VfsError::InvalidOperation);
-            }
-            
-            // 将新分配的数据块索引写入 DiskInode
-            for (i, data_idx) in alloc_unit.datanode.iter().enumerate() {
-                let target_block_idx = current_blocks + i;
-                
-                if target_block_idx < 12 {
-                    // 使用直接块
-                    disk_inode.direct_blocks[target_block_idx] = data_idx.0 as u32;
-                } else if target_block_idx < 12 + POINTERS_PER_BLOCK {
-                    // 使用一级间接块
-                    if disk_inode.indirect_block == 0 {
-                        // 分配间接块
-                        let indirect_alloc = BitMapAlloctor::alloc_datamap(1, block_device.clone())
-                            .ok_or(VfsError::InvalidOperation)?;
-                        if indirect_alloc.datanode.is_empty() {
-                            return Err(VfsError::InvalidOperation);
-                        }
-                        disk_inode.indirect_block = indirect_alloc.datanode[0].0 as u32;
-                    }
-                    
-                    // 读取间接块
-                    let mut indirect_pointers = read_indirect_block(&block_device, disk_inode.indirect_block as usize);
-                    
-                    // 确保有足够的空间
-                    while indirect_pointers.len() <= target_block_idx - 12 {
-                        indirect_pointers.push(0);
-                    }
-                    
-                    // 写入新的指针
-                    indirect_pointers[target_block_idx - 12] = data_idx.0 as u32;
-                    
-                    // 写回间接块
-                    write_indirect_block(&block_device, disk_inode.indirect_block as usize, &indirect_pointers);
-                } else if target_block_idx < 12 + POINTERS_PER_BLOCK + POINTERS_PER_BLOCK * POINTERS_PER_BLOCK {
-                    // 使用二级间接块
-                    let level1_idx = (target_block_idx - 12 - POINTERS_PER_BLOCK) / POINTERS_PER_BLOCK;
-                    let level2_idx = (target_block_idx - 12 - POINTERS_PER_BLOCK) % POINTERS_PER_BLOCK;
-                    
-                    // 分配或获取二级间接块
-                    if disk_inode.double_indirect == 0 {
-                        let double_indirect_alloc = BitMapAlloctor::alloc_datamap(1, block_device.clone())
-                            .ok_or(VfsError::InvalidOperation)?;
-                        if double_indirect_alloc.datanode.is_empty() {
-                            return Err(VfsError::InvalidOperation);
-                        }
-                        disk_inode.double_indirect = double_indirect_alloc.datanode[0].0 as u32;
-                    }
-                    
-                    // 读取二级间接块（包含一级间接块指针）
-                    let mut level1_pointers = read_indirect_block(&block_device, disk_inode.double_indirect as usize);
-                    
-                    // 确保有足够的空间
-                    while level1_pointers.len() <= level1_idx {
-                        level1_pointers.push(0);
-                    }
-                    
-                    // 分配或获取一级间接块
-                    if level1_pointers[level1_idx] == 0 {
-                        let level1_alloc = BitMapAlloctor::alloc_datamap(1, block_device.clone())
-                            .ok_or(VfsError::InvalidOperation)?;
-                        if level1_alloc.datanode.is_empty() {
-                            return Err(VfsError::InvalidOperation);
-                        }
-                        level1_pointers[level1_idx] = level1_alloc.datanode[0].0 as u32;
-                        // 写回二级间接块
-                        write_indirect_block(&block_device, disk_inode.double_indirect as usize, &level1_pointers);
-                    }
-                    
-                    // 读取一级间接块（包含数据块指针）
-                    let mut level2_pointers = read_indirect_block(&block_device, level1_pointers[level1_idx] as usize);
-                    
-                    // 确保有足够的空间
-                    while level2_pointers.len() <= level2_idx {
-                        level2_pointers.push(0);
-                    }
-                    
-                    // 写入数据块指针
-                    level2_pointers[level2_idx] = data_idx.0 as u32;
-                    
-                    // 写回一级间接块
-                    write_indirect_block(&block_device, level1_pointers[level1_idx] as usize, &level2_pointers);
-                } else if target_block_idx < 12 + POINTERS_PER_BLOCK + POINTERS_PER_BLOCK * POINTERS_PER_BLOCK + POINTERS_PER_BLOCK * POINTERS_PER_BLOCK * POINTERS_PER_BLOCK {
-                    // 使用三级间接块
-                    let base_offset = 12 + POINTERS_PER_BLOCK + POINTERS_PER_BLOCK * POINTERS_PER_BLOCK;
-                    let triple_offset = target_block_idx - base_offset;
-                    let level1_idx = triple_offset / (POINTERS_PER_BLOCK * POINTERS_PER_BLOCK);
-                    let level2_idx = (triple_offset % (POINTERS_PER_BLOCK * POINTERS_PER_BLOCK)) / POINTERS_PER_BLOCK;
-                    let level3_idx = triple_offset % POINTERS_PER_BLOCK;
-                    
-                    // 分配或获取三级间接块
-                    if disk_inode.triple_indirect == 0 {
-                        let triple_indirect_alloc = BitMapAlloctor::alloc_datamap(1, block_device.clone())
-                            .ok_or(VfsError::InvalidOperation)?;
-                        if triple_indirect_alloc.datanode.is_empty() {
-                            return Err(VfsError::InvalidOperation);
-                        }
-                        disk_inode.triple_indirect = triple_indirect_alloc.datanode[0].0 as u32;
-                    }
-                    
-                    // 读取三级间接块（包含二级间接块指针）
-                    let mut level1_pointers = read_indirect_block(&block_device, disk_inode.triple_indirect as usize);
-                    
-                    // 确保有足够的空间
-                    while level1_pointers.len() <= level1_idx {
-                        level1_pointers.push(0);
-                    }
-                    
-                    // 分配或获取二级间接块
-                    if level1_pointers[level1_idx] == 0 {
-                        let level1_alloc = BitMapAlloctor::alloc_datamap(1, block_device.clone())
-                            .ok_or(VfsError::InvalidOperation)?;
-                        if level1_alloc.datanode.is_empty() {
-                            return Err(VfsError::InvalidOperation);
-                        }
-                        level1_pointers[level1_idx] = level1_alloc.datanode[0].0 as u32;
-                        // 写回三级间接块
-                        write_indirect_block(&block_device, disk_inode.triple_indirect as usize, &level1_pointers);
-                    }
-                    
-                    // 读取二级间接块（包含一级间接块指针）
-                    let mut level2_pointers = read_indirect_block(&block_device, level1_pointers[level1_idx] as usize);
-                    
-                    // 确保有足够的空间
-                    while level2_pointers.len() <= level2_idx {
-                        level2_pointers.push(0);
-                    }
-                    
-                    // 分配或获取一级间接块
-                    if level2_pointers[level2_idx] == 0 {
-                        let level2_alloc = BitMapAlloctor::alloc_datamap(1, block_device.clone())
-                            .ok_or(VfsError::InvalidOperation)?;
-                        if level2_alloc.datanode.is_empty() {
-                            return Err(VfsError::InvalidOperation);
-                        }
-                        level2_pointers[level2_idx] = level2_alloc.datanode[0].0 as u32;
-                        // 写回二级间接块
-                        write_indirect_block(&block_device, level1_pointers[level1_idx] as usize, &level2_pointers);
-                    }
-                    
-                    // 读取一级间接块（包含数据块指针）
-                    let mut level3_pointers = read_indirect_block(&block_device, level2_pointers[level2_idx] as usize);
-                    
-                    // 确保有足够的空间
-                    while level3_pointers.len() <= level3_idx {
-                        level3_pointers.push(0);
-                    }
-                    
-                    // 写入数据块指针
-                    level3_pointers[level3_idx] = data_idx.0 as u32;
-                    
-                    // 写回一级间接块
-                    write_indirect_block(&block_device, level2_pointers[level2_idx] as usize, &level3_pointers);
-                } else {
-                    // 超过三级间接块支持的范围
-                    return Err(VfsError::InvalidOperation);
-                }
-            }
-            
-            // 写回 DiskInode（包含新分配的块指针）
-            self.write_disk_inode(&disk_inode)?;
+        // 文件大小用u32记录
+        if offset + buf.len() > u32::MAX as usize {
+            return Err(VfsError::InvalidOperation);
         }
+        let block_size = fs_block_size();
         
-        // 写入数据到磁盘
-        let data_blocks = get_all_data_blocks(&block_device, &disk_inode);
+        // 逐块写入 块不存在（文件末尾之后或者洞）时分配，新块已经清零
         let mut bytes_written = 0;
         let mut current_offset = offset;
+        let mut result = Ok(());
         
         while bytes_written < buf.len() {
-            let block_idx = current_offset / BLOCK_SIZE;
-            if block_idx >= data_blocks.len() {
-                break; // 超出已分配块范围
-            }
-            
-            let block_offset = current_offset % BLOCK_SIZE;
-            let remaining_in_block = BLOCK_SIZE - block_offset;
+            let block_idx = current_offset / block_size;
+            let block_offset = current_offset % block_size;
+            let remaining_in_block = block_size - block_offset;
             let to_write = (buf.len() - bytes_written).min(remaining_in_block);
             
-            let block_id = data_blocks[block_idx];
-            let mut block = [0u8; BLOCK_SIZE];
-            block_device.read_block(block_id, &mut block);
+            let data_index = match ensure_block(&block_device, &mut disk_inode, block_idx) {
+                Ok(data_index) => data_index,
+                Err(e) => {
+                    // 空间不足，已经写入的部分保留
+                    result = Err(e);
+                    break;
+                }
+            };
+            let block_id = get_data_block_id(data_index as usize);
+            let mut block = vec![0u8; block_size];
+            if to_write < block_size {
+                block_device.read_block(block_id, &mut block);
+            }
             
             block[block_offset..block_offset + to_write].copy_from_slice(&buf[bytes_written..bytes_written + to_write]);
             block_device.write_block(block_id, &block);
@@ -1069,10 +1153,17 @@ impl VfsNodeOps for FileNode {
             current_offset += to_write;
         }
         
-        // 更新文件大小并写回 DiskInode
-        disk_inode.file_size = new_size as u32;
+        // 更新文件大小和修改时间并写回 DiskInode（包含新分配的块指针）
+        // 一个字节都没写入时不改大小
+        if bytes_written > 0 {
+            disk_inode.file_size = current_size.max(offset + bytes_written) as u32;
+        }
+        disk_inode.modify_time = current_time();
         self.write_disk_inode(&disk_inode)?;
         
+        if bytes_written == 0 {
+            result?;
+        }
         Ok(bytes_written)
     }
 }
\ No newline at end of file
@@ -1,8 +1,10 @@
 #![no_std]
 #![feature(split_array)]
 mod bitmap;
+mod block_cache;
 mod blueosfs;
 mod root;
+mod scaled_device;
 mod vfs;
 mod api;
 
@@ -11,7 +13,7 @@ extern crate alloc;
 // 只导出用户需要的 API
 pub use api::{
     // 文件系统初始化
-    initial_root_filesystem,
+    initial_root_filesystem, initial_root_filesystem_with,
     // 文件操作
     open, create_file, create_dir, remove, read_file, write_file, append_file,
     // 目录操作
@@ -20,6 +22,11 @@ pub use api::{
     exists, is_file, is_dir,
     // 文件描述符
     FileDescriptor, FileFlags,
+    // 块缓存写回
+    sync,
 };
-pub use vfs::{FileAttribute,NodeType, VfsError, BlockDeviceTrait, VfsOps, set_global_block_device,VfsNodeOps};
-pub use blueosfs::{BlueosFileSystem, DATABITMAP_COUNT, INODEBITMAP_COUNT};
\ No newline at end of file
+pub use vfs::{FileAttribute,NodeType, VfsError, BlockDeviceTrait, BLOCK_SIZE, MAX_FS_BLOCK_SIZE, DEFAULT_FS_BLOCK_SIZE, TimeSourceTrait, VfsOps, set_global_block_device, set_global_time_source,VfsNodeOps, FormatOptions};
+pub use block_cache::BlockCache;
+pub use scaled_device::ScaledBlockDevice;
+pub use bitmap::bitmap_free_counts;
+pub use blueosfs::{BlueosFileSystem, FsLayout};
\ No newline at end of file
@@ -0,0 +1,53 @@
+///
+/// 文件系统块到设备扇区的转换
+/// 文件系统块可以比扇区(BLOCK_SIZE)大，一个块的读写拆成连续几个扇区的请求
+
+use alloc::sync::Arc;
+use crate::vfs::{BlockDeviceTrait, BLOCK_SIZE};
+
+pub struct ScaledBlockDevice{
+    device:Arc<dyn BlockDeviceTrait>,
+    ///每个文件系统块占的扇区数
+    sectors_per_block:usize,
+}
+
+impl ScaledBlockDevice {
+    ///block_size必须是BLOCK_SIZE的整数倍
+    pub fn new(device:Arc<dyn BlockDeviceTrait>,block_size:usize)->Self{
+        assert!(block_size%BLOCK_SIZE==0,"block size {} is not a multiple of sector size",block_size);
+        ScaledBlockDevice { device, sectors_per_block: block_size/BLOCK_SIZE }
+    }
+
+    ///块大小等于扇区大小时不需要转换，直接用原设备
+    pub fn wrap(device:Arc<dyn BlockDeviceTrait>,block_size:usize)->Arc<dyn BlockDeviceTrait>{
+        if block_size==BLOCK_SIZE{
+            device
+        }else{
+            Arc::new(Self::new(device, block_size))
+        }
+    }
+}
+
+impl BlockDeviceTrait for ScaledBlockDevice {
+    fn read_block(&self,block_id:usize,read_buffer:&mut [u8]) {
+        let first=block_id*self.sectors_per_block;
+        for (index,sector) in read_buffer.chunks_mut(BLOCK_SIZE).take(self.sectors_per_block).enumerate(){
+            self.device.read_block(first+index, sector);
+        }
+    }
+
+    fn write_block(&self,block_id:usize,write_buffer:&[u8]) {
+        let first=block_id*self.sectors_per_block;
+        for (index,sector) in write_buffer.chunks(BLOCK_SIZE).take(self.sectors_per_block).enumerate(){
+            self.device.write_block(first+index, sector);
+        }
+    }
+
+    fn sync(&self) {
+        self.device.sync();
+    }
+
+    fn block_count(&self)->Option<usize> {
+        self.device.block_count().map(|sectors|sectors/self.sectors_per_block)
+    }
+}
@@ -1,7 +1,7 @@
 use core::str;
 
 use alloc::collections::btree_map::BTreeMap;
-use alloc::{sync::{Arc, Weak}, vec::Vec};
+use alloc::{sync::{Arc, Weak}, vec, vec::Vec};
 use alloc::string::{String, ToString};
 use spin::Mutex;
 use spin::RwLock;
@@ -9,13 +9,67 @@ use spin::RwLock;
 
 
 
-///512byte 4096bit
+///块设备扇区大小 512byte 4096bit
 pub const BLOCK_SIZE:usize = 512;
+///文件系统块最大4KiB 必须是BLOCK_SIZE的整数倍
+pub const MAX_FS_BLOCK_SIZE:usize = 4096;
+///格式化时默认的文件系统块大小
+pub const DEFAULT_FS_BLOCK_SIZE:usize = 4096;
 
 ///块设备trait
 pub trait BlockDeviceTrait:Send + Sync{
     fn read_block(&self,block_id:usize,read_buffer:&mut [u8]);
-    fn write_block(&self,block_id:usize,write_buffer:&[u8]); 
+    fn write_block(&self,block_id:usize,write_buffer:&[u8]);
+    ///把缓存的写回设备 没有缓存的设备什么都不做
+    fn sync(&self){}
+    ///设备总块数 不知道时返回None
+    fn block_count(&self)->Option<usize>{
+        None
+    }
+}
+
+///格式化参数
+#[derive(Debug,Clone,Copy)]
+pub struct FormatOptions{
+    ///文件系统占用的总块数（按block_size计）
+    pub block_count:usize,
+    ///inode个数
+    pub inode_count:usize,
+    ///块大小（字节） BLOCK_SIZE的整数倍，不超过MAX_FS_BLOCK_SIZE
+    pub block_size:usize,
+}
+
+///设备大小未知时格式化的字节数(64MiB)
+pub const DEFAULT_FORMAT_BYTES:usize = 64 * 1024 * 1024;
+///默认每多少字节数据一个inode
+pub const DEFAULT_BYTES_PER_INODE:usize = 4096;
+
+impl FormatOptions {
+    ///block_count个block_size大小的块，inode数按DEFAULT_BYTES_PER_INODE估算
+    pub fn new(block_count:usize,block_size:usize)->Self{
+        FormatOptions {
+            block_count,
+            inode_count: (block_count * block_size / DEFAULT_BYTES_PER_INODE).max(16),
+            block_size,
+        }
+    }
+
+    ///按设备大小格式化 block_count()返回的是扇区数
+    pub fn for_device(block_device:&Arc<dyn BlockDeviceTrait>,block_size:usize)->Self{
+        let bytes = block_device.block_count().map_or(DEFAULT_FORMAT_BYTES, |sectors| sectors * BLOCK_SIZE);
+        Self::new(bytes / block_size, block_size)
+    }
+
+    ///块大小是否可用
+    pub fn block_size_valid(&self)->bool{
+        self.block_size >= BLOCK_SIZE && self.block_size <= MAX_FS_BLOCK_SIZE && self.block_size.is_power_of_two()
+    }
+}
+
+///时间源trait 由内核提供墙上时间，给inode打时间戳
+pub trait TimeSourceTrait:Send + Sync{
+    ///返回当前Unix时间戳（秒）
+    fn now_sec(&self)->u64;
 }
 
 //块设备抽象
@@ -29,6 +83,10 @@ pub trait VfsNodeOps:Sync+Send {
     fn get_attribute(&self)->FileAttribute;
     ///返回节点类型
     fn get_type(&self)->NodeType;
+    ///返回磁盘inode编号 用于页缓存等按文件索引的场景，没有inode的节点返回None
+    fn get_inode_id(&self)->Option<usize>{
+        None
+    }
     ///返回父节点引用
     fn get_parent(&self)->Option<Arc<dyn VfsNodeOps>>{
         None
@@ -90,8 +148,8 @@ pub trait VfsOps:Send+Sync {
     fn get_fs_name(&self)->String;
     ///文件系统启动验证，验证文件系统是否正常
     fn verify_file_system(&self)->bool;
-    ///文件系统初始化函数
-    fn initial_file_systeam(&self,block_device:Arc<dyn BlockDeviceTrait>)->Result<(),VfsError>;
+    ///文件系统初始化函数 按options格式化
+    fn initial_file_systeam(&self,block_device:Arc<dyn BlockDeviceTrait>,options:FormatOptions)->Result<(),VfsError>;
 
 }
 ///挂载点
@@ -134,6 +192,24 @@ pub fn get_block_device() -> Option<Arc<dyn BlockDeviceTrait>> {
     GLOBAL_BLOCK_DEVICE.lock().clone()
 }
 
+/// 全局时间源（在 BlueosFS 模块中）
+lazy_static! {
+    static ref GLOBAL_TIME_SOURCE: Mutex<Option<Arc<dyn TimeSourceTrait>>> = Mutex::new(None);
+}
+
+/// 设置全局时间源（由外部调用）
+pub fn set_global_time_source(source: Arc<dyn TimeSourceTrait>) {
+    *GLOBAL_TIME_SOURCE.lock() = Some(source);
+}
+
+/// 获取当前时间戳（秒），没有设置时间源时返回0
+pub fn current_time() -> u32 {
+    match GLOBAL_TIME_SOURCE.lock().as_ref() {
+        Some(source) => source.now_sec() as u32,
+        None => 0,
+    }
+}
+
 #[derive(Debug,Clone)]
 pub struct FileMetadata{
     ///权限
@@ -228,9 +304,9 @@ impl DirNode {
     
     /// 从磁盘读取 DiskInode
     pub fn read_disk_inode(&self) -> Option<crate::bitmap::DiskInode> {
-        let block_device = get_block_device()?;
+        let block_device = crate::blueosfs::fs_device()?;
         let (block_id, offset) = crate::blueosfs::get_inode_block_and_offset(self.inode_id);
-        let mut inode_block = [0u8; BLOCK_SIZE];
+        let mut inode_block = vec![0u8; crate::blueosfs::fs_block_size()];
         block_device.read_block(block_id, &mut inode_block);
         let disk_inode = unsafe {
             *(inode_block.as_mut_ptr().add(offset) as *const crate::bitmap::DiskInode)
@@ -240,9 +316,9 @@ impl DirNode {
     
     /// 写入 DiskInode 到磁盘
     pub fn write_disk_inode(&self, disk_inode: &crate::bitmap::DiskInode) -> Result<(), VfsError> {
-        let block_device = get_block_device().ok_or(VfsError::InvalidOperation)?;
+        let block_device = crate::blueosfs::fs_device().ok_or(VfsError::InvalidOperation)?;
         let (block_id, offset) = crate::blueosfs::get_inode_block_and_offset(self.inode_id);
-        let mut inode_block = [0u8; BLOCK_SIZE];
+        let mut inode_block = vec![0u8; crate::blueosfs::fs_block_size()];
         block_device.read_block(block_id, &mut inode_block);
         let inode_bytes = unsafe {
             core::slice::from_raw_parts(
@@ -280,9 +356,9 @@ impl FileNode {
     
     /// 从磁盘读取 DiskInode
     pub fn read_disk_inode(&self) -> Option<crate::bitmap::DiskInode> {
-        let block_device = get_block_device()?;
+        let block_device = crate::blueosfs::fs_device()?;
         let (block_id, offset) = crate::blueosfs::get_inode_block_and_offset(self.inode_id);
-        let mut inode_block = [0u8; BLOCK_SIZE];
+        let mut inode_block = vec![0u8; crate::blueosfs::fs_block_size()];
         block_device.read_block(block_id, &mut inode_block);
         let disk_inode = unsafe {
             &*(inode_block.as_ptr().add(offset) as *const crate::bitmap::DiskInode)
@@ -292,9 +368,9 @@ impl FileNode {
     
     /// 写入 DiskInode 到磁盘
     pub fn write_disk_inode(&self, disk_inode: &crate::bitmap::DiskInode) -> Result<(), VfsError> {
-        let block_device = get_block_device().ok_or(VfsError::InvalidOperation)?;
+        let block_device = crate::blueosfs::fs_device().ok_or(VfsError::InvalidOperation)?;
         let (block_id, offset) = crate::blueosfs::get_inode_block_and_offset(self.inode_id);
-        let mut inode_block = [0u8; BLOCK_SIZE];
+        let mut inode_block = vec![0u8; crate::blueosfs::fs_block_size()];
         block_device.read_block(block_id, &mut inode_block);
         let inode_bytes = unsafe {
             core::slice::from_raw_parts(
@@ -309,24 +385,26 @@ impl FileNode {
     
     /// 从磁盘读取数据块
     fn read_data_block(&self, data_index: usize, buf: &mut [u8]) -> Result<(), VfsError> {
-        let block_device = get_block_device().ok_or(VfsError::InvalidOperation)?;
+        let block_device = crate::blueosfs::fs_device().ok_or(VfsError::InvalidOperation)?;
         let block_id = crate::blueosfs::get_data_block_id(data_index);
-        let mut block = [0u8; BLOCK_SIZE];
+        let block_size = crate::blueosfs::fs_block_size();
+        let mut block = vec![0u8; block_size];
         block_device.read_block(block_id, &mut block);
-        let read_len = buf.len().min(BLOCK_SIZE);
+        let read_len = buf.len().min(block_size);
         buf[..read_len].copy_from_slice(&block[..read_len]);
         Ok(())
     }
     
     /// 写入数据块到磁盘
     fn write_data_block(&self, data_index: usize, buf: &[u8]) -> Result<(), VfsError> {
-        let block_device = get_block_device().ok_or(VfsError::InvalidOperation)?;
+        let block_device = crate::blueosfs::fs_device().ok_or(VfsError::InvalidOperation)?;
         let block_id = crate::blueosfs::get_data_block_id(data_index);
-        let mut block = [0u8; BLOCK_SIZE];
-        if buf.len() <= BLOCK_SIZE {
+        let block_size = crate::blueosfs::fs_block_size();
+        let mut block = vec![0u8; block_size];
+        if buf.len() <= block_size {
             block[..buf.len()].copy_from_slice(buf);
         } else {
-            block.copy_from_slice(&buf[..BLOCK_SIZE]);
+            block.copy_from_slice(&buf[..block_size]);
         }
         block_device.write_block(block_id, &block);
         Ok(())
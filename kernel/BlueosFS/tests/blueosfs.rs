@@ -0,0 +1,285 @@
+//! 在主机上用内存块设备测试BlueosFS
+//! 文件系统状态是全局的，用到它的测试通过fs_lock串行执行
+//! 运行: cargo test --target x86_64-unknown-linux-gnu
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use BlueosFS::*;
+
+///内存块设备 每块BLOCK_SIZE字节
+struct RamDisk{
+    sectors:Mutex<Vec<[u8;BLOCK_SIZE]>>,
+}
+
+impl RamDisk {
+    fn new(sector_count:usize)->Arc<Self>{
+        Arc::new(RamDisk { sectors: Mutex::new(vec![[0u8;BLOCK_SIZE];sector_count]) })
+    }
+
+    fn sector(&self,block_id:usize)->[u8;BLOCK_SIZE]{
+        self.sectors.lock().unwrap()[block_id]
+    }
+}
+
+impl BlockDeviceTrait for RamDisk {
+    fn read_block(&self,block_id:usize,read_buffer:&mut [u8]) {
+        let len=read_buffer.len().min(BLOCK_SIZE);
+        read_buffer[..len].copy_from_slice(&self.sectors.lock().unwrap()[block_id][..len]);
+    }
+
+    fn write_block(&self,block_id:usize,write_buffer:&[u8]) {
+        let len=write_buffer.len().min(BLOCK_SIZE);
+        self.sectors.lock().unwrap()[block_id][..len].copy_from_slice(&write_buffer[..len]);
+    }
+
+    fn block_count(&self)->Option<usize> {
+        Some(self.sectors.lock().unwrap().len())
+    }
+}
+
+static FS_LOCK:Mutex<()>=Mutex::new(());
+
+///串行执行用到全局文件系统的测试 前一个测试panic不影响后面的
+fn fs_lock()->MutexGuard<'static,()>{
+    FS_LOCK.lock().unwrap_or_else(|poisoned|poisoned.into_inner())
+}
+
+///在内存盘上按options格式化并挂载
+fn format_ram_disk_with(disk:&Arc<RamDisk>,options:FormatOptions){
+    let device:Arc<dyn BlockDeviceTrait>=disk.clone();
+    set_global_block_device(device);
+    initial_root_filesystem_with(options);
+}
+
+///在sector_count个扇区的内存盘上按block_size格式化并挂载
+fn format_ram_disk(sector_count:usize,block_size:usize)->Arc<RamDisk>{
+    let disk=RamDisk::new(sector_count);
+    let device:Arc<dyn BlockDeviceTrait>=disk.clone();
+    format_ram_disk_with(&disk, FormatOptions::for_device(&device, block_size));
+    disk
+}
+
+///按超级块里的布局重新挂载
+fn remount(disk:&Arc<RamDisk>){
+    let device:Arc<dyn BlockDeviceTrait>=disk.clone();
+    set_global_block_device(device);
+    initial_root_filesystem();
+}
+
+///空闲数据块数
+fn free_data_blocks()->usize{
+    bitmap_free_counts().expect("file system not mounted").1
+}
+
+fn pattern(len:usize)->Vec<u8>{
+    (0..len).map(|index|(index*7+index/512) as u8).collect()
+}
+
+#[test]
+fn block_cache_writes_back_on_sync(){
+    let disk=RamDisk::new(64);
+    let cache=BlockCache::new(disk.clone(), 4);
+
+    cache.write_block(3, &[7u8;BLOCK_SIZE]);
+    assert_eq!(disk.sector(3),[0u8;BLOCK_SIZE]);
+    let mut buf=[0u8;BLOCK_SIZE];
+    cache.read_block(3, &mut buf);
+    assert_eq!(buf,[7u8;BLOCK_SIZE]);
+    assert_eq!(cache.usage(),(1,1));
+
+    cache.sync();
+    assert_eq!(disk.sector(3),[7u8;BLOCK_SIZE]);
+    assert_eq!(cache.usage(),(1,0));
+}
+
+#[test]
+fn block_cache_writes_back_on_eviction(){
+    let disk=RamDisk::new(64);
+    let cache=BlockCache::new(disk.clone(), 4);
+
+    for block_id in 10..15{
+        cache.write_block(block_id, &[block_id as u8;BLOCK_SIZE]);
+    }
+    //容量为4 最久没用的块10被淘汰时写回
+    assert_eq!(disk.sector(10),[10u8;BLOCK_SIZE]);
+    assert_eq!(disk.sector(14),[0u8;BLOCK_SIZE]);
+    assert_eq!(cache.usage(),(4,4));
+
+    //只写一部分时保留块里原来的内容
+    cache.write_block(10, &[1u8;16]);
+    let mut buf=[0u8;BLOCK_SIZE];
+    cache.read_block(10, &mut buf);
+    assert_eq!(buf[..16],[1u8;16]);
+    assert_eq!(buf[16..],[10u8;BLOCK_SIZE-16]);
+}
+
+#[test]
+fn format_fits_the_device(){
+    let _guard=fs_lock();
+    let disk=RamDisk::new(2048);
+    let device:Arc<dyn BlockDeviceTrait>=disk.clone();
+    let options=FormatOptions::for_device(&device, BLOCK_SIZE);
+    assert_eq!(options.block_count,2048);
+    format_ram_disk_with(&disk, options);
+
+    //位图和inode表之外的块都是数据块 根目录占一个inode
+    let (free_inodes,free_data)=bitmap_free_counts().unwrap();
+    assert_eq!(free_inodes,options.inode_count-1);
+    assert!(free_data<2048 && free_data>1800);
+}
+
+#[test]
+fn remount_uses_the_superblock_layout(){
+    let _guard=fs_lock();
+    let disk=RamDisk::new(1024);
+    format_ram_disk_with(&disk, FormatOptions { block_count: 1024, inode_count: 40, block_size: BLOCK_SIZE });
+    create_dir("/d").unwrap();
+    create_file("/d/f").unwrap();
+    let data=pattern(20_000);
+    write_file("/d/f",&data).unwrap();
+    let counts=bitmap_free_counts().unwrap();
+    assert_eq!(counts.0,40-3);
+    sync();
+
+    //按设备大小估算的inode数和格式化时不同，挂载时要用超级块里记录的
+    remount(&disk);
+    assert_eq!(bitmap_free_counts().unwrap(),counts);
+    assert_eq!(read_file("/d/f").unwrap(),data);
+}
+
+#[test]
+fn large_blocks_round_trip_after_remount(){
+    let _guard=fs_lock();
+    let disk=format_ram_disk(8192, 4096);
+    create_dir("/d").unwrap();
+    create_file("/d/f").unwrap();
+    let data=pattern(200_000);
+    write_file("/d/f",&data).unwrap();
+    sync();
+
+    remount(&disk);
+    assert_eq!(read_file("/d/f").unwrap(),data);
+}
+
+#[test]
+fn scaled_device_splits_blocks_into_sectors(){
+    let disk=RamDisk::new(64);
+    let device=ScaledBlockDevice::wrap(disk.clone(), 4*BLOCK_SIZE);
+    assert_eq!(device.block_count(),Some(16));
+
+    let data=pattern(4*BLOCK_SIZE);
+    device.write_block(2, &data);
+    for sector in 0..4{
+        assert_eq!(disk.sector(8+sector)[..],data[sector*BLOCK_SIZE..(sector+1)*BLOCK_SIZE]);
+    }
+    let mut buf=vec![0u8;4*BLOCK_SIZE];
+    device.read_block(2, &mut buf);
+    assert_eq!(buf,data);
+}
+
+#[test]
+fn sparse_file_reads_zero(){
+    let _guard=fs_lock();
+    format_ram_disk(4096, 512);
+    create_file("/sparse").unwrap();
+    let file=open("/sparse",FileFlags::read_write()).unwrap();
+    let before=free_data_blocks();
+
+    //扩展只改大小，不分配块
+    file.node().truncate(1_000_000).unwrap();
+    assert_eq!(file.size(),1_000_000);
+    assert_eq!(free_data_blocks(),before);
+    let mut buf=[0xffu8;64];
+    file.seek(500_000).unwrap();
+    assert_eq!(file.read(&mut buf).unwrap(),64);
+    assert_eq!(buf,[0u8;64]);
+
+    //在洞中间写入 只分配写到的块和它的间接块
+    file.seek(300_000).unwrap();
+    assert_eq!(file.write(b"hole").unwrap(),4);
+    assert!(before-free_data_blocks()<=3);
+    let mut expected=vec![0u8;1_000_000];
+    expected[300_000..300_004].copy_from_slice(b"hole");
+    assert_eq!(read_file("/sparse").unwrap(),expected);
+
+    //写到文件末尾之后 中间的部分也是洞
+    file.seek(1_200_000).unwrap();
+    assert_eq!(file.write(b"tail").unwrap(),4);
+    assert_eq!(file.size(),1_200_004);
+    expected.resize(1_200_000,0);
+    expected.extend_from_slice(b"tail");
+    assert_eq!(read_file("/sparse").unwrap(),expected);
+}
+
+#[test]
+fn truncate_releases_every_indirect_level(){
+    let _guard=fs_lock();
+    //512字节的块每块128个指针 12个直接块之后是一级间接，再往后是二级间接
+    format_ram_disk(4096, 512);
+    create_file("/big").unwrap();
+    let file=open("/big",FileFlags::read_write()).unwrap();
+    let before=free_data_blocks();
+
+    let data=pattern((12+128+200)*512);
+    assert_eq!(file.write(&data).unwrap(),data.len());
+    //340个数据块 一级间接块1个 二级间接的根和两个下级间接块
+    assert_eq!(before-free_data_blocks(),340+1+3);
+
+    //截到一级间接范围内 二级间接整棵释放
+    let keep=(12+5)*512+100;
+    file.node().truncate(keep).unwrap();
+    assert_eq!(before-free_data_blocks(),18+1);
+    assert_eq!(read_file("/big").unwrap(),data[..keep]);
+
+    //截到直接块范围内 一级间接块释放
+    file.node().truncate(100).unwrap();
+    assert_eq!(before-free_data_blocks(),1);
+    assert_eq!(read_file("/big").unwrap(),data[..100]);
+
+    //再扩展时截掉的部分读出来是0
+    file.node().truncate(600).unwrap();
+    let mut expected=data[..100].to_vec();
+    expected.resize(600,0);
+    assert_eq!(read_file("/big").unwrap(),expected);
+
+    file.node().truncate(0).unwrap();
+    assert_eq!(free_data_blocks(),before);
+}
+
+#[test]
+fn remove_releases_indirect_blocks(){
+    let _guard=fs_lock();
+    format_ram_disk(4096, 512);
+    let before=free_data_blocks();
+    create_file("/gone").unwrap();
+    write_file("/gone",&pattern((12+128+10)*512)).unwrap();
+    assert!(free_data_blocks()<before);
+    remove("/gone").unwrap();
+    assert_eq!(free_data_blocks(),before);
+}
+
+#[test]
+fn failed_allocation_frees_new_indirect_blocks(){
+    let _guard=fs_lock();
+    format_ram_disk(150, 512);
+    create_file("/fill").unwrap();
+    create_file("/sparse").unwrap();
+
+    //填到只剩2个空闲块 填充文件有一个一级间接块
+    let filler_blocks=free_data_blocks()-3;
+    assert!(filler_blocks>12 && filler_blocks<=12+128);
+    write_file("/fill",&pattern(filler_blocks*512)).unwrap();
+    assert_eq!(free_data_blocks(),2);
+
+    //二级间接范围的第一块要3个块 分配到数据块时失败，前面新分配的两个间接块要释放
+    let file=open("/sparse",FileFlags::read_write()).unwrap();
+    file.seek((12+128)*512).unwrap();
+    assert!(file.write(b"x").is_err());
+    assert_eq!(free_data_blocks(),2);
+    assert_eq!(file.size(),0);
+
+    //剩下的块仍然可以用
+    file.seek(0).unwrap();
+    assert_eq!(file.write(b"x").unwrap(),1);
+    assert_eq!(free_data_blocks(),1);
+}
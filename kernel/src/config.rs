@@ -37,7 +37,11 @@ extern "C"{
 ///MB的简单封装
 pub const  MB:usize=1024*1024;
 pub const  PAGE_SIZE:usize=4096;//每个页面大小4kb
-pub const KERNEL_HEAP_SIZE:usize=1*MB;//内核堆大小
+pub const KERNEL_HEAP_SIZE:usize=1*MB;//内核堆初始大小 不够时从页帧分配器扩展
+///内核堆每次至少扩展的页数
+pub const KERNEL_HEAP_GROW_PAGES:usize=64;
+///内核堆空闲低于这个字节数时提前扩展，保证扩展过程中页帧分配器自己还有堆可用
+pub const KERNEL_HEAP_RESERVE:usize=64*1024;
 pub const KERNEL_STACK_SIZE:usize=PAGE_SIZE*4;//应用内核栈有四个页面的大小
 pub static mut KERNEL_HEADP:[u8;KERNEL_HEAP_SIZE]=[0;KERNEL_HEAP_SIZE];//内核堆实例
 pub const  PAGE_SIZE_BITS:usize=12;//2^12=4096 4kb
@@ -49,15 +53,41 @@ pub const TRAP_BOTTOM_ADDR:usize=usize::MAX-PAGE_SIZE+1;
 pub const TRAP_CONTEXT_ADDR:usize=TRAP_BOTTOM_ADDR-PAGE_SIZE;
 ///用户start函数在用户地址空间的起始映射地址，不携带页帧，直接操作页表映射 D
 pub const USERLIB_START_RETURN_HIGNADDR:usize=TRAP_CONTEXT_ADDR-PAGE_SIZE;
-pub const HIGNADDRESS_MASK:usize=0xFFFFFFE000000000;//0xFFFFFFFFFFFFF000 hb *0xfffffffffffff070
+///用户栈大小上限(RLIMIT_STACK) 栈顶随分页模式位于用户地址空间最高处，在缺页时向下增长，最多增长这么多
+pub const USER_STACK_LIMIT:usize=8*MB;
+///用户栈初始页数 页帧同样在缺页时分配
+pub const USER_STACK_INIT_PAGES:usize=1;
+///内核选择mmap地址时的搜索起点 上界随分页模式变化，见memory::mmap_top
+pub const MMAP_BASE:usize=0x10_0000_0000;
+///是否允许用户映射同时可写可执行(W^X) 默认拒绝
+pub const USER_WX_ALLOWED:bool=false;
+///空闲页帧低于这个数时在缺页路径上换出匿名页
+pub const SWAP_LOW_WATERMARK:usize=64;
+///每次回收最多换出的页数
+pub const SWAP_RECLAIM_BATCH:usize=16;
+///文件系统块缓存最多缓存的块数
+pub const BLOCK_CACHE_SIZE:usize=256;
+///Sv39 satp中ASID字段最大值 实际位数启动时探测
+pub const ASID_MAX:usize=0xFFFF;
 ///每秒多少次时钟中断
 pub const TIME_FREQUENT:usize=100;
 
 
 ///任务初始ticket(优先级)
 pub const TASK_TICKET:usize=100;
+///ticket范围 越大优先级越高
+pub const TASK_TICKET_MIN:usize=1;
+pub const TASK_TICKET_MAX:usize=1000;
 ///初始大数
 pub const BIG_INT:usize=1_000_000;
+///MLFQ队列层数
+pub const MLFQ_LEVELS:usize=3;
+///MLFQ每层的时间片（时钟中断数）
+pub const MLFQ_TIME_SLICE:[usize;MLFQ_LEVELS]=[1,2,4];
+///MLFQ优先级提升周期（时钟中断数）
+pub const MLFQ_BOOST_INTERVAL:usize=TIME_FREQUENT;
+///init进程的pid（第一个加载的app），init退出时内核关机
+pub const INIT_PID:usize=0;
 
 use lazy_static::lazy_static;
 use crate::{MapSet, sync::UPSafeCell};
@@ -0,0 +1,153 @@
+///设备树(FDT)的最小解析，只用于按compatible查找设备的MMIO基地址
+///必须在激活内核地址空间之前调用，因为dtb不一定在内核恒等映射的范围内
+use alloc::string::String;
+use log::debug;
+
+const FDT_MAGIC:u32 = 0xd00d_feed;
+/* 结构块token */
+const FDT_BEGIN_NODE:u32 = 0x1;
+const FDT_END_NODE:u32 = 0x2;
+const FDT_PROP:u32 = 0x3;
+const FDT_NOP:u32 = 0x4;
+const FDT_END:u32 = 0x9;
+
+///读取大端u32
+fn read_be_u32(addr:usize)->u32{
+    unsafe {
+        u32::from_be(core::ptr::read_unaligned(addr as *const u32))
+    }
+}
+
+///读取以0结尾的字符串
+fn read_cstr(addr:usize)->&'static [u8]{
+    let mut len = 0;
+    unsafe {
+        while *((addr + len) as *const u8) != 0 {
+            len += 1;
+        }
+        core::slice::from_raw_parts(addr as *const u8, len)
+    }
+}
+
+///4字节对齐
+fn align4(value:usize)->usize{
+    (value + 3) & !3
+}
+
+///结构块中的一项
+enum FdtToken {
+    ///节点开始 节点名
+    BeginNode(&'static [u8]),
+    EndNode,
+    ///属性 属性名和属性值
+    Prop(&'static [u8], &'static [u8]),
+}
+
+///顺序遍历设备树结构块 visit返回Some时提前结束
+fn fdt_walk<T>(dtb_addr:usize,mut visit:impl FnMut(FdtToken)->Option<T>)->Option<T>{
+    if dtb_addr == 0 || read_be_u32(dtb_addr) != FDT_MAGIC {
+        debug!("Invalid FDT at {:#x}",dtb_addr);
+        return None;
+    }
+    let struct_start = dtb_addr + read_be_u32(dtb_addr + 8) as usize;
+    let strings_start = dtb_addr + read_be_u32(dtb_addr + 12) as usize;
+
+    let mut cursor = struct_start;
+    loop {
+        let token = read_be_u32(cursor);
+        cursor += 4;
+        let item = match token {
+            FDT_BEGIN_NODE => {
+                //跳过节点名
+                let name = read_cstr(cursor);
+                cursor = align4(cursor + name.len() + 1);
+                FdtToken::BeginNode(name)
+            }
+            FDT_END_NODE => FdtToken::EndNode,
+            FDT_PROP => {
+                let len = read_be_u32(cursor) as usize;
+                let name_off = read_be_u32(cursor + 4) as usize;
+                let value = cursor + 8;
+                cursor = align4(value + len);
+                let name = read_cstr(strings_start + name_off);
+                let value = unsafe { core::slice::from_raw_parts(value as *const u8, len) };
+                FdtToken::Prop(name, value)
+            }
+            FDT_NOP => continue,
+            FDT_END => {
+                return None;
+            }
+            _ => {
+                debug!("Unknown FDT token:{:#x}",token);
+                return None;
+            }
+        };
+        if let Some(result) = visit(item) {
+            return Some(result);
+        }
+    }
+}
+
+///在设备树中查找compatible包含compat的节点，返回reg属性的第一个地址
+/// dtb_addr:sbi传入的设备树物理地址 默认#address-cells为2(qemu virt)
+pub fn fdt_find_compatible(dtb_addr:usize,compat:&str)->Option<usize>{
+    //当前节点是否匹配compatible 以及当前节点的reg地址
+    let mut matched = false;
+    let mut reg:Option<usize> = None;
+    fdt_walk(dtb_addr, |token| {
+        match token {
+            FdtToken::BeginNode(_) => {
+                //新节点，重置状态
+                matched = false;
+                reg = None;
+            }
+            FdtToken::EndNode => {
+                if matched {
+                    return Some(reg);
+                }
+                matched = false;
+                reg = None;
+            }
+            FdtToken::Prop(name, value) => {
+                if name == b"compatible" {
+                    //compatible是多个以0分割的字符串
+                    matched = value
+                        .split(|&b| b == 0)
+                        .any(|item| item == compat.as_bytes());
+                } else if name == b"reg" && value.len() >= 8 {
+                    let high = read_be_u32(value.as_ptr() as usize) as usize;
+                    let low = read_be_u32(value.as_ptr() as usize + 4) as usize;
+                    reg = Some((high << 32) | low);
+                }
+            }
+        }
+        None
+    }).flatten()
+}
+
+///读取/chosen节点的bootargs（qemu -append传入的内核命令行）
+///返回拷贝，激活地址空间后dtb不可访问
+pub fn fdt_chosen_bootargs(dtb_addr:usize)->Option<String>{
+    let mut depth = 0;
+    let mut in_chosen = false;
+    fdt_walk(dtb_addr, |token| {
+        match token {
+            FdtToken::BeginNode(name) => {
+                depth += 1;
+                //根节点深度1 chosen是根的直接子节点
+                in_chosen = depth == 2 && name == b"chosen";
+            }
+            FdtToken::EndNode => {
+                depth -= 1;
+                in_chosen = false;
+            }
+            FdtToken::Prop(name, value) => {
+                if in_chosen && name == b"bootargs" {
+                    let args = value.split(|&b| b == 0).next().unwrap_or(&[]);
+                    return Some(String::from_utf8_lossy(args).into_owned());
+                }
+            }
+        }
+        None
+    })
+}
@@ -2,7 +2,11 @@ mod stdio;
 mod normal_externel_interrupt;
 mod virtio;
 mod virtio_blk;
+mod fdt;
+mod rtc;
 pub use self::stdio::*;
 pub use self::virtio::*;
 pub use self::virtio_blk::*;
-pub use self::virtio_blk::{init_global_block_device, get_global_block_device};
\ No newline at end of file
+pub use self::rtc::*;
+pub use self::fdt::fdt_chosen_bootargs;
+pub use self::virtio_blk::{init_global_block_device, get_global_block_device, probe_swap_block_device};
\ No newline at end of file
@@ -0,0 +1,78 @@
+///goldfish RTC驱动 qemu virt平台的实时时钟
+///寄存器返回的是自1970年以来的纳秒数，先读低32位会锁存高32位
+use alloc::sync::Arc;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use BlueosFS::TimeSourceTrait;
+use crate::driver::fdt::fdt_find_compatible;
+use crate::sync::UPSafeCell;
+
+const GOLDFISH_RTC_COMPATIBLE:&str = "google,goldfish-rtc";
+/* 寄存器偏移 */
+const RTC_TIME_LOW:usize = 0x00;
+const RTC_TIME_HIGH:usize = 0x04;
+
+const NSEC_PER_SEC:u64 = 1_000_000_000;
+
+pub struct GoldfishRtc{
+    ///MMIO基地址（内核恒等映射硬件段）
+    base:usize
+}
+
+impl GoldfishRtc {
+    pub fn new(base:usize)->Self{
+        GoldfishRtc { base }
+    }
+
+    ///读取纳秒时间戳 必须先读低位
+    pub fn read_ns(&self)->u64{
+        unsafe {
+            let low = core::ptr::read_volatile((self.base + RTC_TIME_LOW) as *const u32) as u64;
+            let high = core::ptr::read_volatile((self.base + RTC_TIME_HIGH) as *const u32) as u64;
+            (high << 32) | low
+        }
+    }
+
+    ///读取Unix时间戳（秒）
+    pub fn read_sec(&self)->u64{
+        self.read_ns() / NSEC_PER_SEC
+    }
+}
+
+lazy_static!{
+    ///全局RTC实例，没有在设备树找到时为None
+    pub static ref RTC:UPSafeCell<Option<GoldfishRtc>> = UPSafeCell::new(None);
+}
+
+///从设备树探测goldfish RTC 必须在激活内核地址空间之前调用
+pub fn init_rtc(dtb_addr:usize){
+    match fdt_find_compatible(dtb_addr, GOLDFISH_RTC_COMPATIBLE) {
+        Some(base)=>{
+            let rtc = GoldfishRtc::new(base);
+            debug!("Goldfish RTC at {:#x}, unix time:{}",base,rtc.read_sec());
+            *RTC.lock() = Some(rtc);
+        }
+        None=>{
+            warn!("No goldfish RTC found in FDT, realtime clock unavailable");
+        }
+    }
+}
+
+///返回Unix时间戳（秒），没有RTC时返回None
+pub fn rtc_read_sec()->Option<u64>{
+    RTC.lock().as_ref().map(|rtc| rtc.read_sec())
+}
+
+///提供给BlueosFS的时间源
+pub struct RtcTimeSource;
+
+impl TimeSourceTrait for RtcTimeSource {
+    fn now_sec(&self)->u64 {
+        rtc_read_sec().unwrap_or(0)
+    }
+}
+
+///把RTC设置为BlueosFS的时间源
+pub fn install_fs_time_source(){
+    BlueosFS::set_global_time_source(Arc::new(RtcTimeSource));
+}
@@ -7,6 +7,10 @@ use crate::{memory::*};
 use crate::sync::UPSafeCell;
 use spin::Mutex;
 const VIRTIO0: usize = 0x10001000;
+///第二个virtio-mmio槽(virtio-mmio-bus.1)，挂载交换分区
+const VIRTIO1: usize = 0x10002000;
+const VIRTIO_MAGIC: u32 = 0x74726976;//"virt"
+const VIRTIO_DEVICE_ID_BLOCK: u32 = 2;
 
 lazy_static!{
     static ref QUEUE_FRAMES:UPSafeCell<Vec<FramTracker>> = UPSafeCell::new(Vec::new());
@@ -25,20 +29,47 @@ pub fn get_global_block_device() -> Option<Arc<dyn BlockDeviceTrait>> {
     GLOBAL_BLOCK_DEVICE.lock().clone()
 }
 
-pub struct VirtBlk(UPSafeCell<VirtIOBlk<'static,VirtioHal>>);
+/// 探测交换分区使用的块设备 槽上没有块设备时返回None
+pub fn probe_swap_block_device() -> Option<Arc<dyn BlockDeviceTrait>> {
+    VirtBlk::probe(VIRTIO1).map(|device| Arc::new(device) as Arc<dyn BlockDeviceTrait>)
+}
+
+///virtio-mmio设备配置空间偏移 块设备配置的第一个字段是容量(512字节扇区数)
+const VIRTIO_MMIO_CONFIG: usize = 0x100;
+
+///(驱动,设备容量扇区数)
+pub struct VirtBlk(UPSafeCell<VirtIOBlk<'static,VirtioHal>>,usize);
 
 
 
 impl VirtBlk {
+    ///检查virtio-mmio槽上是否有块设备，有则初始化
+    pub fn probe(base:usize)->Option<Self>{
+        let (magic,device_id)=unsafe {
+            ((base as *const u32).read_volatile(),((base+8) as *const u32).read_volatile())
+        };
+        if magic!=VIRTIO_MAGIC || device_id!=VIRTIO_DEVICE_ID_BLOCK{
+            return None;
+        }
+        let blk=unsafe { VirtIOBlk::new(&mut *(base as *mut VirtIOHeader)) }.ok()?;
+        Some(VirtBlk(UPSafeCell::new(blk),Self::capacity(base)))
+    }
+
     pub fn new()->Self{
         VirtBlk(
             UPSafeCell::new(
                 unsafe {
                     VirtIOBlk::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).expect("failed new blk device")
                 }
-            )
+            ),
+            Self::capacity(VIRTIO0)
         )
     }
+
+    ///从配置空间读设备容量
+    fn capacity(base:usize)->usize{
+        unsafe { ((base+VIRTIO_MMIO_CONFIG) as *const u64).read_volatile() as usize }
+    }
 }
 
 impl BlockDevice for VirtBlk {
@@ -62,30 +93,24 @@ impl BlockDeviceTrait for VirtBlk {
     fn write_block(&self,block_id:usize,write_buffer:&[u8]) {
         self.write_blk(block_id as u64, write_buffer);
     }
+    fn block_count(&self)->Option<usize> {
+        Some(self.1)
+    }
 }
 
 pub struct VirtioHal;
 impl Hal for VirtioHal {
     fn dma_alloc(pages: usize) -> virtio_drivers::PhysAddr {
-        let mut base_ppn = PhysiNumber(0);
-        for i in 0..pages {
-            let frame_trace =alloc_frame().expect("no frame alloced");
-            let ppn = frame_trace.ppn;
-            if i==0{
-                base_ppn = ppn;
-            }
-            QUEUE_FRAMES.lock().push(frame_trace);
-        }
-        let base_addr:PhysiAddr = base_ppn.into();
+        //virtqueue要求物理连续，逐页分配回收过的页帧不能保证
+        let frames = alloc_contiguous_frames(pages, 1).expect("no contiguous frames for dma");
+        let base_addr:PhysiAddr = frames[0].ppn.into();
+        QUEUE_FRAMES.lock().extend(frames);
         base_addr.0
     }
     fn dma_dealloc(paddr: virtio_drivers::PhysAddr, pages: usize) -> i32 {
-        let mut ppn:PhysiNumber = PhysiAddr(paddr).into();
-        for _ in 0..pages {
-            dealloc_frame(ppn.0);
-
-            ppn.0+=1;
-        }
+        //页帧随FramTracker drop回收
+        let base:PhysiNumber = PhysiAddr(paddr).into();
+        QUEUE_FRAMES.lock().retain(|frame| frame.ppn.0 < base.0 || frame.ppn.0 >= base.0 + pages);
         0
     }
     fn phys_to_virt(paddr: virtio_drivers::PhysAddr) -> virtio_drivers::VirtAddr {
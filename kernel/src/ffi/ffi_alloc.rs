@@ -188,9 +188,9 @@ static FREE_COUNT: AtomicUsize = AtomicUsize::new(0);
 /// 
 /// # 内存连续性保证
 /// - **保证单个分配的内存块是连续的**
-/// - 使用 Buddy System 分配器（buddy_system_allocator::LockedHeap）
+/// - 使用 Buddy System 分配器（memory::KernelHeap 包装的 buddy_system_allocator::LockedHeap）
 /// - Buddy System 从连续的堆内存区域中分配连续的内存块
-/// - 分配器管理的堆内存区域本身是连续的（KERNEL_HEADP 到 KERNEL_HEADP + KERNEL_HEAP_SIZE）
+/// - 堆由初始的 KERNEL_HEADP 和之后扩展的连续页帧组成，每个区域本身是连续的，单个分配不会跨区域
 /// 
 /// # 技术细节
 /// - Buddy System 分配器会将堆内存分成不同大小的块（2的幂次）
@@ -25,18 +25,22 @@ mod fs;
 mod ffi;
 
 use alloc::string::String;
+use alloc::sync::Arc;
 use log::{debug, trace, warn};
-use crate::driver::{init_global_block_device, get_global_block_device};
+use crate::driver::{init_global_block_device, get_global_block_device, probe_swap_block_device, init_rtc, install_fs_time_source, fdt_chosen_bootargs};
 use riscv::asm;
 use crate::config::{ebss, sbss};
 use crate::driver::{BLOCK_DEVICE, BlockDevice, test_block_write_read};
-use crate::task::run_first_task;
+use crate::task::{run_first_task, init_sched_policy};
 use crate::time::{ set_next_timeInterupt};
 use crate::trap::{enable_timer_interupt, rather_global_interrupt, set_kernel_trap_handler};
 extern crate alloc;
 use crate::{config::*, logger::kernel_info_debug, memory::allocator_init};
 use crate::memory::init_frame_allocator;
 use crate::memory::MapSet;
+use crate::memory::init_swap;
+use crate::memory::init_asid;
+use crate::memory::init_paging_mode;
 use BlueosFS::*;
 global_asm!(include_str!("entry.asm"));
 global_asm!(include_str!("app.asm"));
@@ -56,11 +60,18 @@ pub fn kernel_init(){
     init_frame_allocator(ekernel as usize,ekernel as usize +MEMORY_SIZE);//物理内存页分配器初始化
 }
 /// the rust entry-point of os
+/// hart_id和dtb_addr由sbi通过a0,a1传入
 #[no_mangle]
-pub fn blue_main() -> ! {//永远不会返回
+pub extern "C" fn blue_main(hart_id:usize,dtb_addr:usize) -> ! {//永远不会返回
     kernel_init(); //bss，日志，分配器初始化
+    debug!("hart:{} dtb:{:#x}",hart_id,dtb_addr);
+    init_rtc(dtb_addr);//设备树探测RTC,必须在地址空间激活前，dtb不在内核映射里
+    let bootargs=fdt_chosen_bootargs(dtb_addr);
+    init_sched_policy(bootargs.as_deref());//bootargs选择调度器，必须在任务管理器初始化前
+    init_paging_mode(bootargs.as_deref());//bootargs选择Sv39/Sv48，必须在内核地址空间创建前
     set_kernel_trap_handler();//初始化陷阱入口，应该在地址空间激活前开启
     KERNEL_SPACE.lock().activate();//激活地址空间
+    init_asid();//探测ASID位数，必须在地址空间激活后
     rather_global_interrupt();//愿意处理全局中断使能
     enable_timer_interupt();//开启全局时间中断使能
     set_next_timeInterupt();//第一次开启时钟中断
@@ -73,7 +84,9 @@ pub fn blue_main() -> ! {//永远不会返回
     // 初始化全局块设备并设置到 BlueosFS
     init_global_block_device();
     let block_device = get_global_block_device().expect("Failed to get global block device");
-    BlueosFS::set_global_block_device(block_device);
+    BlueosFS::set_global_block_device(Arc::new(BlockCache::new(block_device, BLOCK_CACHE_SIZE)));//文件系统经过块缓存访问设备
+    init_swap(probe_swap_block_device());//第二个virtio块设备作为交换分区
+    install_fs_time_source();//RTC作为文件系统时间戳来源
     
     initial_root_filesystem();//初始化根文件系统（包含格式化检查）
     
@@ -83,6 +96,10 @@ pub fn blue_main() -> ! {//永远不会返回
     
     //test_block_device();
     //test_block_write_read();
+    #[cfg(feature = "selftest")]
+    assert!(memory::test_unmap_range(),"memset unmap self test failed");
+    #[cfg(feature = "selftest")]
+    assert!(memory::test_mprotect_roundtrip(),"memset mprotect self test failed");
     run_first_task();
     panic!("Kernel End");
 
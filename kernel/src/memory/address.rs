@@ -5,7 +5,9 @@ use log::{debug, error, trace, warn};
 use riscv::addr;
 use riscv::register::satp;
 use crate::memory::MapArea;
-use crate::{config::*, memory::frame_allocator::*};
+use crate::{config::*, memory::{frame_allocator::*, paging_mode, Asid, MAX_PAGE_LEVELS, flush_tlb_asid, flush_tlb_page}};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use alloc::vec;
 #[derive(Debug,Clone,Copy,PartialEq, Eq, PartialOrd, Ord)]
@@ -20,10 +22,30 @@ pub struct PhysiAddr(pub usize);
 #[repr(C)]
 pub struct PageTableEntry(pub usize);
 
+///RSW的第一位(bit 8) 不合法页表项中标记页面被换出
+pub const PTE_SWAPPED:usize=1<<8;
+
+///satp中ASID字段的位置
+const SATP_ASID_SHIFT:usize=44;
+///最后一级页表的层号 根页表为0 Sv39为2，Sv48为3
+pub fn leaf_level()->usize{
+    paging_mode().levels()-1
+}
+
+///第level层叶子页表项覆盖的页数 最后一层4KiB，往上每层乘512(2MiB/1GiB/512GiB)
+pub fn level_pages(level:usize)->usize{
+    1<<(9*(leaf_level()-level))
+}
+
 #[derive(Clone)]
 pub struct PageTable{
     pub root_ppn:PhysiNumber,
-    entries:Vec<FramTracker>,
+    ///根页表和中间页表的页帧 按ppn索引，空了的中间页表可以单独回收
+    entries:BTreeMap<usize,FramTracker>,
+    ///地址空间标识 临时视图从satp中取出
+    asid:usize,
+    ///持有的ASID，临时视图为None 最后一个引用释放时回收
+    asid_owner:Option<Arc<Asid>>,
 }
 bitflags! {
     pub struct PTEFlags: usize {
@@ -72,13 +94,15 @@ impl From<VirAddr> for VirNumber {
 
 
 impl VirNumber {
- pub fn index(&self) -> [usize; 3] {
+ ///每一级页表的下标 从根页表开始，只有前levels个有效，遍历到leaf_level()为止
+ pub fn index(&self) -> [usize; MAX_PAGE_LEVELS] {
         let  vpn = self.0;
-        let mut idx: [usize; 3] = [0; 3];
-        // SV39: VPN[2] (最高位) -> VPN[1] -> VPN[0] (最低位)
-        idx[0] = (vpn >> 18) & 0x1FF;  
-        idx[1] = (vpn >> 9) & 0x1FF;   
-        idx[2] = vpn & 0x1FF;
+        let levels = paging_mode().levels();
+        let mut idx: [usize; MAX_PAGE_LEVELS] = [0; MAX_PAGE_LEVELS];
+        // VPN[levels-1] (最高位) -> ... -> VPN[0] (最低位)
+        for level in 0..levels {
+            idx[level] = (vpn >> (9 * (levels - 1 - level))) & 0x1FF;
+        }
         idx
     }
 
@@ -111,6 +135,15 @@ impl VirAddr {
 }
 
 
+impl PhysiNumber {
+    ///物理页的字节数组 内核恒等映射了所有物理内存
+    pub fn get_bytes_array(&self)->&'static mut [u8;PAGE_SIZE]{
+        unsafe {
+            &mut *((self.0*PAGE_SIZE) as *mut [u8;PAGE_SIZE])
+        }
+    }
+}
+
 impl PhysiAddr{
     ///向上对齐到页面
     pub fn floor_up(&self)->PhysiNumber{
@@ -143,19 +176,59 @@ impl PageTableEntry {
     pub fn is_valid(&self)->bool{
         self.flags().contains(PTEFlags::V)
     }
+    ///带RWX的合法页表项是叶子，在中间层就是大页
+    pub fn is_leaf(&self)->bool{
+        self.is_valid() && self.flags().intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X)
+    }
     ///设置页表项不合法
     pub fn set_inValid(&mut self){
         self.0=0 //全部置零 
     }
+    ///修改叶子页表项的访问权限 保留ppn和A/D位
+    ///没有RWX的合法页表项会被当成下一级页表，所以这时置为不合法，恢复权限时重新置为合法
+    pub fn set_flags(&mut self,flags:PTEFlags){
+        let mut new_flags=flags | (self.flags() & (PTEFlags::A | PTEFlags::D));
+        if flags.intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X){
+            new_flags|=PTEFlags::V;
+        }else {
+            new_flags.remove(PTEFlags::V);
+        }
+        self.0=(self.0 & !255) | new_flags.bits();
+    }
+    ///访问位 时钟算法用
+    pub fn is_accessed(&self)->bool{
+        self.flags().contains(PTEFlags::A)
+    }
+    ///清除访问位
+    pub fn clear_accessed(&mut self){
+        self.0&=!PTEFlags::A.bits();
+    }
+    ///换出页的页表项 不合法，RSW最低位标记换出，ppn字段保存交换槽号
+    pub fn new_swapped(slot:usize)->Self{
+        PageTableEntry((slot<<10) | PTE_SWAPPED)
+    }
+    ///是否是换出页的页表项
+    pub fn is_swapped(&self)->bool{
+        !self.is_valid() && self.0 & PTE_SWAPPED!=0
+    }
+    ///换出页的交换槽号
+    pub fn swap_slot(&self)->usize{
+        self.0>>10
+    }
 }
 
 impl PageTable {
-    pub fn new()->Self{
-        let mut root_frame=alloc_frame().expect("failed to alloc frame for page table");
-        PageTable{
+    ///只有根页表的新页表 根页表分配不到页帧时返回错误
+    pub fn new()->Result<Self,MemoryError>{
+        let mut root_frame=try_alloc_frame()?;
+        root_frame.ppn.get_bytes_array().fill(0);
+        let asid=Asid::alloc();
+        Ok(PageTable{
             root_ppn:PhysiNumber(root_frame.ppn.0),
-            entries:vec![root_frame], //把根页面挂下面 正确，获取所有权
-        }
+            entries:BTreeMap::from([(root_frame.ppn.0,root_frame)]), //把根页面挂下面 正确，获取所有权
+            asid:asid.id(),
+            asid_owner:Some(Arc::new(asid)),
+        })
     }
 
     ///获取内核地址空间的页表视图 只能由内核调用
@@ -163,16 +236,12 @@ impl PageTable {
         let inner=KERNEL_SPACE.lock();
         let satp =inner.table.satp_token();
         drop(inner);//drop inner 好习惯
-        let table=PageTable{
-            root_ppn:PhysiNumber(satp & ((1usize << 44) -1)),
-            entries:Vec::new()
-        };
-        table
+        PageTable::crate_table_from_satp(satp)
     }
 
     ///根据起始虚拟地址，从satp和vpn和len获取可变的u8数组
-    ///通过临时的 PageTable 视图访问用户页表（只用于地址转换）
-    pub fn get_mut_slice_from_satp(satp:usize,len:usize,startAddr:VirAddr)->Vec<&'static mut [u8]>{
+    ///通过临时的 PageTable 视图访问用户页表（只用于地址转换） 范围内有页面没有映射时返回None
+    pub fn get_mut_slice_from_satp(satp:usize,len:usize,startAddr:VirAddr)->Option<Vec<&'static mut [u8]>>{
         let mut start_addr = startAddr;
         let end_addr = VirAddr(start_addr.0 + len);
         // 创建临时页表视图，只用于地址转换，不管理页表生命周期
@@ -182,8 +251,7 @@ impl PageTable {
         while start_addr < end_addr {
             // 获取当前地址所在的页
             let start_vpn = start_addr.floor_down();
-            let source_slice = table.get_mut_byte(start_vpn.into())
-                .expect("Get VPN to RealAddr failed");
+            let source_slice = table.get_mut_byte(start_vpn.into())?;
             // 计算当前页的结束地址
             let mut page_end_addr: VirAddr = VirNumber(start_vpn.0 + 1).into();
             // 取当前页结束地址和总结束地址的最小值
@@ -203,24 +271,26 @@ impl PageTable {
             start_addr = real_end_addr;
         }
 
-        result_v
+        Some(result_v)
     }
 
     ///从给定的satp中创建临时新页表 临时使用物理ppn为粗略提取
     pub fn crate_table_from_satp(satp:usize)->Self{
         let table=PageTable{
             root_ppn:PhysiNumber(satp & ((1usize << 44) -1)),
-            entries:Vec::new()
+            entries:BTreeMap::new(),
+            asid:(satp>>SATP_ASID_SHIFT) & ASID_MAX,
+            asid_owner:None,
         };
         table
     }
 
  
 
-    ///根据vpn获取该页的可变数组切片,获取从物理页开头的地址切片
+    ///根据vpn获取该页的可变数组切片,获取从物理页开头的地址切片 vpn没有合法映射返回None
     pub fn get_mut_byte(&mut self,vpn:VirNumber)->Option<&'static mut [u8;PAGE_SIZE]>{//防止跨页
 
-        let phydr=self.translate(vpn.into()).expect("Virtual Address translate to Physical Adress Failed!");
+        let phydr=self.translate(vpn.into())?;
         unsafe {
            Some(core::slice::from_raw_parts_mut(phydr.0 as  *mut u8, PAGE_SIZE).try_into().expect("GET_MUT_BYTE FAILED TO TRY TRANSLATE A  POINTER TO STATIC PAGESIZE")) 
         }
@@ -231,9 +301,8 @@ impl PageTable {
     ///专注翻译完整虚拟地址带偏移,结束地址不考虑是否对齐,使用者肯定
     pub fn translate(&mut self,VDDR:VirAddr)->Option<PhysiAddr>{
         
-        match self.find_pte_vpn(VDDR.into()){
-            Some(pte)=>{
-                let ppn=pte.ppn();
+        match self.translate_byvpn(VDDR.into()){
+            Some(ppn)=>{
                 let addr=(ppn.0*PAGE_SIZE)+VDDR.offset();//不考虑是否对齐,使用者肯定
                 Some(PhysiAddr(addr))
             }
@@ -247,9 +316,10 @@ impl PageTable {
     pub fn translate_byvpn(&mut self,vpn:VirNumber)->Option<PhysiNumber>{
         //使用编译器屏障，防止优化内存访问重新排序
         compiler_fence(Ordering::SeqCst);
-        match self.find_pte_vpn(vpn.into()){
-            Some(pte)=>{
-                let ppn=pte.ppn();
+        match self.find_leaf(vpn){
+            Some((pte,level))=>{
+                //大页加上vpn在页内的偏移
+                let ppn=PhysiNumber(pte.ppn().0+(vpn.0 & (level_pages(level)-1)));
 
                 compiler_fence(Ordering::SeqCst);
                 Some(ppn)
@@ -267,8 +337,30 @@ impl PageTable {
         unsafe{core::slice::from_raw_parts_mut(phyaddr.0 as  *mut PageTableEntry, 512).try_into().expect("GET_PET_ARRAY FAILED ,WHEN TRANSLATE A POINTER TO 512 SIZE")}
     }
     
-    ///查找但是不创建新页表项
+    ///查找但是不创建新页表项 遇到大页返回大页的页表项
     pub fn find_pte_vpn(&mut self,VirNum:VirNumber)->Option<&mut PageTableEntry>{
+        self.find_leaf(VirNum).map(|(entry,_)|entry)
+    }
+
+    ///查找vpn所在的合法叶子页表项和它所在的层 不合法的页表项(包括换出页)返回None
+    pub fn find_leaf(&mut self,VirNum:VirNumber)->Option<(&mut PageTableEntry,usize)>{
+        self.find_leaf_entry(VirNum).filter(|(entry,_)|entry.is_valid())
+    }
+
+    ///查找持有物理页帧的叶子页表项 包括没有访问权限(PROT_NONE)而被置为不合法的页表项，不包括换出页
+    ///修改权限和替换页帧时用，恢复权限后页表项仍然指向原来的页帧
+    pub fn find_frame_pte(&mut self,VirNum:VirNumber)->Option<&mut PageTableEntry>{
+        self.find_leaf_entry(VirNum).map(|(entry,_)|entry)
+            .filter(|entry|entry.is_valid() || (!entry.is_swapped() && entry.ppn().0!=0))
+    }
+
+    ///查找换出页的页表项 它的ppn字段是交换槽号，不能当成物理页号
+    pub fn find_swapped_pte(&mut self,VirNum:VirNumber)->Option<&mut PageTableEntry>{
+        self.find_leaf_entry(VirNum).map(|(entry,_)|entry).filter(|entry|entry.is_swapped())
+    }
+
+    ///查找vpn所在的叶子页表项和它所在的层 最后一层的页表项可能不合法
+    fn find_leaf_entry(&mut self,VirNum:VirNumber)->Option<(&mut PageTableEntry,usize)>{
         let mut current_ppn=self.root_ppn.0;
         let mut idx=VirNum.index();
         let mut pte_array=self.get_pte_array(current_ppn);
@@ -278,8 +370,8 @@ impl PageTable {
             compiler_fence(Ordering::SeqCst);
             let entry=&mut pte_array[*index];
                         
-                if id==2{//最后一级
-                    return Some(entry);
+                if id==leaf_level() || entry.is_leaf(){//最后一级或者大页
+                    return Some((entry,id));
                 }
             if !entry.is_valid(){
                 return  None;//不合法
@@ -291,17 +383,34 @@ impl PageTable {
         None
     }
 
-    ///创建vpn到ppn的映射，自动设置pte为合法
-    pub fn map(&mut self,vpn:VirNumber,ppn:PhysiNumber,flags:PTEFlags){//map是需要传入对应vpn和ppn的
-        let pte=self.find_or_create_pte_vpn(vpn).expect("Failed When Map");
+    ///创建vpn到ppn的映射，自动设置pte为合法 中间页表分配失败时返回错误
+    pub fn map(&mut self,vpn:VirNumber,ppn:PhysiNumber,flags:PTEFlags)->Result<(),MemoryError>{//map是需要传入对应vpn和ppn的
+        let pte=self.find_or_create_pte(vpn,leaf_level())?;
 
         if pte.is_valid(){
             //说明之前已经存在对应的映射了,给个警告级别的提示，因为可能有重叠的
             warn!("MAP error！vpn:{}has maped before, pte exist ppn:{}",vpn.0,pte.ppn().0);
-            return;//返回
+            return Ok(());//返回
         }
 
         *pte=PageTableEntry::new(ppn.0,flags|PTEFlags::V); //否则创建映射
+        //实现可能缓存不合法的页表项，变为合法也要刷新
+        self.flush_page(vpn);
+        Ok(())
+    }
+
+    ///在level层创建大页映射 vpn和ppn必须按大页对齐
+    ///这个位置已经有映射或者下一级页表时返回false，由调用者改用小页
+    pub fn map_huge(&mut self,vpn:VirNumber,ppn:PhysiNumber,level:usize,flags:PTEFlags)->Result<bool,MemoryError>{
+        let pages=level_pages(level);
+        assert!(vpn.0%pages==0 && ppn.0%pages==0,"huge page vpn:{:#x} ppn:{:#x} not aligned",vpn.0,ppn.0);
+        let pte=self.find_or_create_pte(vpn,level)?;
+        if pte.0!=0{
+            return Ok(false);
+        }
+        *pte=PageTableEntry::new(ppn.0,flags|PTEFlags::V);
+        self.flush_page(vpn);
+        Ok(true)
     }
 
     ///判断该vpn是否存在合法映射
@@ -318,25 +427,86 @@ impl PageTable {
     }
 
     ///取消映射，应该和地址空间memset的frametracer联系一起，同时释放对应物理帧  能调用这里说明MapArea的Vpn一定存在
+    ///换出页和没有访问权限的页表项虽然不合法也一起清除 清除后整页为空的中间页表被回收
     pub fn unmap(&mut self,vpn:VirNumber){
-        //查找对应pte
-        let pte=self.find_pte_vpn(vpn);
-        match pte{
-            Some(pte)=>{
-                if pte.is_valid(){                   
-                    //second
-                    pte.set_inValid();
-                }else{
+        //记录每一级页表的ppn，回收时从下往上检查
+        let mut tables=[0usize;MAX_PAGE_LEVELS];
+        let leaf=leaf_level();
+        let mut split=false;
+        let mut current_ppn=self.root_ppn.0;
+        let idx=vpn.index();
+        for (level,index) in idx.iter().enumerate(){
+            tables[level]=current_ppn;
+            let entry=&mut self.get_pte_array(current_ppn)[*index];
+            if level==leaf{
+                if entry.0==0{
                     error!("This PTE is Invalid");
+                    return;
                 }
+                entry.set_inValid();
+                break;
             }
-            None=>{
+            if !entry.is_valid(){
                 error!("Unmap failed!No PTE find to unmap");
+                return;
             }
+            if entry.is_leaf(){
+                //只取消大页里面的一页，先拆成下一级页表
+                if self.split_huge(entry, level).is_err(){
+                    error!("Unmap failed!No frame to split huge page");
+                    return;
+                }
+                split=true;
+            }
+            current_ppn=entry.ppn().0;
+        }
+        self.flush_page(vpn);
+        //中间页表为空就回收，根页表不回收
+        let mut reclaimed=split;
+        for level in (1..=leaf).rev(){
+            let table_ppn=tables[level];
+            if !self.entries.contains_key(&table_ppn) || self.get_pte_array(table_ppn).iter().any(|pte|pte.0!=0){
+                break;
+            }
+            self.get_pte_array(tables[level-1])[idx[level-1]].set_inValid();
+            self.entries.remove(&table_ppn);
+            reclaimed=true;
+        }
+        if reclaimed{
+            //非叶子页表项的修改只有不带地址的sfence.vma能刷新
+            flush_tlb_asid(self.asid);
         }
     }
 
-    fn find_or_create_pte_vpn(&mut self,VirNum:VirNumber)->Option<&mut PageTableEntry>{
+    ///把level层的大页拆成下一级页表，权限和访问位不变
+    fn split_huge(&mut self,entry:&mut PageTableEntry,level:usize)->Result<(),MemoryError>{
+        let frame=try_alloc_frame()?;
+        let step=level_pages(level+1);
+        for (index,child) in self.get_pte_array(frame.ppn.0).iter_mut().enumerate(){
+            *child=PageTableEntry::new(entry.ppn().0+index*step, entry.flags());
+        }
+        *entry=PageTableEntry::new(frame.ppn.0, PTEFlags::V);
+        self.entries.insert(frame.ppn.0,frame);
+        Ok(())
+    }
+
+    ///刷新本地址空间一页的TLB项 直接改写页表项之后调用
+    pub fn flush_page(&self,vpn:VirNumber){
+        flush_tlb_page(vpn, self.asid);
+    }
+
+    ///刷新本地址空间的所有TLB项
+    pub fn flush_all(&self){
+        flush_tlb_asid(self.asid);
+    }
+
+    ///中间页表(包括根页表)占用的页帧数
+    pub fn table_frames(&self)->usize{
+        self.entries.len()
+    }
+
+    ///查找或者创建到target层的页表项 中途遇到大页返回大页的页表项
+    fn find_or_create_pte(&mut self,VirNum:VirNumber,target:usize)->Result<&mut PageTableEntry,MemoryError>{
         let mut current_ppn=self.root_ppn.0;
         let mut idx=VirNum.index();
         let mut pte_array=self.get_pte_array(current_ppn);
@@ -344,27 +514,28 @@ impl PageTable {
 
             let entry=&mut pte_array[*index];
                         
-                 if id==2{//最后一级
-                    return Some(entry);
+                 if id==target || entry.is_leaf(){//目标层或者大页
+                    return Ok(entry);
                 }
             if !entry.is_valid(){
-                //不存在页表，开始创建页表
-                let frame=alloc_frame().expect("Frame alloc failed on pte alloc");
+                //不存在页表，开始创建页表 回收的页帧可能有旧数据，先清零
+                let frame=try_alloc_frame()?;
+                frame.ppn.get_bytes_array().fill(0);
                 let ppn =frame.ppn.0;
                 *entry=PageTableEntry::new(ppn, PTEFlags::V);
-                self.entries.push(frame);
+                self.entries.insert(ppn,frame);
             }
             current_ppn=entry.ppn().0;
             pte_array=self.get_pte_array(current_ppn);
         }
-        None
+        unreachable!("target level beyond leaf level")
     }
 
         ///获取适用于satp的token
     pub fn satp_token(&self)->usize{
           //debug!("token: root ppn:{}", self.root_ppn.0);
-          // MODE (8 for Sv39) | ASID (0) | PPN
-          (8 << 60) | (self.root_ppn.0)
+          // MODE (8 for Sv39, 9 for Sv48) | ASID | PPN
+          (paging_mode().satp_mode() << 60) | (self.asid<<SATP_ASID_SHIFT) | (self.root_ppn.0)
     }
 
     
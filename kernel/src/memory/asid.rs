@@ -0,0 +1,112 @@
+///
+/// 地址空间标识(ASID)
+/// 每个页表分配一个ASID写入satp，TLB项按ASID区分，切换地址空间时不用全部刷新
+/// ASID 0 留给分配不到ASID的地址空间共用，satp的ASID为0时陷阱入口和返回会刷新整个TLB
+
+use alloc::vec::Vec;
+use core::arch::asm;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use riscv::register::satp;
+use crate::config::ASID_MAX;
+use crate::memory::VirNumber;
+use crate::sync::UPSafeCell;
+
+pub struct AsidAllocator{
+    ///下一个没有分配过的ASID
+    next:usize,
+    ///可用ASID上界，不能取
+    limit:usize,
+    ///回收的ASID
+    recycle:Vec<usize>,
+}
+
+impl AsidAllocator {
+    fn alloc(&mut self)->Option<usize>{
+        if let Some(asid)=self.recycle.pop(){
+            Some(asid)
+        }else if self.next<self.limit{
+            self.next+=1;
+            Some(self.next-1)
+        }else {
+            None
+        }
+    }
+}
+
+lazy_static!{
+    pub static ref ASID_ALLOCATOR:UPSafeCell<AsidAllocator> = UPSafeCell::new(AsidAllocator { next: 1, limit: ASID_MAX+1, recycle: Vec::new() });
+}
+
+///探测硬件支持的ASID位数 必须在内核地址空间激活后调用
+///往satp的ASID字段写全1再读回，没有实现的位读回为0
+pub fn init_asid(){
+    let origin=satp::read().bits();
+    let probe=unsafe {
+        satp::write(origin | (ASID_MAX<<44));
+        let probe=(satp::read().bits()>>44) & ASID_MAX;
+        satp::write(origin);
+        asm!("sfence.vma");
+        probe
+    };
+    ASID_ALLOCATOR.lock().limit=probe+1;
+    if probe==0{
+        warn!("ASID not supported, flush whole TLB on every switch");
+    }else {
+        debug!("ASID max:{:#x}",probe);
+    }
+}
+
+///硬件能区分的私有ASID 其他ASID(包括0)刷新时只能全部刷新
+pub fn asid_is_private(asid:usize)->bool{
+    asid!=0 && asid<ASID_ALLOCATOR.lock().limit
+}
+
+///刷新一个地址空间的所有TLB项(包括非叶子页表项)
+pub fn flush_tlb_asid(asid:usize){
+    unsafe {
+        if asid_is_private(asid){
+            asm!("sfence.vma zero, {}", in(reg) asid);
+        }else {
+            asm!("sfence.vma");
+        }
+    }
+}
+
+///刷新一个地址空间中一页的叶子TLB项
+pub fn flush_tlb_page(vpn:VirNumber,asid:usize){
+    let addr=vpn.0<<12;
+    unsafe {
+        if asid_is_private(asid){
+            asm!("sfence.vma {}, {}", in(reg) addr, in(reg) asid);
+        }else {
+            asm!("sfence.vma {}, zero", in(reg) addr);
+        }
+    }
+}
+
+///页表持有的ASID drop时回收并刷新它留在TLB里的项
+#[derive(Debug)]
+pub struct Asid(usize);
+
+impl Asid {
+    ///分配ASID 用完时返回共用的ASID 0
+    pub fn alloc()->Self{
+        let asid=ASID_ALLOCATOR.lock().alloc();
+        Asid(asid.unwrap_or(0))
+    }
+
+    pub fn id(&self)->usize{
+        self.0
+    }
+}
+
+impl Drop for Asid {
+    fn drop(&mut self) {
+        if self.0==0{
+            return;
+        }
+        flush_tlb_asid(self.0);
+        ASID_ALLOCATOR.lock().recycle.push(self.0);
+    }
+}
@@ -1,30 +1,38 @@
-use buddy_system_allocator::LockedHeap;
 use log::trace;
-use crate::{config::{KERNEL_HEADP, KERNEL_HEAP_SIZE, MB, PAGE_SIZE}, memory::address::*,sync::UPSafeCell};
+use crate::{config::{MB, PAGE_SIZE}, memory::address::*,sync::UPSafeCell};
 use core::cell::UnsafeCell;
 use lazy_static::lazy_static;
-
-#[global_allocator]
-pub static ALLOCATOR:LockedHeap=LockedHeap::empty(); //内核堆分配器
+use alloc::collections::BTreeSet;
+use alloc::vec;
 use alloc::vec::Vec;
 
-pub fn allocator_init(){
-    unsafe{
-        ALLOCATOR.lock().init(KERNEL_HEADP.as_ptr() as usize,KERNEL_HEAP_SIZE);
-    }
-    trace!("Kernel HeapAlloctor init, can use size:{}MB , mount on KERNEL_HEADP",KERNEL_HEAP_SIZE/MB);
-}
+///伙伴系统最大阶 最大块为2^FRAME_MAX_ORDER个页帧
+const FRAME_MAX_ORDER:usize=20;
 
-///物理页分配器 [start,end)
+///物理页分配器 [start,end) 伙伴系统
+///块按物理页号自然对齐，阶为order的块起始页号是2^order的倍数，DMA需要的对齐由此保证
 pub struct FrameAlloctor{
     ///代表起始物理页号
     start:usize,
-    ///辅助记录初始start
-    origin:usize,
     ///代表结束物理页号，不能取
     end:usize,
-    ///页帧回收池
-    recycle:Vec<usize>
+    ///每一阶的空闲块起始页号
+    free_lists:Vec<BTreeSet<usize>>,
+    ///页帧是否已分配 每一位代表一个页帧，用于检查重复释放
+    used_bitmap:Vec<u64>,
+    ///空闲页帧数
+    free:usize,
+    ///已分配页帧数的峰值
+    peak:usize,
+}
+
+///物理页帧统计 单位为页帧
+#[derive(Debug,Clone,Copy)]
+pub struct FrameStats{
+    pub total:usize,
+    pub free:usize,
+    pub used:usize,
+    pub peak:usize,
 }
 
 trait FrameAllocatorTrait{
@@ -37,34 +45,41 @@ impl FrameAllocatorTrait for FrameAlloctor{
         FrameAlloctor{
             start:0,
             end:0,
-            origin:0,
-            recycle:Vec::new()
+            free_lists:Vec::new(),
+            used_bitmap:Vec::new(),
+            free:0,
+            peak:0,
         }
     }
-    ///分配物理页帧
+    ///分配物理页帧 页帧用完返回None
     fn alloc(&mut self)->Option<FramTracker>{
-        if let Some(ppn)=self.recycle.pop(){
-            trace!("realloc frame:ppn:{}",ppn);
-            Some(FramTracker::new(PhysiNumber(ppn)))
-        }else if self.start<self.end{
-            let ppn=self.start;
-            self.start+=1;
-            trace!("alloc frame:ppn:{}",ppn);
-            Some(FramTracker::new(PhysiNumber(ppn)))
-        }else{
-            panic!("no more frame!");
-        }
+        let ppn=self.alloc_block(0)?;
+        self.mark_used(ppn, 1);
+        self.update_peak();
+        trace!("alloc frame:ppn:{}",ppn);
+        Some(FramTracker::new(PhysiNumber(ppn)))
     }
 
-    ///回收物理页帧
+    ///回收物理页帧 和空闲的伙伴逐级合并
     fn dealloc(&mut self,ppn:usize) {
         //页号合法性检查
-        if ppn<self.origin || ppn>= self.start || ppn>self.end || self.recycle.contains(&ppn){
-            panic!("frame ppn:{} is not valid! orign:{} start:{} end:{} ",ppn,self.origin,self.start,self.end);
+        if ppn<self.start || ppn>=self.end{
+            panic!("frame ppn:{} is not valid! start:{} end:{} ",ppn,self.start,self.end);
+        }
+        let index=ppn-self.start;
+        if self.used_bitmap[index/64] & (1<<(index%64))==0{
+            panic!("frame ppn:{} double free!",ppn);
         }
+        self.used_bitmap[index/64]&=!(1<<(index%64));
+        self.free+=1;
         //trace!("Frame ppn: {} was recycled!",ppn);
-        //回收物理页帧
-        self.recycle.push(ppn);
+        let mut block=ppn;
+        let mut order=0;
+        while order<FRAME_MAX_ORDER && self.free_lists[order].remove(&(block^(1<<order))){
+            block&=!(1<<order);
+            order+=1;
+        }
+        self.free_lists[order].insert(block);
     }
 
 }
@@ -73,13 +88,90 @@ impl FrameAlloctor {
     pub fn init(&mut self,start:usize,end:usize){
         self.start=PhysiAddr(start).floor_up().0;
         self.end=PhysiAddr(end).floor_down().0;
-        self.recycle=Vec::new(); 
-        self.origin=PhysiAddr(start).floor_up().0;
+        self.free_lists=(0..=FRAME_MAX_ORDER).map(|_|BTreeSet::new()).collect();
+        let total=self.end-self.start;
+        self.used_bitmap=vec![0;(total+63)/64];
+        self.free=total;
+        self.peak=0;
+        //把[start,end)切成尽量大的对齐块
+        let mut ppn=self.start;
+        while ppn<self.end{
+            let mut order=(ppn.trailing_zeros() as usize).min(FRAME_MAX_ORDER);
+            while ppn+(1<<order)>self.end{
+                order-=1;
+            }
+            self.free_lists[order].insert(ppn);
+            ppn+=1<<order;
+        }
         trace!("frame allocator init: start ppn:{} end ppn:{} size:{}MB",self.start,self.end,(end-start)/MB);
     }
+
+    ///分配连续n个页帧，起始页号按align个页帧对齐(align为2的幂) 用于DMA
+    ///从足够大的块里切出前n个页帧，剩下的页帧立即还给伙伴系统
+    pub fn alloc_contiguous(&mut self,n:usize,align:usize)->Option<Vec<FramTracker>>{
+        let base=self.alloc_contiguous_raw(n, align)?;
+        Some((base..base+n).map(|ppn|FramTracker::new(PhysiNumber(ppn))).collect())
+    }
+
+    ///分配连续页帧但不构造FramTracker，返回起始页号 页帧永久交给调用者，比如内核堆
+    pub fn alloc_contiguous_raw(&mut self,n:usize,align:usize)->Option<usize>{
+        if n==0 || !align.is_power_of_two(){
+            return None;
+        }
+        let order=n.max(align).next_power_of_two().trailing_zeros() as usize;
+        if order>FRAME_MAX_ORDER{
+            return None;
+        }
+        let base=self.alloc_block(order)?;
+        self.mark_used(base, 1<<order);
+        for ppn in base+n..base+(1<<order){
+            self.dealloc(ppn);
+        }
+        self.update_peak();
+        trace!("alloc contiguous frames:ppn:{}..{}",base,base+n);
+        Some(base)
+    }
+
+    ///取出一个order阶的空闲块 没有时拆分更大的块
+    fn alloc_block(&mut self,order:usize)->Option<usize>{
+        //初始化之前没有空闲链表
+        let found=(order..self.free_lists.len()).find(|&index|!self.free_lists[index].is_empty())?;
+        let block=self.free_lists[found].pop_first()?;
+        //拆分时高半部分放回低一阶
+        for index in (order..found).rev(){
+            self.free_lists[index].insert(block+(1<<index));
+        }
+        Some(block)
+    }
+
+    ///标记[ppn,ppn+count)已分配
+    fn mark_used(&mut self,ppn:usize,count:usize){
+        for index in ppn-self.start..ppn-self.start+count{
+            self.used_bitmap[index/64]|=1<<(index%64);
+        }
+        self.free-=count;
+    }
+
+    fn update_peak(&mut self){
+        self.peak=self.peak.max(self.end-self.start-self.free);
+    }
+
+    pub fn stats(&self)->FrameStats{
+        let total=self.end-self.start;
+        FrameStats { total, free: self.free, used: total-self.free, peak: self.peak }
+    }
 }
 
 
+///内存分配错误
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum MemoryError{
+    ///没有空闲的物理页帧
+    OutOfMemory,
+    ///用户地址没有映射或者没有访问权限
+    BadAddress,
+}
+
 #[derive(Debug,Clone)]
 pub struct FramTracker{
     pub ppn:PhysiNumber
@@ -104,6 +196,26 @@ pub fn alloc_frame()->Option<FramTracker>{
     FRAME_ALLOCATOR.lock().alloc()
 }
 
+///分配物理页帧 失败时返回MemoryError，用于需要向上传递错误的路径
+pub fn try_alloc_frame()->Result<FramTracker,MemoryError>{
+    alloc_frame().ok_or(MemoryError::OutOfMemory)
+}
+
+///分配连续n个页帧 起始页号按align个页帧对齐，align必须是2的幂
+pub fn alloc_contiguous_frames(n:usize,align:usize)->Option<Vec<FramTracker>>{
+    FRAME_ALLOCATOR.lock().alloc_contiguous(n, align)
+}
+
+///剩余可分配的物理页帧数
+pub fn free_frame_count()->usize{
+    FRAME_ALLOCATOR.lock().free
+}
+
+///物理页帧使用统计
+pub fn frame_stats()->FrameStats{
+    FRAME_ALLOCATOR.lock().stats()
+}
+
 pub fn dealloc_frame(ppn:usize){
     FRAME_ALLOCATOR.lock().dealloc(ppn);
 }
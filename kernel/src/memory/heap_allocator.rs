@@ -0,0 +1,130 @@
+///
+/// 内核堆
+/// 初始为静态数组KERNEL_HEADP，分配失败或者空闲不足时从页帧分配器取连续页帧扩展
+/// 扩展出去的页帧不再归还
+
+use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use log::trace;
+use crate::config::{KERNEL_HEADP, KERNEL_HEAP_GROW_PAGES, KERNEL_HEAP_RESERVE, KERNEL_HEAP_SIZE, MB, PAGE_SIZE};
+use crate::memory::FRAME_ALLOCATOR;
+
+pub struct KernelHeap{
+    heap:LockedHeap,
+    ///正在扩展 扩展时页帧分配器自己的分配不能再触发扩展
+    growing:AtomicBool,
+    ///已分配字节数的峰值
+    peak:AtomicUsize,
+    ///扩展后仍然失败的分配次数
+    failed:AtomicUsize,
+    ///从页帧分配器扩展的页数
+    grown_pages:AtomicUsize,
+}
+
+///内核堆统计 单位为字节
+#[derive(Debug,Clone,Copy)]
+pub struct HeapStats{
+    pub total:usize,
+    pub used:usize,
+    pub peak:usize,
+    pub failed:usize,
+    pub grown_pages:usize,
+}
+
+impl KernelHeap {
+    pub const fn new()->Self{
+        KernelHeap {
+            heap: LockedHeap::empty(),
+            growing: AtomicBool::new(false),
+            peak: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            grown_pages: AtomicUsize::new(0),
+        }
+    }
+
+    fn try_alloc(&self,layout:Layout)->*mut u8{
+        let mut heap=self.heap.lock();
+        match heap.alloc(layout) {
+            Ok(ptr)=>{
+                self.peak.fetch_max(heap.stats_alloc_actual(), Ordering::Relaxed);
+                ptr.as_ptr()
+            }
+            Err(_)=>null_mut(),
+        }
+    }
+
+    fn free_bytes(&self)->usize{
+        let heap=self.heap.lock();
+        heap.stats_total_bytes()-heap.stats_alloc_actual()
+    }
+
+    ///至少扩展bytes字节 页数取2的幂并按自身对齐，保证伙伴系统能拼出这么大的块
+    ///页帧分配器正被使用或者正在扩展时放弃
+    fn grow(&self,bytes:usize)->bool{
+        if self.growing.swap(true, Ordering::Acquire){
+            return false;
+        }
+        let pages=((bytes+PAGE_SIZE-1)/PAGE_SIZE).max(KERNEL_HEAP_GROW_PAGES).next_power_of_two();
+        let base=FRAME_ALLOCATOR.try_lock().and_then(|mut frames|frames.alloc_contiguous_raw(pages, pages));
+        if let Some(base)=base{
+            let start=base*PAGE_SIZE;
+            unsafe {
+                self.heap.lock().add_to_heap(start, start+pages*PAGE_SIZE);
+            }
+            self.grown_pages.fetch_add(pages, Ordering::Relaxed);
+            trace!("kernel heap grow {} pages at {:#x}",pages,start);
+        }
+        self.growing.store(false, Ordering::Release);
+        base.is_some()
+    }
+
+    pub fn stats(&self)->HeapStats{
+        let heap=self.heap.lock();
+        HeapStats {
+            total: heap.stats_total_bytes(),
+            used: heap.stats_alloc_actual(),
+            peak: self.peak.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            grown_pages: self.grown_pages.load(Ordering::Relaxed),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut ptr=self.try_alloc(layout);
+        if ptr.is_null(){
+            //按请求的大小扩展后重试
+            if self.grow(layout.size().max(layout.align())){
+                ptr=self.try_alloc(layout);
+            }
+        }else if self.free_bytes()<KERNEL_HEAP_RESERVE{
+            self.grow(KERNEL_HEAP_GROW_PAGES*PAGE_SIZE);
+        }
+        if ptr.is_null(){
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.heap.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+}
+
+#[global_allocator]
+pub static ALLOCATOR:KernelHeap=KernelHeap::new(); //内核堆分配器
+
+pub fn allocator_init(){
+    unsafe{
+        ALLOCATOR.heap.lock().init(KERNEL_HEADP.as_ptr() as usize,KERNEL_HEAP_SIZE);
+    }
+    trace!("Kernel HeapAlloctor init, can use size:{}MB , mount on KERNEL_HEADP",KERNEL_HEAP_SIZE/MB);
+}
+
+///内核堆使用统计
+pub fn heap_stats()->HeapStats{
+    ALLOCATOR.stats()
+}
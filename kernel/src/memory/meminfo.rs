@@ -0,0 +1,56 @@
+///
+/// 内存使用情况 通过meminfo和maps系统调用返回给用户
+/// 结构布局与user_lib保持一致
+
+use crate::memory::{frame_stats, heap_stats, swap_usage};
+
+///整个系统的内存使用 页帧相关的单位为页，堆为字节
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MemInfo{
+    pub frames_total:usize,     //物理页帧总数
+    pub frames_free:usize,      //空闲页帧
+    pub frames_peak:usize,      //已分配页帧的峰值
+    pub heap_total:usize,       //内核堆大小（字节）
+    pub heap_used:usize,        //内核堆已分配（字节）
+    pub heap_peak:usize,        //内核堆已分配的峰值（字节）
+    pub heap_failed:usize,      //内核堆分配失败次数
+    pub page_table_frames:usize,//所有地址空间页表占用的页帧
+    pub swap_used:usize,        //已用交换槽
+    pub swap_total:usize,       //交换槽总数
+}
+
+impl MemInfo {
+    ///page_table_frames由任务管理器统计
+    pub fn collect(page_table_frames:usize)->Self{
+        let frames=frame_stats();
+        let heap=heap_stats();
+        let (swap_used,swap_total)=swap_usage();
+        MemInfo {
+            frames_total: frames.total,
+            frames_free: frames.free,
+            frames_peak: frames.peak,
+            heap_total: heap.total,
+            heap_used: heap.used,
+            heap_peak: heap.peak,
+            heap_failed: heap.failed,
+            page_table_frames,
+            swap_used,
+            swap_total,
+        }
+    }
+}
+
+///一个MapArea的信息
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MapInfo{
+    pub start:usize,        //起始地址
+    pub end:usize,          //结束地址（不包含）
+    pub prot:usize,         //PROT_READ/PROT_WRITE/PROT_EXEC
+    pub area_type:usize,    //AREA_DEFAULT/AREA_MMAP/AREA_ELF/AREA_STACK
+    pub resident:usize,     //常驻页帧数
+    pub swapped:usize,      //换出的页数
+    pub file_backed:usize,  //是否有后备文件
+    pub shared:usize,       //是否MAP_SHARED
+}
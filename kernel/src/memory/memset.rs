@@ -1,13 +1,17 @@
 use alloc::collections::btree_map::BTreeMap;
+use alloc::sync::Arc;
+use core::fmt::{self, Debug, Formatter};
+use BlueosFS::VfsNodeOps;
 use bitflags::bitflags;
 use alloc::vec::Vec;
-use log::{debug, error, trace};
+use alloc::vec;
+use log::{debug, error, trace, warn};
 use riscv::paging::PTE;
-use core::arch::asm;
     use riscv::register::satp;
     use crate::task::file_loader;
 
-use crate::{config::*, memory::{address::*, alloc_frame, frame_allocator::FramTracker}};
+use crate::syscall::EFAULT;
+use crate::{config::*, memory::{address::*, MapInfo, mmap_top, user_stack_top, try_alloc_frame, frame_allocator::{FramTracker, MemoryError}, PageCacheKey, PAGE_CACHE, invalidate_page_cache, SwapSlot}};
 use crate::trap::no_return_start;
 use crate::trap::TrapFunction;
 ///开始和结束，一个范围,自动[start,end] start地址自动向下取整，end也向下取整，因为virnumrange用于代码映射，防止代码缺失, startva/PAGE =num+offset ,从num开始，endva/pagesize=endva+offset由于闭区间所以向下取整,防止多映射
@@ -83,12 +87,8 @@ impl VirNumRange {
         let end =self.1;
         let target_start=vpnRange.0;
         let target_end = vpnRange.1;
-        //闭区间
-        if (target_start>=start && target_start<=end )| (target_end>=start && target_end<=end){
-            return true;
-        }else {
-            return false;
-        }
+        //闭区间 两个区间有交集当且仅当各自的起点都不超过对方的终点
+        target_start<=end && start<=target_end
     }
 }
 
@@ -100,19 +100,53 @@ impl From<MapAreaFlags> for PTEFlags {
         }
     }
 }
+impl MapAreaFlags {
+    ///W^X检查 同时可写可执行的映射只有USER_WX_ALLOWED时允许
+    pub fn wx_permitted(&self)->bool{
+        USER_WX_ALLOWED || !self.contains(MapAreaFlags::W | MapAreaFlags::X)
+    }
+
+    ///转换为PROT_*位 R:1 W:2 X:4
+    pub fn to_prot(&self)->usize{
+        (self.contains(MapAreaFlags::R) as usize) | (self.contains(MapAreaFlags::W) as usize)<<1 | (self.contains(MapAreaFlags::X) as usize)<<2
+    }
+}
+
 #[derive(PartialEq,Clone, Copy,Debug)]
 pub enum MapType {
     Indentical,//直接分配页帧
     Maped,//不直接分配页帧
 }
+///文件映射的后备文件
+#[derive(Clone)]
+pub struct MmapBacking{
+    pub file:Arc<dyn VfsNodeOps>,
+    ///area起始页对应的文件偏移 页对齐
+    pub offset:usize,
+    ///文件数据的结束偏移(不包含) 之后的部分填0，ELF段用它区分文件数据和.bss，mmap为usize::MAX
+    pub file_end:usize,
+    ///MAP_SHARED 修改会写回文件
+    pub shared:bool,
+    ///文件以可写方式打开 MAP_SHARED映射只有这时才能有写权限
+    pub writable:bool,
+}
+
+impl Debug for MmapBacking {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmapBacking").field("offset", &self.offset).field("file_end", &self.file_end).field("shared", &self.shared).field("writable", &self.writable).finish()
+    }
+}
+
 #[derive(Debug,Clone)]
 pub struct MapArea{
     ///虚拟页号范围,闭区间
     range:VirNumRange,
     flags:MapAreaFlags,//访问标志   
-    pub frames:BTreeMap<VirNumber,FramTracker>,//Maparea 持有的物理页
+    pub frames:BTreeMap<VirNumber,Arc<FramTracker>>,//Maparea 持有的物理页 只读ELF页可能和其他任务共享
+    swapped:BTreeMap<VirNumber,Arc<SwapSlot>>,//被换出的匿名页
     map_type:MapType,
-    area_type:MapAreaType
+    area_type:MapAreaType,
+    backing:Option<MmapBacking>,//文件映射，匿名映射为None
 }
 #[derive(Debug,Clone, Copy,PartialEq, Eq)]
 ///用户程序如果MMAP 只能 maped映射
@@ -121,6 +155,22 @@ pub enum MapAreaType {
     DEFAULT,
     ///只是预留虚拟地址空间，没有合法页表项，目前没有对应物理页帧，后期pagefault处理，目的只是检测访问pagefault的地址是否为先前映射的
     MMAP,
+    ///ELF的LOAD段 缺页时从可执行文件读取，超出文件数据的部分(.bss)填0，不能被unmap
+    ELF,
+    ///用户栈 和MMAP一样缺页时分配页帧，另外可以在缺页时向下增长，不能被unmap
+    STACK,
+}
+
+impl MapAreaType {
+    ///maps系统调用中的类型编号
+    pub fn code(&self)->usize{
+        match self {
+            MapAreaType::DEFAULT=>0,
+            MapAreaType::MMAP=>1,
+            MapAreaType::ELF=>2,
+            MapAreaType::STACK=>3,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -128,6 +178,10 @@ pub struct MapSet{
     ///页表
     pub table:PageTable,
     areas:Vec<MapArea>,
+    ///用户堆起始地址(页对齐)，堆area从这里开始
+    heap_start:usize,
+    ///当前program break，[heap_start,brk)为堆
+    brk:usize,
 }
 impl MapArea {
     ///range,闭区间
@@ -136,8 +190,56 @@ impl MapArea {
             range,
             flags,
             frames:BTreeMap::new(),
+            swapped:BTreeMap::new(),
             map_type,
             area_type,
+            backing:None,
+        }
+    }
+
+    ///vpn在后备文件中的偏移
+    fn file_offset(&self,backing:&MmapBacking,vpn:VirNumber)->usize{
+        backing.offset+(vpn.0-self.range.0.0)*PAGE_SIZE
+    }
+
+    ///缺页时填充新分配的页 先清零，文件映射再从文件读取，超出文件或file_end的部分保持为0
+    fn fill_page(&self,vpn:VirNumber,frame:&FramTracker){
+        let page=frame.ppn.get_bytes_array();
+        page.fill(0);
+        if let Some(backing)=&self.backing{
+            let offset=self.file_offset(backing, vpn);
+            if offset>=backing.file_end{
+                return;
+            }
+            let len=PAGE_SIZE.min(backing.file_end-offset);
+            if let Err(err)=backing.file.read_at(offset, &mut page[..len]){
+                error!("mmap read file at offset:{} failed:{:?}",offset,err);
+            }
+        }
+    }
+
+    ///MAP_SHARED映射把已经分配的页写回文件 不扩展文件
+    pub fn sync_shared(&self,range:VirNumRange){
+        let backing=match &self.backing {
+            Some(backing) if backing.shared=>backing,
+            _=>return,
+        };
+        let file_size=backing.file.get_attribute().size;
+        let mut written=false;
+        for (vpn,frame) in self.frames.range(range.0..=range.1){
+            let offset=self.file_offset(backing, *vpn);
+            if offset>=file_size{
+                break;
+            }
+            let len=PAGE_SIZE.min(file_size-offset);
+            if let Err(err)=backing.file.write_at(offset, &frame.ppn.get_bytes_array()[..len]){
+                error!("mmap write back at offset:{} failed:{:?}",offset,err);
+            }
+            written=true;
+        }
+        //文件内容变了，页缓存里这个文件的页不能再给新任务用
+        if written{
+            invalidate_page_cache(&backing.file);
         }
     }
 
@@ -151,53 +253,244 @@ impl MapArea {
     }
     
 
-    pub fn map_one(&mut self,vpn:VirNumber,page_table:&mut PageTable){//带自动分配物理页帧的
+    ///页帧或者页表分配失败时返回错误，已经分配的页帧随之释放
+    pub fn map_one(&mut self,vpn:VirNumber,page_table:&mut PageTable)->Result<(),MemoryError>{//带自动分配物理页帧的
         //可能是恒等和普通映射
-        let ppn:PhysiNumber;
-        if page_table.is_maped(vpn){return;}//如果映射过了就跳过,防止多个一个vpn对应多个ppn，但是只有最后的ppn有效
+        if page_table.is_maped(vpn){return Ok(());}//如果映射过了就跳过,防止多个一个vpn对应多个ppn，但是只有最后的ppn有效
         match self.map_type{
             MapType::Indentical=>{
                // trace!("Identical map");
-                ppn =PhysiNumber(vpn.0) //内核特权高大上，恒等映射 内核映射所有物理帧，但是不能占用和分配对应Framtracer，需要构建一个特殊页表
+                page_table.map(vpn, PhysiNumber(vpn.0), self.flags.into())?; //内核特权高大上，恒等映射 内核映射所有物理帧，但是不能占用和分配对应Framtracer，需要构建一个特殊页表
             }
             MapType::Maped=>{
-               let frame= alloc_frame().expect("Memory Alloc Failed By map_one");
-                ppn=frame.ppn;
-                self.frames.insert(vpn,frame ); //管理最终pte对应的frametracer，分工明确 巧妙！！！！
-                trace!("map vpn:{}->ppn:{}",vpn.0,ppn.0)
+               let frame= try_alloc_frame()?;
+                page_table.map(vpn, frame.ppn, self.flags.into())?;
+                trace!("map vpn:{}->ppn:{}",vpn.0,frame.ppn.0);
+                self.frames.insert(vpn,Arc::new(frame)); //管理最终pte对应的frametracer，分工明确 巧妙！！！！
             }
         };
-        page_table.map(vpn, ppn, self.flags.into());
         //debug!("Map Aread map vpn:{} -> ppn:{}",vpn.0,ppn.0);
+        Ok(())
+    }
+
+    ///只读ELF页在页缓存中的索引 整页都是文件数据时才能共享，含.bss的页每个任务私有
+    fn cache_key(&self,vpn:VirNumber)->Option<PageCacheKey>{
+        if !self.areatype_is_this(MapAreaType::ELF) || self.flags.contains(MapAreaFlags::W){
+            return None;
+        }
+        let backing=self.backing.as_ref()?;
+        let offset=self.file_offset(backing, vpn);
+        if offset+PAGE_SIZE>backing.file_end{
+            return None;
+        }
+        Some((backing.file.get_inode_id()?, offset/PAGE_SIZE))
+    }
+
+    ///缺页时映射一页 只读ELF页从页缓存共享，其他页分配新页帧，懒分配的area填充页面内容
+    pub fn map_fault_page(&mut self,vpn:VirNumber,page_table:&mut PageTable)->Result<(),MemoryError>{
+        let key=match self.cache_key(vpn) {
+            Some(key)=>key,
+            None=>{
+                self.map_one(vpn, page_table)?;
+                if !self.areatype_is_this(MapAreaType::DEFAULT){
+                    let frame=self.frames.get(&vpn).expect("Fill page without frame").clone();
+                    self.fill_page(vpn, &frame);
+                }
+                return Ok(());
+            }
+        };
+        let cached=PAGE_CACHE.lock().get(key);
+        let frame=match cached {
+            Some(frame)=>frame,
+            None=>{
+                let frame=Arc::new(try_alloc_frame()?);
+                self.fill_page(vpn, &frame);
+                PAGE_CACHE.lock().insert(key, &frame);
+                frame
+            }
+        };
+        page_table.map(vpn, frame.ppn, self.flags.into())?;
+        self.frames.insert(vpn, frame);
+        Ok(())
     }
 
     ///映射分割和挂载MapArea所有段,闭区间全部映射
-    pub fn map_all(&mut self,page_table:&mut PageTable){
+    ///恒等映射在对齐允许时使用1GiB/2MiB大页
+    pub fn map_all(&mut self,page_table:&mut PageTable)->Result<(),MemoryError>{
         let start=self.range.0;
         let end=self.range.1;
         let mut current=start;
         while current.0<=end.0 {
-            self.map_one(current, page_table);
+            if let MapType::Indentical=self.map_type{
+                let remaining=end.0-current.0+1;
+                let mut mapped=0;
+                for level in 0..leaf_level(){
+                    let pages=level_pages(level);
+                    if current.0%pages==0 && remaining>=pages && page_table.map_huge(current, PhysiNumber(current.0), level, self.flags.into())?{
+                        mapped=pages;
+                        break;
+                    }
+                }
+                if mapped>0{
+                    current.0+=mapped;
+                    continue;
+                }
+            }
+            self.map_one(current, page_table)?;
             current.0+=1;
         }
+        Ok(())
 
     }
 
-    ///通过虚拟页号释放一个页帧
+    ///通过虚拟页号释放一个页帧 换出的页释放交换槽
     pub fn unmap_one(&mut self,table:&mut PageTable,vpn:VirNumber){
         if self.frames.contains_key(&vpn){
             self.frames.remove(&vpn.clone()).expect("Remove a exist vpn failed!!");//回收页帧
             table.unmap(vpn);
+        }else if self.swapped.remove(&vpn).is_some(){
+            table.unmap(vpn);
         }else{
             error!("MapArea try Unmap vpn:{} but not find vpn in this area",vpn.0);
         }
     }
 
-    ///释放maparea所有页帧
-    pub fn unmap_all(){
+    ///释放maparea所有页帧和交换槽并非法对应页表项
+    pub fn unmap_all(&mut self,table:&mut PageTable){
+        for vpn in self.frames.keys().chain(self.swapped.keys()){
+            table.unmap(*vpn);
+        }
+        self.frames.clear();
+        self.swapped.clear();
+    }
+
+    ///收缩area到new_end(闭区间)，释放new_end之后已经分配的页帧 懒分配的页面可能没有页帧
+    pub fn shrink_to(&mut self,new_end:VirNumber,table:&mut PageTable){
+        let release_range=VirNumRange(VirNumber(new_end.0+1), self.range.1);
+        for vpn in release_range{
+            if self.frames.contains_key(&vpn) || self.swapped.contains_key(&vpn){
+                self.unmap_one(table, vpn);
+            }
+        }
+        self.range.1=new_end;
+    }
+
+
+    ///在at处切分area 自身保留[start,at-1]，返回[at,end]，页帧和文件偏移随之划分 要求start<at<=end
+    pub fn split_off(&mut self,at:VirNumber)->MapArea{
+        let frames=self.frames.split_off(&at);
+        let swapped=self.swapped.split_off(&at);
+        let backing=self.backing.as_ref().map(|backing|MmapBacking {
+            file: backing.file.clone(),
+            offset: self.file_offset(backing, at),
+            file_end: backing.file_end,
+            shared: backing.shared,
+            writable: backing.writable,
+        });
+        let upper=MapArea{
+            range:VirNumRange(at, self.range.1),
+            flags:self.flags,
+            frames,
+            swapped,
+            map_type:self.map_type,
+            area_type:self.area_type,
+            backing,
+        };
+        self.range.1=VirNumber(at.0-1);
+        upper
+    }
+
+    ///修改area访问权限，并改写已经分配页帧的页表项 变为可写时先把共享的页帧换成私有副本
+    pub fn set_flags(&mut self,flags:MapAreaFlags,table:&mut PageTable)->Result<(),MemoryError>{
+        if flags.contains(MapAreaFlags::W){
+            self.privatize_frames(table)?;
+        }
+        self.flags=flags;
+        for vpn in self.frames.keys(){
+            if let Some(pte)=table.find_frame_pte(*vpn){
+                pte.set_flags(flags.into());
+            }
+        }
+        Ok(())
+    }
+
+    ///匿名页 没有后备文件，只能换出到交换分区
+    fn is_anonymous(&self)->bool{
+        self.backing.is_none() && (self.areatype_is_this(MapAreaType::MMAP) || self.areatype_is_this(MapAreaType::STACK))
+    }
+
+    ///时钟算法 从from开始找一个可以换出的页 访问位为1的页清除访问位后跳过
+    ///只考虑本area独占的匿名页
+    fn clock_scan(&self,from:VirNumber,table:&mut PageTable)->Option<VirNumber>{
+        if !self.is_anonymous(){
+            return None;
+        }
+        for (vpn,frame) in self.frames.range(from..){
+            if Arc::strong_count(frame)!=1{
+                continue;
+            }
+            let pte=match table.find_pte_vpn(*vpn) {
+                Some(pte) if pte.is_valid()=>pte,
+                _=>continue,
+            };
+            if pte.is_accessed(){
+                pte.clear_accessed();
+                //TLB里的项还带着访问位的话硬件不会再置位
+                table.flush_page(*vpn);
+            }else {
+                return Some(*vpn);
+            }
+        }
+        None
+    }
+
+    ///把一页写入交换槽，释放页帧，页表项改为换出标记
+    fn swap_out(&mut self,vpn:VirNumber,slot:SwapSlot,table:&mut PageTable){
+        let frame=self.frames.remove(&vpn).expect("swap out page without frame");
+        slot.write(frame.ppn.get_bytes_array());
+        if let Some(pte)=table.find_pte_vpn(vpn){
+            *pte=PageTableEntry::new_swapped(slot.id());
+        }
+        table.flush_page(vpn);
+        trace!("swap out vpn:{:#x} -> slot:{}",vpn.0,slot.id());
+        self.swapped.insert(vpn, Arc::new(slot));
+    }
 
+    ///缺页时把换出的页读回新分配的页帧 交换槽随之释放 vpn没有被换出时返回false
+    ///页表项记录的槽号和area记录的不一致说明换出状态已经损坏，直接panic
+    fn swap_in(&mut self,vpn:VirNumber,table:&mut PageTable)->Result<bool,MemoryError>{
+        let slot=match self.swapped.get(&vpn) {
+            Some(slot)=>slot,
+            None=>return Ok(false),
+        };
+        if table.find_swapped_pte(vpn).map_or(true,|pte|pte.swap_slot()!=slot.id()){
+            panic!("swap in vpn:{:#x} pte does not match slot:{}",vpn.0,slot.id());
+        }
+        let frame=try_alloc_frame()?;
+        slot.read(frame.ppn.get_bytes_array());
+        trace!("swap in vpn:{:#x} <- slot:{}",vpn.0,slot.id());
+        table.map(vpn, frame.ppn, self.flags.into())?;
+        self.frames.insert(vpn, Arc::new(frame));
+        self.swapped.remove(&vpn);
+        Ok(true)
     }
 
+    ///把和其他任务或页缓存共享的页帧换成私有副本
+    fn privatize_frames(&mut self,table:&mut PageTable)->Result<(),MemoryError>{
+        for (vpn,frame) in self.frames.iter_mut(){
+            if Arc::strong_count(frame)==1 && Arc::weak_count(frame)==0{
+                continue;
+            }
+            let private=try_alloc_frame()?;
+            private.ppn.get_bytes_array().copy_from_slice(frame.ppn.get_bytes_array());
+            if let Some(pte)=table.find_frame_pte(*vpn){
+                *pte=PageTableEntry::new(private.ppn.0, pte.flags());
+            }
+            table.flush_page(*vpn);
+            *frame=Arc::new(private);
+        }
+        Ok(())
+    }
 
     ///复制MAPED映射的数据到物理页帧,maped方式才调用它(不包含判断)  必须按照elf格式的顺序复制,传入的data需要自行截断，有栈等映射不需要复制数据
     pub fn copy_data(&mut self,data:Option<&[u8]>,table:&mut PageTable){
@@ -219,6 +512,13 @@ impl MapArea {
     
     
 }
+
+///[start,start+size)覆盖的页 size为0或者范围越过地址空间末尾时返回None
+fn page_range(start:VirAddr,size:usize)->Option<VirNumRange>{
+    let last=start.0.checked_add(size.checked_sub(1)?)?;
+    Some(VirNumRange(start.floor_down(), VirAddr(last).floor_down()))
+}
+
 impl MapSet {
 
     ///获取当前memset的table临时借用
@@ -228,88 +528,366 @@ impl MapSet {
 
 
     ///查找这个vpn对应的area 给这个vpn的maparea分配物理帧，添加合法页表映射 前提是检查过确实有area包含vpn
-    pub fn findarea_allocFrame_and_setPte(&mut self,vpn:VirNumber){
+    ///area没有任何访问权限(PROT_NONE)时返回false，调用者应该杀掉任务 物理内存耗尽时返回错误
+    pub fn findarea_allocFrame_and_setPte(&mut self,vpn:VirNumber)->Result<bool,MemoryError>{
         let index = self.areas.iter().position(|area|{
             area.range.is_contain_thisvpn(vpn)
         }).expect("Logim ");
         let area=&mut self.areas[index];
         debug!("Find Map Area! vpn:{} ",vpn.0);
-        area.map_one(vpn, &mut self.table);//mmap类型的area也是maped不可能存在恒等映射的用户程序
+        //没有RWX的pte会被当成下一级页表，不能映射
+        if !area.flags.intersects(MapAreaFlags::R | MapAreaFlags::W | MapAreaFlags::X){
+            return Ok(false);
+        }
+        if !area.swap_in(vpn, &mut self.table)?{
+            area.map_fault_page(vpn, &mut self.table)?;//mmap类型的area也是maped不可能存在恒等映射的用户程序
+        }
+        Ok(true)
     }
 
+    ///所有area的信息 按area的起始地址排序
+    pub fn area_infos(&self)->Vec<MapInfo>{
+        let mut infos:Vec<MapInfo>=self.areas.iter().map(|area|MapInfo {
+            start: area.range.0.0*PAGE_SIZE,
+            end: (area.range.1.0+1)*PAGE_SIZE,
+            prot: area.flags.to_prot(),
+            area_type: area.area_type.code(),
+            resident: area.frames.len(),
+            swapped: area.swapped.len(),
+            file_backed: area.backing.is_some() as usize,
+            shared: area.backing.as_ref().map_or(false, |backing|backing.shared) as usize,
+        }).collect();
+        infos.sort_by_key(|info|info.start);
+        infos
+    }
+
+    ///常驻内存的页帧数 OOM时用来挑选任务
+    pub fn resident_frames(&self)->usize{
+        self.areas.iter().map(|area|area.frames.len()).sum()
+    }
 
-    ///mmap系统调用，创建一个有vpnrange的maparea，没有实际映射条目和物理页帧的maparea 
-    ///startVAR mmap起始地址 size:映射长度(会被裁剪，小于一个页映射一个页,不满一个页补全一个页) 返回-1代表失败 0代表成功 
-    pub fn mmap(&mut self,startVAR:VirAddr,size:usize)->isize{
-        let start_vpn:VirNumber=startVAR.floor_down();
-        let end_vpn:VirNumber=VirAddr(startVAR.0+size-1).floor_down();//就是flourdown
-        let range:VirNumRange=VirNumRange(start_vpn, end_vpn);
-        //映射地址合法性检查（从开始到结束的区间是否和当前有交集）
-        //1.受否已经存在对应vpn项
-        if self.AallArea_Iscontain_thisVpn_plus(range){
+    ///时钟算法 在本地址空间从hand开始按地址顺序找一个可以换出的匿名页
+    pub fn clock_select(&mut self,hand:VirNumber)->Option<VirNumber>{
+        let mut areas:Vec<&MapArea>=self.areas.iter().filter(|area|area.range.1>=hand).collect();
+        areas.sort_by_key(|area|area.range.0.0);
+        let table=&mut self.table;
+        areas.into_iter().find_map(|area|area.clock_scan(hand.max(area.range.0), table))
+    }
+
+    ///换出vpn对应的页 交换分区满了返回false
+    pub fn swap_out(&mut self,vpn:VirNumber)->bool{
+        let slot=match SwapSlot::alloc() {
+            Some(slot)=>slot,
+            None=>return false,
+        };
+        let area=self.areas.iter_mut().find(|area|area.range.is_contain_thisvpn(vpn)).expect("swap out vpn without area");
+        area.swap_out(vpn, slot, &mut self.table);
+        true
+    }
+
+    ///用户栈增长范围内最低的虚拟页号
+    fn stack_limit_vpn()->VirNumber{
+        VirAddr(user_stack_top()-USER_STACK_LIMIT).floor_down()
+    }
+
+    ///vpn是否是用户栈下方的guard page 访问它说明栈溢出
+    pub fn is_stack_guard(&self,vpn:VirNumber)->bool{
+        vpn.0+1==Self::stack_limit_vpn().0
+    }
+
+    ///缺页地址在栈area之下、栈大小上限之内时向下扩展栈area 返回是否扩展
+    ///只调整范围，页帧仍然在缺页时逐页分配
+    pub fn grow_stack(&mut self,vpn:VirNumber)->bool{
+        let stack=self.areas.iter_mut()
+            .filter(|area|area.areatype_is_this(MapAreaType::STACK))
+            .min_by_key(|area|area.range.0.0);
+        match stack {
+            Some(area) if vpn>=Self::stack_limit_vpn() && vpn<area.range.0=>{
+                debug!("user stack grow to vpn:{:#x}",vpn.0);
+                area.range.0=vpn;
+                true
+            }
+            _=>false,
+        }
+    }
+
+    ///内核访问用户内存前处理范围内的缺页 懒分配的页面没有页表项，内核直接翻译会失败
+    ///access为内核要做的访问(读R/写W) 内核通过物理地址访问不经过用户页表的权限检查，必须在这里检查
+    ///范围内有页面没有area或者area没有U和access权限时返回BadAddress 物理内存耗尽时返回OutOfMemory
+    pub fn prefault_range(&mut self,start:VirAddr,len:usize,access:MapAreaFlags)->Result<(),MemoryError>{
+        if len==0{
+            return Ok(());
+        }
+        let end=start.0.checked_add(len-1).ok_or(MemoryError::BadAddress)?;
+        let range=VirNumRange(start.floor_down(), VirAddr(end).floor_down());
+        for vpn in range{
+            self.grow_stack(vpn);
+            //只读ELF页和其他任务共享页帧，写进去会破坏所有任务的程序
+            match self.areas.iter().find(|area|area.range.is_contain_thisvpn(vpn)) {
+                Some(area) if area.flags.contains(access | MapAreaFlags::U)=>{}
+                _=>return Err(MemoryError::BadAddress),
+            }
+            if self.table.is_maped(vpn){
+                continue;
+            }
+            //DEFAULT段创建时就映射好了，没有映射说明地址不对
+            if !self.AllArea_NoDefaultType(VirNumRange(vpn, vpn)) || !self.findarea_allocFrame_and_setPte(vpn)?{
+                return Err(MemoryError::BadAddress);
+            }
+        }
+        Ok(())
+    }
+
+    ///从start开始找一段pages个页面的空闲虚拟地址 不超过mmap_top()
+    fn find_free_range(&self,start:VirNumber,pages:usize)->Option<VirNumRange>{
+        let top=VirAddr(mmap_top()).floor_down();
+        let mut candidate=start;
+        while candidate.0+pages<=top.0{
+            let range=VirNumRange(candidate, VirNumber(candidate.0+pages-1));
+            //和已有area冲突时跳到冲突area之后继续找
+            match self.areas.iter().filter(|area|area.range.is_contain_thisvpnRange(range)).map(|area|area.range.1).max(){
+                Some(end)=>candidate=VirNumber(end.0+1),
+                None=>return Some(range),
+            }
+        }
+        None
+    }
+
+
+    ///brk系统调用 new_brk为0时返回当前brk
+    ///堆area是MMAP类型，只调整范围，页帧在pagefault时分配，收缩时释放
+    ///成功返回新的brk，失败返回原来的brk
+    pub fn brk(&mut self,new_brk:usize)->usize{
+        let old_brk=self.brk;
+        if new_brk==0 || new_brk==old_brk{
+            return old_brk;
+        }
+        if new_brk<self.heap_start || new_brk>mmap_top(){
+            return old_brk;
+        }
+        let heap_start_vpn=VirAddr(self.heap_start).floor_down();
+        //堆的最后一页(闭区间)，堆为空时为None
+        let last_vpn=|brk:usize|{
+            if brk>self.heap_start {Some(VirAddr(brk-1).floor_down())} else {None}
+        };
+        let old_last=last_vpn(old_brk);
+        let new_last=last_vpn(new_brk);
+        let heap_index=self.areas.iter().position(|area|area.range.0==heap_start_vpn);
+        if new_brk>old_brk{
+            //扩展 new_last一定存在，新增的页面不能和其他area重叠
+            let new_last=new_last.expect("brk grow without page");
+            let grow_start=old_last.map_or(heap_start_vpn,|old_last|VirNumber(old_last.0+1));
+            if new_last>=grow_start{
+                if self.AallArea_Iscontain_thisVpn_plus(VirNumRange(grow_start, new_last)){
+                    return old_brk;
+                }
+                match heap_index {
+                    Some(index)=>self.areas[index].range.1=new_last,
+                    None=>{
+                        //MMAP类型的area不分配页帧，不会失败
+                        if self.add_area(VirNumRange(heap_start_vpn, new_last),
+                            MapType::Maped,
+                            MapAreaFlags::R | MapAreaFlags::W | MapAreaFlags::U,
+                            None,
+                            MapAreaType::MMAP).is_err(){
+                            return old_brk;
+                        }
+                    }
+                }
+            }
+        }else if let Some(index)=heap_index{
+            //收缩 堆变空时移除堆area
+            match new_last {
+                Some(new_last)=>self.areas[index].shrink_to(new_last, &mut self.table),
+                None=>{
+                    let mut area=self.areas.remove(index);
+                    area.shrink_to(VirNumber(heap_start_vpn.0-1), &mut self.table);
+                }
+            }
+        }
+        debug!("brk:{:#x}->{:#x}",old_brk,new_brk);
+        self.brk=new_brk;
+        new_brk
+    }
+
+    ///mmap系统调用，创建一个有vpnrange的maparea，没有实际映射条目和物理页帧的maparea 页帧在pagefault时分配并填充
+    ///hint:建议地址 fixed为true时必须使用hint(页对齐且不能和已有映射重叠)，否则hint不可用时由内核选择地址
+    ///len:映射长度(不满一个页补全一个页) flags:area访问标志 backing:文件映射的后备文件
+    ///返回映射起始地址，-1代表失败
+    pub fn mmap(&mut self,hint:usize,len:usize,flags:MapAreaFlags,fixed:bool,backing:Option<MmapBacking>)->isize{
+        //长度超过mmap区域的上界一定放不下，先排除，之后的页数计算不会溢出
+        if len==0 || len>mmap_top() || !flags.wx_permitted(){
             return -1;
         }
-        //记得加用户U权限
-        let mapflags=MapAreaFlags::R | MapAreaFlags::W | MapAreaFlags::X | MapAreaFlags::U;//默认可读写可执行（兼容后面的换入换出）
+        let pages=(len+PAGE_SIZE-1)/PAGE_SIZE;
+        let hint_vpn=VirAddr(hint).floor_down();
+        //hint可用:范围不溢出、在mmap区域内并且不和已有映射重叠
+        let hint_range=hint_vpn.0.checked_add(pages-1)
+            .map(|end|VirNumRange(hint_vpn, VirNumber(end)))
+            .filter(|range|hint!=0 && range.1.0<VirAddr(mmap_top()).floor_down().0 && !self.AallArea_Iscontain_thisVpn_plus(*range));
+        let range=if fixed{
+            match hint_range {
+                Some(range) if hint%PAGE_SIZE==0=>range,
+                _=>return -1,
+            }
+        }else {
+            match hint_range.or_else(||self.find_free_range(VirAddr(MMAP_BASE).floor_down(), pages)) {
+                Some(range)=>range,
+                None=>return -1,
+            }
+        };
         //没有对应vpn，在该个mapset就没有对应的映射。之前存在并且unmap时应该处理或销毁其对应页表项，所有这里合法，支持!
-        self.add_area(range, MapType::Maped, mapflags, None, MapAreaType::MMAP);
+        if self.add_area(range, MapType::Maped, flags | MapAreaFlags::U, None, MapAreaType::MMAP).is_err(){
+            return -1;
+        }
+        self.areas.last_mut().expect("mmap area lost").backing=backing;
+        debug!("mmap [{:#x},{:#x}) flags:{:?}",range.0.0*PAGE_SIZE,(range.1.0+1)*PAGE_SIZE,flags);
+        (range.0.0*PAGE_SIZE) as isize
+    }
+
+    ///msync系统调用 把范围内MAP_SHARED映射的已分配页写回文件 范围内没有映射返回-EFAULT
+    pub fn msync(&self,startVAR:VirAddr,size:usize)->isize{
+        if size==0{
+            return 0;
+        }
+        let range=match page_range(startVAR, size) {
+            Some(range)=>range,
+            None=>return -EFAULT,
+        };
+        if !self.AallArea_Iscontain_thisVpn_plus(range){
+            return -EFAULT;
+        }
+        self.areas.iter()
+            .filter(|area|area.range.is_contain_thisvpnRange(range))
+            .for_each(|area|area.sync_shared(range));
+        0
+    }
+
+    ///写回所有MAP_SHARED映射 任务退出时调用
+    pub fn sync_all_shared(&self){
+        self.areas.iter().for_each(|area|area.sync_shared(area.range));
+    }
+
+    ///如果有area跨过vpn(vpn不是起始页)，在vpn处切成两个area
+    fn split_area_at(&mut self,vpn:VirNumber){
+        if let Some(index)=self.areas.iter().position(|area|area.range.0<vpn && area.range.is_contain_thisvpn(vpn)){
+            let upper=self.areas[index].split_off(vpn);
+            self.areas.insert(index+1, upper);
+        }
+    }
+
+    ///范围内每一页都属于某个用户area
+    fn is_range_user_mapped(&self,range:VirNumRange)->bool{
+        let mut areas:Vec<&MapArea>=self.areas.iter()
+            .filter(|area|area.range.0<=range.1 && area.range.1>=range.0)
+            .collect();
+        areas.sort_by_key(|area|area.range.0.0);
+        let mut next=range.0;
+        for area in areas{
+            if area.range.0>next || !area.flags.contains(MapAreaFlags::U){
+                return false;
+            }
+            next=VirNumber(next.0.max(area.range.1.0+1));
+            if next>range.1{
+                return true;
+            }
+        }
+        false
+    }
+
+    ///mprotect系统调用 修改[startVAR,startVAR+size)的访问权限
+    ///startVAR必须页对齐，范围必须全部被用户映射覆盖，部分覆盖的area会被切分
+    ///已经映射的页表项直接改写并刷新TLB，范围没有全部被映射返回-EFAULT，其他失败返回-1
+    pub fn mprotect(&mut self,startVAR:VirAddr,size:usize,flags:MapAreaFlags)->isize{
+        if startVAR.0%PAGE_SIZE!=0{
+            return -1;
+        }
+        if size==0{
+            return 0;
+        }
+        if !flags.wx_permitted(){
+            error!("mprotect refuse W+X mapping at {:#x}",startVAR.0);
+            return -1;
+        }
+        let range=match page_range(startVAR, size) {
+            Some(range)=>range,
+            None=>return -EFAULT,
+        };
+        if !self.is_range_user_mapped(range){
+            return -EFAULT;
+        }
+        //和mmap一样，只读打开的文件的共享映射不能加上写权限，否则写回时会写只读文件
+        if flags.contains(MapAreaFlags::W) && self.areas.iter()
+            .filter(|area|area.range.0<=range.1 && area.range.1>=range.0)
+            .any(|area|area.backing.as_ref().map_or(false,|backing|backing.shared && !backing.writable)){
+            error!("mprotect refuse writable shared mapping of read-only file at {:#x}",startVAR.0);
+            return -1;
+        }
+        self.split_area_at(range.0);
+        self.split_area_at(VirNumber(range.1.0+1));
+        let table=&mut self.table;
+        let result=self.areas.iter_mut()
+            .filter(|area|area.range.0>=range.0 && area.range.1<=range.1)
+            .try_for_each(|area|area.set_flags(flags | MapAreaFlags::U, table));
+        self.table.flush_all();
+        if result.is_err(){
+            error!("mprotect out of memory");
+            return -1;
+        }
+        debug!("mprotect [{:#x},{:#x}) flags:{:?}",range.0.0*PAGE_SIZE,(range.1.0+1)*PAGE_SIZE,flags);
         0
     }
 
     ///unmap系统调用,取消映射一个[start,end]范围的虚拟页面，并且设置对应页表项不合法
     /// startVAR mmap起始地址 size:映射长度(会被裁剪，小于一个页取消映射一个页,不满一个页补全一个页) 返回-1代表失败 0代表成功 
+    /// 只和范围部分重叠的area会在范围边界切分，只释放范围内的页帧
     pub fn unmap_range(&mut self,startVAR:VirAddr,size:usize,)->isize{
+        if size==0{
+            return -1;
+        }
         //合法性检查，是否之前有过映射
-        let start_vpn:VirNumber=startVAR.floor_down();
-        let end_vpn:VirNumber=VirAddr(startVAR.0+size-1).floor_down();//就是flourdown
-        let range:VirNumRange=VirNumRange(start_vpn, end_vpn);
+        let range:VirNumRange=match page_range(startVAR, size) {
+            Some(range)=>range,
+            None=>return -1,
+        };
         if !self.AallArea_Iscontain_thisVpn_plus(range){//没有映射不能取消映射
             return -1;
         }
-        //剩下是有映射的了，但是得判断是不是MMAP类型的area，不能取消映射DEFAULTD段
-        //找存在映射的area判断所有是否是default
-        if !self.AllArea_NoDefaultType(range){
+        //剩下是有映射的了，但是得判断是不是MMAP类型的area，不能取消映射DEFAULTD段、ELF段和用户栈
+        if !self.AllArea_IsMmapType(range){
             return -1;
         }
-        debug!("nocontain default type area,and  exits:{:#x} flect previous",startVAR.0);
-        //也没有defalut的area，可以取消映射
-        //1.非法对应页表项 MMAP area有可能没有触发过缺页，就没有对应pte
-        for num in range{
-            match self.table.find_pte_vpn(num){
-                Some(mut pte)=>{
-                debug!("find mmaped pte will set it illegal! vpnnum:{}",num.0);
-                  pte.set_inValid();  
-                }
-                None=>{
-
-                }
-            }
-        }
+        //1.在范围边界切分area，之后和范围有交集的area都完全在范围内
+        self.split_area_at(range.0);
+        self.split_area_at(VirNumber(range.1.0+1));
 
         //2.移除对应area 需要查找所有关联的maparea，因为可能是多次
-        let match_vec= self.pop_contain_range_area(range);
+        let mut match_vec= self.pop_contain_range_area(range);
         debug!("Find {} maparea connect",match_vec.len());
-        //3.判断结果是否为空
-        if match_vec.is_empty(){
-            panic!("Unmap logim error");//是有还是没有，逻辑严重不符
-            return -1;
+
+        //3.MAP_SHARED映射释放前写回文件，然后非法对应页表项并释放页帧 MMAP area有可能没有触发过缺页，就没有对应页帧
+        for area in match_vec.iter_mut(){
+            area.sync_shared(area.range);
+            area.unmap_all(&mut self.table);
         }
+        self.table.flush_all();
         trace!("Unmap Area:{:?}",match_vec);
-
-        //成功 maparea之后会free~~~~，页帧会自动释放
         0
     }
 
 
 
-    ///从elf解析数据创建应用地址空间 Mapset entry user_stack,kernel_sp
+    ///从elf文件创建应用地址空间 Mapset entry user_stack,kernel_sp
     /// appid从0开始，必须手动+1
-    /// elf_data: ELF 文件数据（可以从文件系统读取）
-    pub fn from_elf(old_appid:usize,elf_data:&[u8])->(Self,usize,VirAddr,usize){ 
+    /// elf_file: 文件系统中的ELF文件节点，这里只读取文件头和程序头
+    /// LOAD段注册为ELF类型的area，页面在第一次访问时从文件读取，.bss部分填0
+    /// 页表、陷阱上下文或者内核栈分配不到物理页帧时返回错误
+    pub fn from_elf(old_appid:usize,elf_file:Arc<dyn VfsNodeOps>)->Result<(Self,usize,VirAddr,usize),MemoryError>{ 
         let appid=old_appid+1;//适配之前的栈布局
-        let mut memory_set = Self::new_bare();
+        let mut memory_set = Self::new_bare()?;
+        let elf_head=Self::read_elf_head(&elf_file);
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+        let elf = xmas_elf::ElfFile::new(&elf_head).unwrap();
         let elf_header = elf.header;
         let magic = elf_header.pt1.magic;
         assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
@@ -319,7 +897,7 @@ impl MapSet {
         debug!("ELF entry point: {:#x}, program headers: {}", entry_point, ph_count);
         for i in 0..ph_count {
             let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
+            if ph.get_type().unwrap() == xmas_elf::program::Type::Load && ph.mem_size()>0 {
                 let start_va: VirAddr = VirAddr(ph.virtual_addr() as usize);
                 let end_va: VirAddr = VirAddr((ph.virtual_addr() + ph.mem_size()) as usize);
                 let mut map_perm = MapAreaFlags::U;
@@ -333,42 +911,54 @@ impl MapSet {
                 if ph_flags.is_execute() {
                     map_perm |= MapAreaFlags::X;
                 }
+                if !map_perm.wx_permitted(){
+                    warn!("  [{}] ELF segment is writable and executable",i);
+                }
                 
                 debug!("  [{}] Mapping segment: [{:#x}, {:#x}), perm: {:?}", 
                        i, start_va.0, end_va.0, map_perm);
                 
-                max_end_vpn=end_va.floor_up();
-                memory_set.add_area(VirNumRange::new(start_va, end_va),
+                if end_va.floor_up()>max_end_vpn{
+                    max_end_vpn=end_va.floor_up();
+                }
+                //段的虚拟地址和文件偏移页内偏移相同，area从段所在页开始
+                let file_start=ph.offset() as usize;
+                let backing=MmapBacking {
+                    file: elf_file.clone(),
+                    offset: file_start-start_va.offset(),
+                    file_end: file_start+ph.file_size() as usize,
+                    shared: false,
+                    writable: false,
+                };
+                memory_set.add_area(VirNumRange::new(start_va, VirAddr(end_va.0-1)),
                  MapType::Maped,
                   map_perm,
-                  Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                  MapAreaType::DEFAULT );//应用area默认default
+                  None,
+                  MapAreaType::ELF )?;
+                memory_set.areas.last_mut().expect("elf area lost").backing=Some(backing);
             }
         }
         
         //程序地址空间创建完成，接下来是
         // 映射陷阱
-        memory_set.map_traper();
+        memory_set.map_traper()?;
         //映射上下文
-        memory_set.map_trapContext();
-        //映射普通用户栈
-        let userstack_start_vpn=VirNumber(max_end_vpn.0+1);//留guradpage
-        let userstack_end_vpn=VirNumber(userstack_start_vpn.0+1);
-        let user_sp:VirAddr=VirAddr(userstack_end_vpn.0*PAGE_SIZE + PAGE_SIZE);//因为结尾不包含，属于下一个页面
+        memory_set.map_trapContext()?;
+        //映射用户栈 固定在用户地址空间顶部，缺页时向下增长，下面留guardpage
+        let user_sp:VirAddr=VirAddr(user_stack_top());//因为结尾不包含，属于下一个页面
+        let userstack_end_vpn=VirNumber(user_sp.floor_down().0-1);
+        let userstack_start_vpn=VirNumber(userstack_end_vpn.0+1-USER_STACK_INIT_PAGES);
         debug!("  Mapping user stack: vpn={:#x}, sp={:#x}", userstack_start_vpn.0, user_sp.0);
         memory_set.add_area(VirNumRange(userstack_start_vpn,userstack_end_vpn), 
         MapType::Maped,
          MapAreaFlags::W | MapAreaFlags::R | MapAreaFlags::U,
           None
-        ,MapAreaType::DEFAULT);
-        //映射用户堆
-        let userheap_start_end_vpn = VirNumber(userstack_start_vpn.0+1);//无需guardpage，堆不会向下溢出
-        debug!("  Mapping user heap: vpn={:#x}", userheap_start_end_vpn.0);
-        memory_set.add_area(VirNumRange(userheap_start_end_vpn, userheap_start_end_vpn),
-         MapType::Maped, 
-         MapAreaFlags::R | MapAreaFlags::W | MapAreaFlags::U, 
-         None,
-        MapAreaType::DEFAULT);
+        ,MapAreaType::STACK)?;
+        //用户堆 初始为空，由brk扩展
+        let userheap_start_vpn = VirNumber(max_end_vpn.0+1);//留guardpage
+        debug!("  User heap start: vpn={:#x}", userheap_start_vpn.0);
+        memory_set.heap_start=userheap_start_vpn.0*PAGE_SIZE;
+        memory_set.brk=memory_set.heap_start;
         //映射内核栈
         //debug!("Kernel stack start viadr:{:#x} appid:{}",TRAP_BOTTOM_ADDR-(PAGE_SIZE+PAGE_SIZE)*appid,appid);
         let strat_kernel_vpn =VirAddr(TRAP_BOTTOM_ADDR-(PAGE_SIZE+KERNEL_STACK_SIZE)*appid).strict_into_virnum();//隔了一个guardpage 
@@ -380,40 +970,57 @@ impl MapSet {
             VirNumRange(strat_kernel_vpn, end_kernel_vpn),
              MapType::Maped, MapAreaFlags::R | MapAreaFlags::W, 
              None
-            ,MapAreaType::DEFAULT);
-        (
+            ,MapAreaType::DEFAULT)?;
+        Ok((
             memory_set,
             entry_point as usize,
             user_sp,
             kernel_stack_top
-        )
+        ))
     }
 
 
-    fn new_bare()->Self{
-        MapSet{
-            table:PageTable::new(),
-            areas:Vec::new(),
+    ///读取ELF文件头和程序头表 先读一页，程序头表超出时再读到表尾
+    fn read_elf_head(elf_file:&Arc<dyn VfsNodeOps>)->Vec<u8>{
+        let file_size=elf_file.get_attribute().size;
+        let mut head=vec![0u8;PAGE_SIZE.min(file_size)];
+        elf_file.read_at(0, &mut head).expect("read elf header failed");
+        let header=xmas_elf::header::parse_header(&head).expect("invalid elf header");
+        let ph_end=header.pt2.ph_offset() as usize+header.pt2.ph_count() as usize*header.pt2.ph_entry_size() as usize;
+        if ph_end>head.len(){
+            head.resize(ph_end.min(file_size), 0);
+            elf_file.read_at(0, &mut head).expect("read elf program headers failed");
         }
+        head
+    }
+
+    ///空地址空间 只有根页表，没有任何area 根页表分配不到页帧时返回错误
+    pub fn new_bare()->Result<Self,MemoryError>{
+        Ok(MapSet{
+            table:PageTable::new()?,
+            areas:Vec::new(),
+            heap_start:0,
+            brk:0,
+        })
     }
 
     ///在目前的地址空间页表里面映射陷阱
-    pub fn map_traper(&mut self,){
+    pub fn map_traper(&mut self,)->Result<(),MemoryError>{
         let kernel_trape:usize=straper as usize;//内核陷阱起始物理地址
-        self.table.map(VirAddr(TRAP_BOTTOM_ADDR).into(), PhysiAddr(kernel_trape as usize).into(), PTEFlags::X | PTEFlags::R);
+        self.table.map(VirAddr(TRAP_BOTTOM_ADDR).into(), PhysiAddr(kernel_trape as usize).into(), PTEFlags::X | PTEFlags::R)
 
        
     }
 
     ///映射陷阱上下文
-    pub fn map_trapContext(&mut self){
+    pub fn map_trapContext(&mut self)->Result<(),MemoryError>{
         let trapcontext_addr:VirAddr = VirAddr(TRAP_CONTEXT_ADDR);
         self.add_area(
             VirNumRange(trapcontext_addr.strict_into_virnum(), 
             trapcontext_addr.strict_into_virnum()), 
             MapType::Maped, MapAreaFlags::R | MapAreaFlags::W, 
             None
-            ,MapAreaType::DEFAULT);
+            ,MapAreaType::DEFAULT)
     }
 
     ///目前不可用
@@ -444,50 +1051,63 @@ impl MapSet {
         })
     }
 
-    ///判断这个范围内的area有DEFAULT的映射方式吗
+    ///判断这个范围内的area都不是DEFAULT的映射方式 范围内没有area也返回true
     pub fn AllArea_NoDefaultType(&self,range:VirNumRange)->bool{
         //首先找到哪些area包含range里面的vpn
-        self.areas.iter().any(|area|{
-            area.areatype_is_this(MapAreaType::DEFAULT)
+        !self.areas.iter().any(|area|{
+            area.range.is_contain_thisvpnRange(range) && area.areatype_is_this(MapAreaType::DEFAULT)
+        })
+    }
+
+    ///判断这个范围内的area都是MMAP类型 只有它们可以被unmap
+    pub fn AllArea_IsMmapType(&self,range:VirNumRange)->bool{
+        self.areas.iter().all(|area|{
+            !area.range.is_contain_thisvpnRange(range) || area.areatype_is_this(MapAreaType::MMAP)
         })
     }
 
     ///获取所有包含范围内vpn的maparea的实体move所有权
     pub fn pop_contain_range_area(&mut self,range:VirNumRange)->Vec<MapArea>{
         let mut result:Vec<MapArea>=Vec::new();//存放结果 
-        let index:Vec<usize> = self.areas.iter().enumerate().filter(|(_,area)|{
-            area.range.is_contain_thisvpnRange(range)
-        }).map(|(index,_)|{index}).collect();
-        for inde in index{
-           result.push(self.areas.remove(inde));
+        let mut index=0;
+        while index<self.areas.len(){
+            if self.areas[index].range.is_contain_thisvpnRange(range){
+                result.push(self.areas.remove(index));//移除后后面的area前移，index不变
+            }else {
+                index+=1;
+            }
         }
         result
-        
     }
 
     ///输入range，maptype和flags 自动处理maparea的映射和物理帧挂载以及对应memset的pagetable映射,处理数据的复制映射   但是映射用户栈不需要数据
     /// area_type优先级更高 其次map_type
-    pub fn add_area(&mut self,range:VirNumRange,map_type :MapType,flags:MapAreaFlags,data:Option<&[u8]>,area_type:MapAreaType){
+    /// 物理页帧耗尽时撤销已经建立的映射并返回错误，area不会加入地址空间
+    pub fn add_area(&mut self,range:VirNumRange,map_type :MapType,flags:MapAreaFlags,data:Option<&[u8]>,area_type:MapAreaType)->Result<(),MemoryError>{
         let mut area=MapArea::new(range, flags, map_type,area_type);
         match area_type{
             MapAreaType::DEFAULT=>{
-                area.map_all(&mut self.table);//映射area,处理物理页帧分配逻辑
+                if let Err(err)=area.map_all(&mut self.table){//映射area,处理物理页帧分配逻辑
+                    area.unmap_all(&mut self.table);
+                    return Err(err);
+                }
                 if let MapType::Maped = map_type{//maped方式要复制数据
                     area.copy_data(data, &mut self.table);
                 }
             }
-            MapAreaType::MMAP=>{
+            MapAreaType::MMAP | MapAreaType::STACK | MapAreaType::ELF=>{
                 //啥都不做，mmap目前不用映射和分配物理页帧，留在pagefalut
             }
         }
         self.areas.push(area);
+        Ok(())
     } 
 
     pub fn new_kernel()->Self{
-        let mut mem_set =MapSet::new_bare();
+        let mut mem_set =MapSet::new_bare().expect("Kernel space page table alloc failed");
 
         //映射陷阱
-        mem_set.map_traper();
+        mem_set.map_traper().expect("Kernel space map failed");
 
         //映射硬件段
         let hardware_range = VirNumRange::new(VirAddr(0x0 as usize), VirAddr(0x10010000 as usize));//range封装过
@@ -495,7 +1115,7 @@ impl MapSet {
             MapType::Indentical, 
              MapAreaFlags::R | MapAreaFlags::W  ,
              None
-            ,MapAreaType::DEFAULT);
+            ,MapAreaType::DEFAULT).expect("Kernel space map failed");
 
         //映射代码段
         let text_range = VirNumRange::new(VirAddr(stext as usize), VirAddr(etext as usize));//range封装过
@@ -503,7 +1123,7 @@ impl MapSet {
             MapType::Indentical, 
              MapAreaFlags::R | MapAreaFlags::X  ,
              None
-            ,MapAreaType::DEFAULT);
+            ,MapAreaType::DEFAULT).expect("Kernel space map failed");
 
 
         //映射rodata段
@@ -512,7 +1132,7 @@ impl MapSet {
              MapType::Indentical, 
              MapAreaFlags::R,
              None
-            ,MapAreaType::DEFAULT);
+            ,MapAreaType::DEFAULT).expect("Kernel space map failed");
         //trace!("{} {}\n",rodata_start_vpn.0,rodata_end_vpn.0);
 
     
@@ -522,7 +1142,7 @@ impl MapSet {
              MapType::Indentical,
               MapAreaFlags::R | MapAreaFlags::W,
               None
-            ,MapAreaType::DEFAULT);
+            ,MapAreaType::DEFAULT).expect("Kernel space map failed");
        // trace!("{} {}\n",data_start.0,data_end.0);
 
         //映射bss段
@@ -531,7 +1151,7 @@ impl MapSet {
              MapType::Indentical,
               MapAreaFlags::R | MapAreaFlags::W,
               None,
-            MapAreaType::DEFAULT);
+            MapAreaType::DEFAULT).expect("Kernel space map failed");
        // trace!("{} {}\n",bss_start.0,bss_end.0);
         
         // 映射物理内存(必须手动构造range区间)，phystart需要向上取整,end需要手动-1 range
@@ -541,7 +1161,7 @@ impl MapSet {
         mem_set.add_area(phys_range, MapType::Indentical,
              MapAreaFlags::W | MapAreaFlags::R,
               None,
-            MapAreaType::DEFAULT);
+            MapAreaType::DEFAULT).expect("Kernel space map failed");
        // trace!("{} {}\n",phys_start.0,phys_end.0);
 
         //内核地址空间映射完成
@@ -564,13 +1184,14 @@ impl MapSet {
     }
 
     /// Change page table by writing satp CSR Register.
+    /// 带ASID写入satp，只刷新这个地址空间的TLB项
     pub fn activate(&self) {
          let satps = self.table.satp_token();
         debug!("Active PageTable: SATP = {:#x}", satps);
         unsafe {
             satp::write(satps);
-            asm!("sfence.vma");
-            debug!("Page Witch Successful!!!!!");
         }
+        self.table.flush_all();
+        debug!("Page Witch Successful!!!!!");
     }
 }
\ No newline at end of file
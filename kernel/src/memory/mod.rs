@@ -2,10 +2,26 @@ use log::trace;
 
 
 mod address;
+mod asid;
 mod frame_allocator;
+mod heap_allocator;
 mod memset;
+mod meminfo;
+mod page_cache;
+mod paging;
+mod swap;
+#[cfg(feature = "selftest")]
+mod testmemset;
 
 
 pub use address::*;
+pub use asid::*;
 pub use frame_allocator::*;
+pub use heap_allocator::*;
 pub use memset::*;
+pub use meminfo::*;
+pub use page_cache::*;
+pub use paging::*;
+pub use swap::*;
+#[cfg(feature = "selftest")]
+pub use testmemset::{test_unmap_range, test_mprotect_roundtrip};
@@ -0,0 +1,77 @@
+///
+/// 可执行文件只读页的页缓存
+/// 按(inode,文件页号)索引，运行同一个程序的任务映射同一个物理页帧
+/// 缓存只持有Weak引用，最后一个映射释放时页帧随之回收，失效的条目在查找时和条目数翻倍时清理
+/// 文件被改写、截断或删除时丢弃它的所有缓存页，inode编号被重新使用也不会拿到旧文件的页
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use BlueosFS::VfsNodeOps;
+use lazy_static::lazy_static;
+use log::trace;
+use crate::memory::FramTracker;
+use crate::sync::UPSafeCell;
+
+///(inode编号,文件页号)
+pub type PageCacheKey=(usize,usize);
+
+///条目数至少到这里才清理失效条目
+const PAGE_CACHE_PRUNE_MIN:usize=64;
+
+pub struct PageCache{
+    pages:BTreeMap<PageCacheKey,Weak<FramTracker>>,
+    ///条目数到这里时清理一次失效条目，之后设为剩余条目数的两倍，均摊到每次插入是O(1)
+    prune_at:usize,
+}
+
+impl PageCache {
+    pub fn new()->Self{
+        PageCache { pages: BTreeMap::new(), prune_at: PAGE_CACHE_PRUNE_MIN }
+    }
+
+    ///查找缓存页 页帧已经被回收时移除条目
+    pub fn get(&mut self,key:PageCacheKey)->Option<Arc<FramTracker>>{
+        let frame=self.pages.get(&key)?.upgrade();
+        if frame.is_none(){
+            self.pages.remove(&key);
+        }
+        frame
+    }
+
+    ///缓存新读取的页 条目数翻倍时清理已经没有映射的条目
+    pub fn insert(&mut self,key:PageCacheKey,frame:&Arc<FramTracker>){
+        if self.pages.len()>=self.prune_at{
+            self.pages.retain(|_,page|page.strong_count()>0);
+            self.prune_at=(self.pages.len()*2).max(PAGE_CACHE_PRUNE_MIN);
+        }
+        self.pages.insert(key, Arc::downgrade(frame));
+        trace!("page cache insert inode:{} page:{} ppn:{}",key.0,key.1,frame.ppn.0);
+    }
+
+    ///丢弃一个文件的所有缓存页 已经映射的任务继续使用旧页帧，之后的缺页重新从文件读取
+    pub fn invalidate(&mut self,inode_id:usize){
+        let mut file_pages=self.pages.split_off(&(inode_id,0));
+        let mut rest=file_pages.split_off(&(inode_id+1,0));
+        self.pages.append(&mut rest);
+        if !file_pages.is_empty(){
+            trace!("page cache invalidate inode:{} pages:{}",inode_id,file_pages.len());
+        }
+    }
+
+    ///仍然被映射的缓存页数
+    pub fn len(&self)->usize{
+        self.pages.values().filter(|page|page.strong_count()>0).count()
+    }
+}
+
+lazy_static!{
+    ///全局页缓存
+    pub static ref PAGE_CACHE:UPSafeCell<PageCache> = UPSafeCell::new(PageCache::new());
+}
+
+///文件内容改变或者文件被删除时调用 丢弃它的缓存页
+pub fn invalidate_page_cache(file:&Arc<dyn VfsNodeOps>){
+    if let Some(inode_id)=file.get_inode_id(){
+        PAGE_CACHE.lock().invalidate(inode_id);
+    }
+}
@@ -0,0 +1,125 @@
+///
+/// 分页模式
+/// 支持Sv39和Sv48，启动时由bootargs的paging=选择，硬件不支持Sv48时退回Sv39
+/// 模式决定页表级数、satp的MODE字段和用户地址空间大小，内核地址空间创建后不能再修改
+
+use core::arch::asm;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use riscv::register::satp;
+use crate::config::{PAGE_SIZE, USER_STACK_LIMIT};
+use crate::memory::{alloc_frame, PTEFlags, PageTableEntry};
+use crate::sync::UPSafeCell;
+
+///支持的最大页表级数
+pub const MAX_PAGE_LEVELS:usize=4;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum PagingMode{
+    Sv39,
+    Sv48,
+}
+
+impl PagingMode {
+    pub fn from_name(name:&str)->Option<Self>{
+        match name {
+            "sv39"=>Some(PagingMode::Sv39),
+            "sv48"=>Some(PagingMode::Sv48),
+            _=>None,
+        }
+    }
+
+    ///从内核命令行解析 paging=sv39|sv48
+    pub fn from_bootargs(bootargs:&str)->Option<Self>{
+        let name=bootargs.split_whitespace().find_map(|arg|arg.strip_prefix("paging="))?;
+        Self::from_name(name)
+    }
+
+    ///页表级数
+    pub fn levels(&self)->usize{
+        match self {
+            PagingMode::Sv39=>3,
+            PagingMode::Sv48=>4,
+        }
+    }
+
+    ///satp的MODE字段
+    pub fn satp_mode(&self)->usize{
+        match self {
+            PagingMode::Sv39=>8,
+            PagingMode::Sv48=>9,
+        }
+    }
+
+    ///虚拟地址有效位数
+    pub fn va_bits(&self)->usize{
+        12+9*self.levels()
+    }
+
+    ///用户地址空间上界(不包含) 即虚拟地址空间的低半部分
+    pub fn user_top(&self)->usize{
+        1<<(self.va_bits()-1)
+    }
+
+    ///高半部分地址符号扩展的位
+    pub fn high_address_mask(&self)->usize{
+        !(self.user_top()-1)
+    }
+}
+
+lazy_static!{
+    ///启动时选择的分页模式 必须在内核地址空间创建前设置
+    pub static ref PAGING_MODE:UPSafeCell<PagingMode> = UPSafeCell::new(
+        option_env!("BLUESTAR_PAGING").and_then(PagingMode::from_name).unwrap_or(PagingMode::Sv39)
+    );
+}
+
+///当前分页模式
+pub fn paging_mode()->PagingMode{
+    *PAGING_MODE.lock()
+}
+
+///写入Sv48的satp再读回，不支持的模式写入会被忽略
+///临时根页表用一个512GiB的叶子恒等映射低地址，保证写入成功后内核还能继续取指
+fn sv48_supported()->bool{
+    let root=match alloc_frame() {
+        Some(frame)=>frame,
+        None=>return false,
+    };
+    let entries=root.ppn.get_bytes_array().as_mut_ptr() as *mut PageTableEntry;
+    unsafe {
+        core::slice::from_raw_parts_mut(entries, 512).fill(PageTableEntry(0));
+        *entries=PageTableEntry::new(0, PTEFlags::V | PTEFlags::R | PTEFlags::W | PTEFlags::X | PTEFlags::A | PTEFlags::D);
+    }
+    let mode=unsafe {
+        let origin=satp::read().bits();
+        satp::write((PagingMode::Sv48.satp_mode()<<60) | root.ppn.0);
+        let mode=satp::read().bits()>>60;
+        satp::write(origin);
+        asm!("sfence.vma");
+        mode
+    };
+    mode==PagingMode::Sv48.satp_mode()
+}
+
+///根据内核命令行设置分页模式 没有paging=参数时使用编译时的BLUESTAR_PAGING，默认Sv39
+///必须在分页开启前调用
+pub fn init_paging_mode(bootargs:Option<&str>){
+    let mut mode=bootargs.and_then(PagingMode::from_bootargs).unwrap_or_else(paging_mode);
+    if mode==PagingMode::Sv48 && !sv48_supported(){
+        warn!("Sv48 not supported, fall back to Sv39");
+        mode=PagingMode::Sv39;
+    }
+    *PAGING_MODE.lock()=mode;
+    debug!("Paging mode:{:?}",mode);
+}
+
+///用户栈顶(不包含) 位于用户地址空间最高处
+pub fn user_stack_top()->usize{
+    paging_mode().user_top()
+}
+
+///内核选择mmap地址时的上界 上面留出用户栈的增长空间和一个guard page
+pub fn mmap_top()->usize{
+    user_stack_top()-USER_STACK_LIMIT-PAGE_SIZE
+}
@@ -0,0 +1,138 @@
+///
+/// 交换分区
+/// 匿名页换出到专用块设备，每个交换槽一页(8个512字节的块)
+/// 换出页的页表项不合法并保存槽号，MapArea持有SwapSlot，释放时槽回收
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use BlueosFS::{BlockDeviceTrait, BLOCK_SIZE};
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use crate::config::PAGE_SIZE;
+use crate::memory::VirNumber;
+use crate::sync::UPSafeCell;
+
+///每个交换槽占用的块数
+const BLOCKS_PER_SLOT:usize=PAGE_SIZE/BLOCK_SIZE;
+
+pub struct SwapSpace{
+    device:Option<Arc<dyn BlockDeviceTrait>>,
+    ///槽位图 每一位代表一个槽是否被占用
+    bitmap:Vec<u64>,
+    slots:usize,
+    used:usize,
+    ///时钟算法的指针 (pid,vpn) 下一次从这里继续扫描
+    hand:(usize,VirNumber),
+}
+
+impl SwapSpace {
+    pub fn new()->Self{
+        SwapSpace { device: None, bitmap: Vec::new(), slots: 0, used: 0, hand: (0, VirNumber(0)) }
+    }
+
+    fn alloc(&mut self)->Option<usize>{
+        let (word_index,word)=self.bitmap.iter_mut().enumerate().find(|(_,word)|**word!=u64::MAX)?;
+        let bit=word.trailing_ones() as usize;
+        let slot=word_index*64+bit;
+        if slot>=self.slots{
+            return None;
+        }
+        *word|=1<<bit;
+        self.used+=1;
+        Some(slot)
+    }
+
+    fn dealloc(&mut self,slot:usize){
+        let word=&mut self.bitmap[slot/64];
+        if *word & (1<<(slot%64))==0{
+            panic!("swap slot:{} double free",slot);
+        }
+        *word&=!(1<<(slot%64));
+        self.used-=1;
+    }
+}
+
+lazy_static!{
+    pub static ref SWAP_SPACE:UPSafeCell<SwapSpace> = UPSafeCell::new(SwapSpace::new());
+}
+
+///初始化交换分区 槽数按设备块数计算 没有交换设备、设备大小未知或者不够一个槽时不启用换出
+pub fn init_swap(device:Option<Arc<dyn BlockDeviceTrait>>){
+    let device=match device {
+        Some(device)=>device,
+        None=>{
+            warn!("No swap device, swap disabled");
+            return;
+        }
+    };
+    let slots=device.block_count().unwrap_or(0)/BLOCKS_PER_SLOT;
+    if slots==0{
+        warn!("Swap device size unknown or smaller than one page, swap disabled");
+        return;
+    }
+    let mut swap=SWAP_SPACE.lock();
+    swap.slots=slots;
+    swap.bitmap=vec![0;(swap.slots+63)/64];
+    swap.device=Some(device);
+    debug!("Swap enabled, slots:{}",swap.slots);
+}
+
+///是否启用了交换分区
+pub fn swap_enabled()->bool{
+    SWAP_SPACE.lock().device.is_some()
+}
+
+///时钟算法指针
+pub fn swap_hand()->(usize,VirNumber){
+    SWAP_SPACE.lock().hand
+}
+
+pub fn set_swap_hand(pid:usize,vpn:VirNumber){
+    SWAP_SPACE.lock().hand=(pid,vpn);
+}
+
+///(已用槽数,总槽数)
+pub fn swap_usage()->(usize,usize){
+    let swap=SWAP_SPACE.lock();
+    (swap.used,swap.slots)
+}
+
+///交换槽 drop时回收
+#[derive(Debug)]
+pub struct SwapSlot(usize);
+
+impl SwapSlot {
+    ///分配一个交换槽 没有交换设备或者交换分区满了返回None
+    pub fn alloc()->Option<Self>{
+        let mut swap=SWAP_SPACE.lock();
+        swap.device.as_ref()?;
+        swap.alloc().map(SwapSlot)
+    }
+
+    pub fn id(&self)->usize{
+        self.0
+    }
+
+    ///把一页写入交换槽
+    pub fn write(&self,page:&[u8;PAGE_SIZE]){
+        let device=SWAP_SPACE.lock().device.clone().expect("swap device lost");
+        for (index,block) in page.chunks(BLOCK_SIZE).enumerate(){
+            device.write_block(self.0*BLOCKS_PER_SLOT+index, block);
+        }
+    }
+
+    ///从交换槽读回一页
+    pub fn read(&self,page:&mut [u8;PAGE_SIZE]){
+        let device=SWAP_SPACE.lock().device.clone().expect("swap device lost");
+        for (index,block) in page.chunks_mut(BLOCK_SIZE).enumerate(){
+            device.read_block(self.0*BLOCKS_PER_SLOT+index, block);
+        }
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_SPACE.lock().dealloc(self.0);
+    }
+}
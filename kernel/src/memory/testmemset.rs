@@ -0,0 +1,108 @@
+use log::{debug, error};
+
+use crate::config::{MMAP_BASE, PAGE_SIZE};
+use crate::memory::{MapAreaFlags, MapAreaType, MapSet, MapType, VirAddr, VirNumRange, VirNumber};
+
+///检查一项，失败时打印
+fn check(ok:bool,what:&str)->bool{
+    if !ok{
+        error!("memset test failed: {}",what);
+    }
+    ok
+}
+
+///vpn仍然在area中并且有合法页表项
+fn resident(memset:&mut MapSet,vpn:VirNumber)->bool{
+    memset.AallArea_Iscontain_thisVpn(vpn) && memset.get_table().is_maped(vpn)
+}
+
+///vpn既不在area中也没有合法页表项
+fn gone(memset:&mut MapSet,vpn:VirNumber)->bool{
+    !memset.AallArea_Iscontain_thisVpn(vpn) && !memset.get_table().is_maped(vpn)
+}
+
+///部分munmap的边界情况自测 在内核地址空间激活后调用，返回是否全部通过
+pub fn test_unmap_range()->bool{
+    let mut ok=true;
+
+    //区间交集 包括完全覆盖的情况
+    let area=VirNumRange(VirNumber(10), VirNumber(20));
+    ok&=check(area.is_contain_thisvpnRange(VirNumRange(VirNumber(5), VirNumber(25))),"range covers area");
+    ok&=check(area.is_contain_thisvpnRange(VirNumRange(VirNumber(12), VirNumber(15))),"range inside area");
+    ok&=check(area.is_contain_thisvpnRange(VirNumRange(VirNumber(20), VirNumber(30))),"range touches area end");
+    ok&=check(!area.is_contain_thisvpnRange(VirNumRange(VirNumber(21), VirNumber(30))),"range after area");
+    ok&=check(!area.is_contain_thisvpnRange(VirNumRange(VirNumber(0), VirNumber(9))),"range before area");
+
+    //[base,base+8)的匿名映射，全部触发缺页
+    let Ok(mut memset)=MapSet::new_bare() else {
+        return check(false,"alloc page table");
+    };
+    let base=VirAddr(MMAP_BASE).floor_down().0;
+    let vpn=|index:usize|VirNumber(base+index);
+    let addr=|index:usize|VirAddr((base+index)*PAGE_SIZE);
+    ok&=check(memset.mmap(MMAP_BASE, 8*PAGE_SIZE, MapAreaFlags::R | MapAreaFlags::W, true, None)==MMAP_BASE as isize,"mmap");
+    for index in 0..8{
+        ok&=check(memset.findarea_allocFrame_and_setPte(vpn(index))==Ok(true),"fault in");
+    }
+
+    //中间一页 area被切成两段
+    ok&=check(memset.unmap_range(addr(3), PAGE_SIZE)==0,"unmap middle");
+    ok&=check(gone(&mut memset,vpn(3)),"middle page freed");
+    ok&=check(resident(&mut memset,vpn(2)) && resident(&mut memset,vpn(4)),"middle neighbours kept");
+
+    //左边界 范围从映射之前开始
+    ok&=check(memset.unmap_range(VirAddr(addr(0).0-2*PAGE_SIZE), 3*PAGE_SIZE)==0,"unmap left edge");
+    ok&=check(gone(&mut memset,vpn(0)) && resident(&mut memset,vpn(1)),"left edge");
+
+    //右边界 不满一页按一页处理，范围延伸到映射之后
+    ok&=check(memset.unmap_range(VirAddr(addr(7).0+PAGE_SIZE/2), 4*PAGE_SIZE)==0,"unmap right edge");
+    ok&=check(gone(&mut memset,vpn(7)) && resident(&mut memset,vpn(6)),"right edge");
+
+    //跨过空洞覆盖两个area剩下的部分
+    ok&=check(memset.unmap_range(addr(2), 3*PAGE_SIZE)==0,"unmap across hole");
+    ok&=check(gone(&mut memset,vpn(2)) && gone(&mut memset,vpn(4)),"across hole");
+    ok&=check(resident(&mut memset,vpn(1)) && resident(&mut memset,vpn(5)),"across hole neighbours kept");
+
+    //完全覆盖剩下的所有area
+    ok&=check(memset.unmap_range(addr(0), 8*PAGE_SIZE)==0,"unmap cover all");
+    ok&=check((0..8).all(|index|gone(&mut memset,vpn(index))),"cover all");
+
+    //没有映射和长度为0
+    ok&=check(memset.unmap_range(addr(0), PAGE_SIZE)==-1,"unmap nothing");
+    ok&=check(memset.unmap_range(addr(0), 0)==-1,"unmap zero length");
+
+    //DEFAULT段不能取消映射，即使范围只覆盖它的一部分
+    ok&=check(memset.add_area(VirNumRange(vpn(10), vpn(11)), MapType::Maped, MapAreaFlags::R | MapAreaFlags::U, None, MapAreaType::DEFAULT).is_ok(),"add default area");
+    ok&=check(memset.unmap_range(addr(9), 2*PAGE_SIZE)==-1,"unmap default area");
+    ok&=check(resident(&mut memset,vpn(10)),"default area kept");
+
+    debug!("memset unmap test {}",if ok {"passed"} else {"failed"});
+    ok
+}
+
+///PROT_NONE之后恢复读写权限的自测 页表项要重新指向原来的页帧，页里的数据不变
+pub fn test_mprotect_roundtrip()->bool{
+    let mut ok=true;
+    let Ok(mut memset)=MapSet::new_bare() else {
+        return check(false,"alloc page table");
+    };
+    let vpn=VirAddr(MMAP_BASE).floor_down();
+    let rw=MapAreaFlags::R | MapAreaFlags::W;
+    ok&=check(memset.mmap(MMAP_BASE, PAGE_SIZE, rw, true, None)==MMAP_BASE as isize,"mmap");
+    ok&=check(memset.findarea_allocFrame_and_setPte(vpn)==Ok(true),"fault in");
+    let ppn=memset.get_table().translate_byvpn(vpn);
+    ok&=check(ppn.is_some(),"page mapped");
+    if let Some(page)=memset.get_table().get_mut_byte(vpn){
+        page[0]=0x5a;
+    }
+
+    ok&=check(memset.mprotect(VirAddr(MMAP_BASE), PAGE_SIZE, MapAreaFlags::empty())==0,"mprotect none");
+    ok&=check(!memset.get_table().is_maped(vpn),"none page not accessible");
+
+    ok&=check(memset.mprotect(VirAddr(MMAP_BASE), PAGE_SIZE, rw)==0,"mprotect read write");
+    ok&=check(memset.get_table().translate_byvpn(vpn)==ppn,"same frame after restore");
+    ok&=check(memset.get_table().get_mut_byte(vpn).map_or(false,|page|page[0]==0x5a),"data kept after restore");
+
+    debug!("memset mprotect test {}",if ok {"passed"} else {"failed"});
+    ok
+}
@@ -1,6 +1,6 @@
 use core::panic::PanicInfo;
 use log::error;
-use crate::sbi::shutdown;
+use crate::sbi::{system_reset, RESET_REASON_SYSTEM_FAILURE, RESET_TYPE_SHUTDOWN};
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     let location = _info.location();
@@ -10,5 +10,5 @@ fn panic(_info: &PanicInfo) -> ! {
         error!("[Kernel Panic]: Kernel panic: {}", _info.message().unwrap());
     }
 
-    shutdown()
+    system_reset(RESET_TYPE_SHUTDOWN, RESET_REASON_SYSTEM_FAILURE)
 }
@@ -5,6 +5,14 @@ const SET_TIMER:usize=0;
 const PUTC_CALLID:usize=1;
 const GETCHAR_CALLID:usize=2;
 const SHUTDOWN_CALLID:usize=8;
+/* SRST扩展 系统复位 */
+const SRST_EXTENSION:usize=0x53525354;
+const SRST_SYSTEM_RESET:usize=0;
+pub const RESET_TYPE_SHUTDOWN:usize=0;
+pub const RESET_TYPE_COLD_REBOOT:usize=1;
+pub const RESET_TYPE_WARM_REBOOT:usize=2;
+pub const RESET_REASON_NO_REASON:usize=0;
+pub const RESET_REASON_SYSTEM_FAILURE:usize=1;
 
 
 #[inline(always)]
@@ -22,6 +30,22 @@ fn sbi_call(callid:usize,arg0:usize,arg1:usize,arg2:usize)->isize{
     }
     result
 }
+
+///新版sbi调用 eid:扩展号 fid:功能号 返回a0错误码
+#[inline(always)]
+fn sbi_call_ext(eid:usize,fid:usize,arg0:usize,arg1:usize)->isize{
+    let mut error;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") arg0 => error,
+            inlateout("x11") arg1 => _,
+            in("x16") fid,
+            in("x17") eid,
+        );
+    }
+    error
+}
 ///向串口输出一个字符
 pub fn putc(cha:usize){
     sbi_call(PUTC_CALLID, cha, 0, 0);
@@ -31,9 +55,22 @@ pub fn get_char()->isize{//非阻塞 -1没有字符，>=0ascii码
     sbi_call(GETCHAR_CALLID, 0, 0, 0)
 }
 
-pub fn shutdown()->!{
+///SRST系统复位 reset_type:关机/冷重启/热重启 reason:复位原因
+///sbi不支持SRST时退回legacy关机
+pub fn system_reset(reset_type:usize,reason:usize)->!{
+    sbi_call_ext(SRST_EXTENSION, SRST_SYSTEM_RESET, reset_type, reason);
+    //SRST调用成功不会返回 legacy关机同样不会返回
     sbi_call(SHUTDOWN_CALLID, 0, 0, 0);
-    panic!("It should shutdown!");
+    loop {}
+}
+
+pub fn shutdown()->!{
+    system_reset(RESET_TYPE_SHUTDOWN, RESET_REASON_NO_REASON)
+}
+
+///冷重启
+pub fn reboot()->!{
+    system_reset(RESET_TYPE_COLD_REBOOT, RESET_REASON_NO_REASON)
 }
 ///设置下一次的时钟中断
 pub fn set_next_timetriger(timer:usize){
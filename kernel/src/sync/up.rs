@@ -19,4 +19,9 @@ impl<T> UPSafeCell<T>{
     pub fn lock(&self)->core::cell::RefMut<'_,T>{
         self.inner.borrow_mut()
     }
+
+    ///已经被借用时返回None 用于可能重入的路径
+    pub fn try_lock(&self)->Option<core::cell::RefMut<'_,T>>{
+        self.inner.try_borrow_mut().ok()
+    }
 }
\ No newline at end of file
@@ -7,17 +7,62 @@ pub const SYS_WRITE:usize  =1;     //stdin write系统调用
 pub const SYS_READ:usize   =2;     //stdin read系统调用
 pub const SYS_EXIT:usize   =3;     //exit程序结束，运行下一个程序
 pub const SYS_YIELD:usize  =4;     //主动放弃cpu
-pub const SYS_MAP:usize    =5;     //mmap映射系统调用
+pub const SYS_MAP:usize    =5;     //mmap映射系统调用 (addr,len,prot,flags,fd,offset)
 pub const SYS_UNMAP:usize  =6;     //unmap映射系统调用
 pub const SYS_CREATE:usize =7;     //文件节点创建系统调用
 pub const SYS_DELETE:usize =8;     //文件删除系统调用
 pub const SYS_MKDIR:usize  =9;     //文件夹创建系统调用
 pub const SYS_FORK:usize   =10;    //fork系统调用
 pub const SYS_EXEC:usize   =11;    //exec系统调用
+pub const SYS_REBOOT:usize =12;    //关机/重启系统调用
+pub const SYS_SETPRIORITY:usize=13; //设置任务优先级(ticket)
+pub const SYS_GETPRIORITY:usize=14; //获取任务优先级(ticket)
+pub const SYS_GETRUSAGE:usize=15;   //获取资源使用统计
+pub const SYS_BRK:usize=16;         //调整用户堆program break
+pub const SYS_OPEN:usize=17;        //打开文件
+pub const SYS_CLOSE:usize=18;       //关闭文件描述符
+pub const SYS_MSYNC:usize=19;       //写回MAP_SHARED映射
+pub const SYS_MPROTECT:usize=20;    //修改映射访问权限
+pub const SYS_MEMINFO:usize=21;     //系统内存使用统计
+pub const SYS_MAPS:usize=22;        //列出任务地址空间的area
+pub const SYS_WAIT:usize=23;        //等待子任务退出
+
+/* 错误码 系统调用返回负数 */
+//内核访问用户内存(缓冲区、路径字符串、mprotect/msync的范围)失败时统一返回-EFAULT，其他错误返回-1
+pub const EFAULT:isize=14;   //用户地址没有映射或者没有访问权限
+
+/* SYS_REBOOT命令 */
+pub const REBOOT_CMD_POWER_OFF:usize=0;   //关机
+pub const REBOOT_CMD_RESTART:usize  =1;   //重启
+
+///优先级系统调用中表示当前任务的pid（pid 0是init）
+pub const PRIO_SELF:usize=usize::MAX;
+
+/* SYS_MAP访问权限 */
+pub const PROT_NONE:usize =0;
+pub const PROT_READ:usize =1;
+pub const PROT_WRITE:usize=2;
+pub const PROT_EXEC:usize =4;
+/* SYS_MAP映射标志 */
+pub const MAP_SHARED:usize   =0x01;   //修改写回文件
+pub const MAP_PRIVATE:usize  =0x02;   //私有映射
+pub const MAP_FIXED:usize    =0x10;   //必须使用给定地址
+pub const MAP_ANONYMOUS:usize=0x20;   //匿名映射，忽略fd
+/* SYS_OPEN标志 */
+pub const O_RDONLY:usize=0x0;
+pub const O_WRONLY:usize=0x1;
+pub const O_RDWR:usize  =0x2;
+pub const O_CREAT:usize =0x40;
+pub const O_TRUNC:usize =0x200;
+pub const O_APPEND:usize=0x400;
+
+/* SYS_GETRUSAGE统计对象 */
+pub const RUSAGE_SELF:usize=0;                  //当前任务
+pub const RUSAGE_CHILDREN:usize=usize::MAX;     //已回收的子任务(-1)
 ///id: 系统调用号
-///args:接受1个usize参数
+///args:a0-a5 6个usize参数
 ///返回值：通过 x10 (a0) 寄存器返回给用户态
-pub fn syscall_handler(id:usize,arg:[usize;3]) -> isize {
+pub fn syscall_handler(id:usize,arg:[usize;6]) -> isize {
     match id {
         GET_TIME => {
             0  // 暂未实现
@@ -37,7 +82,7 @@ pub fn syscall_handler(id:usize,arg:[usize;3]) -> isize {
             sys_yield()
         }
         SYS_MAP=>{
-            sys_map(arg[0], arg[1])
+            sys_map(arg[0], arg[1], arg[2], arg[3], arg[4], arg[5])
         }
         SYS_UNMAP=>{
             sys_unmap(arg[0], arg[1])
@@ -57,6 +102,42 @@ pub fn syscall_handler(id:usize,arg:[usize;3]) -> isize {
         SYS_EXEC=>{
             sys_exec(arg[0])
         }
+        SYS_REBOOT=>{
+            sys_reboot(arg[0])
+        }
+        SYS_SETPRIORITY=>{
+            sys_setpriority(arg[0], arg[1])
+        }
+        SYS_GETPRIORITY=>{
+            sys_getpriority(arg[0])
+        }
+        SYS_GETRUSAGE=>{
+            sys_getrusage(arg[0], arg[1])
+        }
+        SYS_BRK=>{
+            sys_brk(arg[0])
+        }
+        SYS_OPEN=>{
+            sys_open(arg[0], arg[1])
+        }
+        SYS_CLOSE=>{
+            sys_close(arg[0])
+        }
+        SYS_MSYNC=>{
+            sys_msync(arg[0], arg[1])
+        }
+        SYS_MPROTECT=>{
+            sys_mprotect(arg[0], arg[1], arg[2])
+        }
+        SYS_MEMINFO=>{
+            sys_meminfo(arg[0])
+        }
+        SYS_MAPS=>{
+            sys_maps(arg[0], arg[1], arg[2])
+        }
+        SYS_WAIT=>{
+            sys_wait()
+        }
         
         _ => {
             panic!("Unknown Syscall type: {}", id);
@@ -4,12 +4,16 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use alloc::string::String;
 use log::{debug, error};
-use crate::sbi::shutdown;
+use crate::sbi::{reboot, shutdown};
 use crate::task::ProcessId;
 use crate::{config::PAGE_SIZE, memory::{PageTable, VirAddr, VirNumber}, task::TASK_MANAER, time::{TimeVal, get_time_ms}};
-use BlueosFS::VfsError;
 use alloc::vec;
-use crate::memory::MapSet;
+use crate::memory::{MapAreaFlags, MapSet, MmapBacking, MemInfo, MapInfo, MemoryError, invalidate_page_cache};
+use crate::syscall::*;
+use BlueosFS::FileFlags;
+use crate::syscall::{REBOOT_CMD_POWER_OFF, REBOOT_CMD_RESTART, PRIO_SELF, RUSAGE_SELF, RUSAGE_CHILDREN};
+use crate::task::RUsage;
+use crate::config::{INIT_PID, TASK_TICKET_MAX, TASK_TICKET_MIN};
 
 
 
@@ -19,7 +23,7 @@ use crate::memory::MapSet;
 pub fn sys_exec(path_ptr: usize)->isize{
     let inner = TASK_MANAER.task_que_inner.lock();
     let current_index = inner.current;
-  //  let current_task = &mut inner.task_queen[current_index];
+  //  let current_task = inner.current_task();
 
     //pid过滤
   
@@ -30,68 +34,69 @@ pub fn sys_exec(path_ptr: usize)->isize{
 
 }
 
-///SYS_FORK系统调用
+///SYS_FORK系统调用 还没有实现，返回-1
+///复制TCB会连pid句柄一起复制，副本drop时pid被回收而原任务还在用，所以不能直接clone当前任务
 pub fn sys_fork()->isize{
-    let mut inner = TASK_MANAER.task_que_inner.lock();
-    let current_index = inner.current;
-    let current_task = &mut inner.task_queen[current_index];
-    let mut bad_task = current_task.clone();
-
-    let arc =Arc::new(bad_task);
-    /* 建立父子关系 */
-    current_task.add_children(arc.clone());
-
-    drop(inner);
-
-    //bad_task.pid=ProcessId(0);//设置fork后的进程pid为0 全复制
-    
-
+    -1
+}
 
-    /* 把任务添加到任务队列 */
-   // TASK_MANAER.task_que_inner.lock().task_queen.push_back(bad_task);
 
-    return 0;
 
 
 
+///内核访问用户缓冲区前处理懒分配页面(mmap、堆、栈)的缺页 access:内核要读(R)还是写(W)
+///范围内有没有映射或者没有权限的页面返回false
+///物理内存耗尽时由OOM killer杀掉任务后重试，当前任务被杀时不会返回
+fn prefault_user(addr:usize,len:usize,access:MapAreaFlags)->bool{
+    loop {
+        let mut inner=TASK_MANAER.task_que_inner.lock();
+        inner.reclaim_frames();
+        match inner.current_task().memory_set.prefault_range(VirAddr(addr), len, access) {
+            Ok(())=>return true,
+            Err(MemoryError::BadAddress)=>return false,
+            Err(MemoryError::OutOfMemory)=>{}
+        }
+        drop(inner);
+        TASK_MANAER.oom_kill();
+    }
 }
 
-
-
-
+///处理缺页并取出用户缓冲区对应的物理内存切片 地址非法或者没有access权限返回None
+fn user_buffer(addr:usize,len:usize,access:MapAreaFlags)->Option<Vec<&'static mut [u8]>>{
+    if !prefault_user(addr, len, access){
+        return None;
+    }
+    PageTable::get_mut_slice_from_satp(TASK_MANAER.get_current_stap(), len, VirAddr(addr))
+}
 
 /// 从用户空间读取 null 结尾的 C 风格字符串
 /// 最大读取长度为 4096 字节，避免读取过长的字符串
-fn read_c_string_from_user(path_ptr: usize) -> Result<String, VfsError> {
+/// 失败时返回系统调用的返回值：地址非法或者不可读为-EFAULT，过长或者不是UTF-8为-1
+fn read_c_string_from_user(path_ptr: usize) -> Result<String, isize> {
     const MAX_PATH_LEN: usize = 4096;
     
-    // 获取当前任务的页表
-    let user_satp = TASK_MANAER.get_current_stap();
-    
-    // 读取用户空间字节，最多读取 MAX_PATH_LEN 字节
-    let buffer = PageTable::get_mut_slice_from_satp(user_satp, MAX_PATH_LEN, VirAddr(path_ptr));
-    
-    // 拼接所有切片并查找 null 终止符
+    // 一次只读到页尾，找到null就停下，不会访问字符串之后可能没有映射的页面
     let mut path_bytes = Vec::new();
-    for slice in buffer {
-        // 查找 null 字节
-        if let Some(null_pos) = slice.iter().position(|&b| b == 0) {
-            // 找到 null 字节，只取到 null 之前的部分
-            path_bytes.extend_from_slice(&slice[..null_pos]);
-            break;
-        } else {
-            // 没有找到 null 字节，添加整个切片
-            path_bytes.extend_from_slice(slice);
-            // 如果已经达到最大长度，停止读取
-            if path_bytes.len() >= MAX_PATH_LEN {
-                return Err(VfsError::InvalidOperation);
+    let mut addr = path_ptr;
+    while path_bytes.len() < MAX_PATH_LEN {
+        let chunk = (PAGE_SIZE - VirAddr(addr).offset()).min(MAX_PATH_LEN - path_bytes.len());
+        let buffer = user_buffer(addr, chunk, MapAreaFlags::R).ok_or(-EFAULT)?;
+        for slice in buffer {
+            // 查找 null 字节
+            if let Some(null_pos) = slice.iter().position(|&b| b == 0) {
+                // 找到 null 字节，只取到 null 之前的部分
+                path_bytes.extend_from_slice(&slice[..null_pos]);
+                // 转换为字符串
+                return String::from_utf8(path_bytes)
+                    .map_err(|_| -1);
             }
+            path_bytes.extend_from_slice(slice);
         }
+        addr += chunk;
     }
     
-    // 转换为字符串
-    String::from_utf8(path_bytes)
-        .map_err(|_| VfsError::InvalidOperation)
+    // 超过最大长度还没有找到 null
+    Err(-1)
 }
 
 
@@ -105,7 +110,7 @@ pub fn sys_create(path_ptr: usize) -> isize {
     // 从用户空间读取路径字符串
     let path_str = match read_c_string_from_user(path_ptr) {
         Ok(path) => path,
-        Err(_) => return -1, // 路径读取失败
+        Err(err) => return err, // 路径读取失败
     };
     
     // 调用文件系统 API 创建文件
@@ -121,7 +126,7 @@ pub fn sys_mkdir(path_ptr: usize) -> isize {
     // 从用户空间读取路径字符串
     let path_str = match read_c_string_from_user(path_ptr) {
         Ok(path) => path,
-        Err(_) => return -1, // 路径读取失败
+        Err(err) => return err, // 路径读取失败
     };
     
     // 调用文件系统 API 创建目录
@@ -138,37 +143,131 @@ pub fn sys_delete(path_ptr: usize) -> isize {
     // 从用户空间读取路径字符串
     let path_str = match read_c_string_from_user(path_ptr) {
         Ok(path) => path,
-        Err(_) => return -1, // 路径读取失败
+        Err(err) => return err, // 路径读取失败
     };
     
+    // 删除后inode编号会被重新分配，先记下文件节点，删除成功后丢弃它的缓存页
+    let node = BlueosFS::open(&path_str, FileFlags::read_only()).ok().map(|file| file.node());
     // 调用文件系统 API 删除文件或目录
     // 如果删除目录且目录非空，会返回 VfsError::NotEmpty
     match BlueosFS::remove(&path_str) {
-        Ok(_) => 0,
+        Ok(_) => {
+            if let Some(node) = node {
+                invalidate_page_cache(&node);
+            }
+            0
+        }
         Err(_) => -1, // 删除失败（路径不存在、权限错误、目录非空等）
     }
 }
 
+///PROT_*转换为area访问标志 RISC-V不允许只写，可写隐含可读
+fn prot_to_flags(prot:usize)->MapAreaFlags{
+    let mut flags=MapAreaFlags::empty();
+    if prot & (PROT_READ | PROT_WRITE)!=0{
+        flags|=MapAreaFlags::R;
+    }
+    if prot & PROT_WRITE!=0{
+        flags|=MapAreaFlags::W;
+    }
+    if prot & PROT_EXEC!=0{
+        flags|=MapAreaFlags::X;
+    }
+    flags
+}
+
 ///mmap系统调用
-/// startaddr:usize size:长度
-pub fn sys_map(start:usize,size:usize)->isize{
-    let inner=TASK_MANAER.task_que_inner.lock();
-    let current=inner.current;
-    drop(inner);
+/// addr:建议地址(MAP_FIXED时必须使用) len:长度 prot:PROT_* flags:MAP_*
+/// fd/offset:文件映射的文件描述符和页对齐的文件偏移，MAP_ANONYMOUS时忽略
+/// 返回映射地址，失败返回-1
+pub fn sys_map(addr:usize,len:usize,prot:usize,flags:usize,fd:usize,offset:usize)->isize{
+    //MAP_SHARED和MAP_PRIVATE必须且只能选一个
+    let shared=flags & MAP_SHARED!=0;
+    if shared==(flags & MAP_PRIVATE!=0) || offset%PAGE_SIZE!=0{
+        return -1;
+    }
+    let backing=if flags & MAP_ANONYMOUS!=0{
+        None
+    }else {
+        let file=match TASK_MANAER.get_current_fd(fd) {
+            Some(file)=>file,
+            None=>return -1,
+        };
+        //文件必须可读，共享可写映射要求文件可写
+        let file_flags=file.flags();
+        if !file_flags.read || (shared && prot & PROT_WRITE!=0 && !file_flags.write){
+            return -1;
+        }
+        Some(MmapBacking { file: file.node(), offset, file_end: usize::MAX, shared, writable: file_flags.write })
+    };
     let mut inner=TASK_MANAER.task_que_inner.lock();
-    let mut memset=&mut inner.task_queen[current].memory_set;
-    memset.mmap(VirAddr(start), size)
+    let memset=&mut inner.current_task().memory_set;
+    memset.mmap(addr, len, prot_to_flags(prot), flags & MAP_FIXED!=0, backing)
     //inner自动销毁
 }
 
+///msync系统调用 把[addr,addr+len)内MAP_SHARED映射写回文件 范围内没有映射返回-EFAULT
+pub fn sys_msync(addr:usize,len:usize)->isize{
+    let mut inner=TASK_MANAER.task_que_inner.lock();
+    let memset=&inner.current_task().memory_set;
+    let ret=memset.msync(VirAddr(addr), len);
+    drop(inner);
+    BlueosFS::sync();//写回的页还在块缓存里，刷到设备
+    ret
+}
+
+///mprotect系统调用 修改[addr,addr+len)的访问权限为prot(PROT_*)
+///addr必须页对齐，违反W^X策略时失败 成功返回0，范围没有全部映射返回-EFAULT，其他失败返回-1
+pub fn sys_mprotect(addr:usize,len:usize,prot:usize)->isize{
+    let mut inner=TASK_MANAER.task_que_inner.lock();
+    let memset=&mut inner.current_task().memory_set;
+    memset.mprotect(VirAddr(addr), len, prot_to_flags(prot))
+}
+
+///open系统调用 path_ptr:用户空间路径 flags:O_*
+///返回文件描述符，path_ptr不可读返回-EFAULT，其他失败返回-1
+pub fn sys_open(path_ptr:usize,flags:usize)->isize{
+    let path_str = match read_c_string_from_user(path_ptr) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let file_flags=FileFlags {
+        read: flags & (O_WRONLY | O_RDWR)!=O_WRONLY,
+        write: flags & (O_WRONLY | O_RDWR)!=O_RDONLY,
+        append: flags & O_APPEND!=0,
+        create: flags & O_CREAT!=0,
+        truncate: flags & O_TRUNC!=0,
+    };
+    match BlueosFS::open(&path_str, file_flags) {
+        Ok(file)=>{
+            //O_TRUNC截断了文件
+            if file_flags.truncate{
+                invalidate_page_cache(&file.node());
+            }
+            TASK_MANAER.alloc_current_fd(Arc::new(file)) as isize
+        }
+        Err(_)=>-1
+    }
+}
+
+///close系统调用 成功返回0，fd不存在返回-1
+pub fn sys_close(fd:usize)->isize{
+    if TASK_MANAER.close_current_fd(fd) {0} else {-1}
+}
+
+///brk系统调用 new_brk:新的program break，0表示查询
+///返回新的brk，失败时返回原来的brk
+pub fn sys_brk(new_brk:usize)->isize{
+    let mut inner=TASK_MANAER.task_que_inner.lock();
+    let memset=&mut inner.current_task().memory_set;
+    memset.brk(new_brk) as isize
+}
+
 ///unmap系统调用
 /// startaddr:usize size:长度
 pub fn sys_unmap(start:usize,size:usize)->isize{
-    let inner=TASK_MANAER.task_que_inner.lock();
-    let current=inner.current;
-    drop(inner);
     let mut inner=TASK_MANAER.task_que_inner.lock();
-    let memset=&mut inner.task_queen[current].memory_set;
+    let memset=&mut inner.current_task().memory_set;
     debug!("SYSCALL_UNMAP:ADDR{:#x} LEN:{}",start,size);
     let resu=memset.unmap_range(VirAddr(start), size);
     //销毁inner,也可以自动销毁
@@ -184,7 +283,10 @@ fn syscall_get_time(addr:*mut TimeVal){  //考虑是否跨页面
       let offset=VirAddr(addr as usize).offset();
       // 获取当前页表的临时视图
       let mut table = PageTable::get_kernel_table_layer();
-      let mut frame_pointer=table.get_mut_byte(VirNumber(vpn)).expect("Big Error!");
+      let mut frame_pointer=match table.get_mut_byte(VirNumber(vpn)) {
+         Some(frame_pointer)=>frame_pointer,
+         None=>return,//地址没有映射
+      };
 
    //判断是否跨页 跨页需要特殊处理
    let len=size_of::<TimeVal>();
@@ -219,9 +321,11 @@ pub fn sys_write(source_buffer: usize, fd_target: usize, buffer_len: usize) -> i
         None => return -1, // 文件描述符不存在
     };
 
-    // 获取当前任务的页表进行地址转换
-    let user_satp = TASK_MANAER.get_current_stap();
-    let buffer = PageTable::get_mut_slice_from_satp(user_satp, buffer_len, VirAddr(source_buffer));
+    // 获取用户缓冲区 地址非法或者不可读返回-EFAULT
+    let buffer = match user_buffer(source_buffer, buffer_len, MapAreaFlags::R) {
+        Some(buffer) => buffer,
+        None => return -EFAULT,
+    };
     
     // 计算总长度并准备写入缓冲区
     let total_len: usize = buffer.iter().map(|slic| slic.len()).sum();
@@ -232,9 +336,12 @@ pub fn sys_write(source_buffer: usize, fd_target: usize, buffer_len: usize) -> i
         write_buffer.extend_from_slice(slice);
     }
 
-    // 使用文件描述符写入
+    // 使用文件描述符写入 写入普通文件后丢弃它的缓存页
     match fd.write(&write_buffer) {
-        Ok(written) => written as isize,
+        Ok(written) => {
+            invalidate_page_cache(&fd.node());
+            written as isize
+        }
         Err(_) => -1,
     }
 }
@@ -247,9 +354,11 @@ pub fn sys_read(source_buffer: usize, fd_target: usize, buffer_len: usize) -> is
         None => return -1, // 文件描述符不存在
     };
 
-    // 获取当前任务的页表进行地址转换
-    let user_satp = TASK_MANAER.get_current_stap();
-    let mut buffer = PageTable::get_mut_slice_from_satp(user_satp, buffer_len, VirAddr(source_buffer));
+    // 获取用户缓冲区 地址非法或者不可写返回-EFAULT，这时不会读文件
+    let mut buffer = match user_buffer(source_buffer, buffer_len, MapAreaFlags::W) {
+        Some(buffer) => buffer,
+        None => return -EFAULT,
+    };
     
     // 计算总缓冲区大小
     let total_len: usize = buffer.iter().map(|slic| slic.len()).sum();
@@ -283,19 +392,8 @@ pub fn sys_exit(exit_code:usize)->isize{
    match exit_code{
       0=>{
          error!("Program Exit Normaly With Code:{}",exit_code);
-         TASK_MANAER.remove_current_task();//移除当前任务块,当前任务块就不存在了
-         // 检查是否还有任务
-         if TASK_MANAER.task_queen_is_empty() {
-            error!("All tasks completed! Shutting down...");
-            shutdown();
-         }
-         // 切换到其他任务，这个函数应该永不返回
-         // 如果返回了，说明出现严重错误（比如只剩一个任务但没被删除）
-         TASK_MANAER.suspend_and_run_task();
-         // 如果执行到这里，说明suspend_and_run_task异常返回了
-         // 这不应该发生，因为我们已经删除了当前任务
-        // panic!("sys_exit: suspend_and_run_task should never return!");
-        -1
+         //移除当前任务块并切换到其他任务或idle，init退出时关机，这个函数永不返回
+         TASK_MANAER.exit_current_and_run_next();
       }
       _=>{
          panic!("Program Exit with code:{}",exit_code);
@@ -303,6 +401,134 @@ pub fn sys_exit(exit_code:usize)->isize{
    }
 }
 
+///reboot系统调用 cmd:REBOOT_CMD_POWER_OFF关机 REBOOT_CMD_RESTART重启
+///成功时永不返回，cmd非法返回-1
+pub fn sys_reboot(cmd:usize)->isize{
+   match cmd {
+      REBOOT_CMD_POWER_OFF=>{
+         error!("Power off requested by user");
+         BlueosFS::sync();
+         shutdown();
+      }
+      REBOOT_CMD_RESTART=>{
+         error!("Reboot requested by user");
+         BlueosFS::sync();
+         reboot();
+      }
+      _=>-1
+   }
+}
+
+///把PRIO_SELF换成当前任务pid
+fn resolve_prio_pid(pid:usize)->usize{
+   if pid==PRIO_SELF { TASK_MANAER.get_current_pid() } else { pid }
+}
+
+///setpriority系统调用 pid:目标任务(PRIO_SELF为当前任务) ticket:新的权重，越大优先级越高
+///init可以任意修改，其他任务只能降低自己的权重 成功返回0，失败返回-1
+pub fn sys_setpriority(pid:usize,ticket:usize)->isize{
+   if !(TASK_TICKET_MIN..=TASK_TICKET_MAX).contains(&ticket){
+      return -1;
+   }
+   let current_pid=TASK_MANAER.get_current_pid();
+   let target_pid=resolve_prio_pid(pid);
+   if current_pid!=INIT_PID{
+      if target_pid!=current_pid{
+         return -1;
+      }
+      match TASK_MANAER.get_ticket(target_pid) {
+         Some(old_ticket) if ticket<=old_ticket=>{}
+         _=>return -1,
+      }
+   }
+   if TASK_MANAER.set_ticket(target_pid, ticket){
+      debug!("pid:{} set ticket of pid:{} to {}",current_pid,target_pid,ticket);
+      0
+   }else {
+      -1
+   }
+}
+
+///getpriority系统调用 pid:目标任务(PRIO_SELF为当前任务) 返回ticket，任务不存在返回-1
+pub fn sys_getpriority(pid:usize)->isize{
+   match TASK_MANAER.get_ticket(resolve_prio_pid(pid)) {
+      Some(ticket)=>ticket as isize,
+      None=>-1
+   }
+}
+
+///把内核数据拷贝到用户空间 可能跨页 地址非法或者不可写时不写入，返回false
+fn copy_to_user(dst:usize,data:&[u8])->bool{
+   let buffer=match user_buffer(dst, data.len(), MapAreaFlags::W) {
+      Some(buffer)=>buffer,
+      None=>return false,
+   };
+   let mut offset=0;
+   for slice in buffer{
+      let slice_len=slice.len();
+      slice.copy_from_slice(&data[offset..offset+slice_len]);
+      offset+=slice_len;
+   }
+   true
+}
+
+///getrusage系统调用 who:RUSAGE_SELF或RUSAGE_CHILDREN usage_ptr:用户RUsage结构地址
+///成功返回0，who非法返回-1，usage_ptr不可写返回-EFAULT
+pub fn sys_getrusage(who:usize,usage_ptr:usize)->isize{
+   let usage=TASK_MANAER.get_current_usage();
+   let rusage=match who {
+      RUSAGE_SELF=>usage.self_rusage(),
+      RUSAGE_CHILDREN=>usage.children_rusage(),
+      _=>return -1
+   };
+   let bytes=unsafe {
+      core::slice::from_raw_parts(&rusage as *const RUsage as *const u8, size_of::<RUsage>())
+   };
+   if !copy_to_user(usage_ptr, bytes){
+      return -EFAULT;
+   }
+   0
+}
+
+///meminfo系统调用 info_ptr:用户MemInfo结构地址 成功返回0，info_ptr不可写返回-EFAULT
+pub fn sys_meminfo(info_ptr:usize)->isize{
+   let info=MemInfo::collect(TASK_MANAER.page_table_frames());
+   let bytes=unsafe {
+      core::slice::from_raw_parts(&info as *const MemInfo as *const u8, size_of::<MemInfo>())
+   };
+   if !copy_to_user(info_ptr, bytes){
+      return -EFAULT;
+   }
+   0
+}
+
+///maps系统调用 pid:目标任务(PRIO_SELF为当前任务) buf:用户MapInfo数组 count:数组长度
+///最多写入count个，返回area总数，pid不存在返回-1，buf不可写返回-EFAULT
+pub fn sys_maps(pid:usize,buf:usize,count:usize)->isize{
+   let infos=match TASK_MANAER.get_maps(resolve_prio_pid(pid)) {
+      Some(infos)=>infos,
+      None=>return -1
+   };
+   let copied=infos.len().min(count);
+   if copied>0{
+      let bytes=unsafe {
+         core::slice::from_raw_parts(infos.as_ptr() as *const u8, copied*size_of::<MapInfo>())
+      };
+      if !copy_to_user(buf, bytes){
+         return -EFAULT;
+      }
+   }
+   infos.len() as isize
+}
+
+///wait系统调用 等待任意一个子任务退出 返回子任务pid，没有子任务返回-1
+pub fn sys_wait()->isize{
+   match TASK_MANAER.wait_child() {
+      Some(pid)=>pid as isize,
+      None=>-1
+   }
+}
+
 ///主动放弃cpu 任务调度型返回-1 
 pub fn sys_yield()->isize{
    TASK_MANAER.suspend_and_run_task();
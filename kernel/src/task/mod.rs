@@ -1,14 +1,17 @@
 mod task;
 mod process;
+mod scheduler;
+mod rusage;
 use crate::config::*;
-use alloc::vec::Vec;
+use alloc::sync::Arc;
+use BlueosFS::VfsNodeOps;
 use alloc::format;
 use log::debug;
 
-/// 文件加载器，根据 app_id 从文件系统 /test 目录加载对应的 ELF 文件
-/// app_id 从 0 开始
-pub fn file_loader(app_id: usize) -> Vec<u8> {
-    use BlueosFS::read_file;
+/// 文件加载器，根据 app_id 从文件系统 /test 目录打开对应的 ELF 文件
+/// app_id 从 0 开始 返回文件节点，段内容在缺页时按需读取
+pub fn file_loader(app_id: usize) -> Arc<dyn VfsNodeOps> {
+    use BlueosFS::{open, FileFlags};
     
     // 应用文件名列表（对应 app.asm 中的顺序）
     let app_names = [
@@ -33,10 +36,10 @@ pub fn file_loader(app_id: usize) -> Vec<u8> {
     
     let file_path = format!("/test/{}", app_name);
     
-    match read_file(&file_path) {
-        Ok(data) => {
-            debug!("Loading app {} ({}) from {} with size {} bytes", app_id, app_name, file_path, data.len());
-            data
+    match open(&file_path, FileFlags::read_only()) {
+        Ok(file) => {
+            debug!("Loading app {} ({}) from {} with size {} bytes", app_id, app_name, file_path, file.size());
+            file.node()
         }
         Err(e) => {
             panic!("Failed to load app {} from {}: {:?}", app_id, file_path, e);
@@ -49,4 +52,7 @@ pub fn get_app_count() -> usize {
     get_app_num()
 }
 
-pub use task::*;
\ No newline at end of file
+pub use task::*;
+pub use process::*;
+pub use scheduler::init_sched_policy;
+pub use rusage::*;
\ No newline at end of file
@@ -1,18 +1,78 @@
 ///
 /// 进程管理调度
+/// 每个hart一个处理器状态，持有idle上下文，没有READY任务时在idle里wfi等待中断
 
+use core::arch::{asm, global_asm};
+use lazy_static::lazy_static;
+use log::trace;
+use crate::sync::UPSafeCell;
+use crate::task::{TaskContext, TASK_MANAER};
+use crate::time::set_next_timeInterupt;
+use crate::trap::set_kernel_forbid;
+use riscv::register::{scause::{self, Interrupt, Trap}, sstatus, stvec, utvec::TrapMode};
+global_asm!(include_str!("_idle_trap.S"));
 
-use alloc::sync::Arc;
-use crate::task::TaskControlBlock;
-
-
+extern "C" {
+    fn __idle_trap();
+}
 
 
 
  /**
-  * 进程管理器
+  * 处理器状态
   */
-struct Processer{
-    task:Arc<TaskControlBlock>,
-    current_task:usize
-}
\ No newline at end of file
+pub struct Processer{
+    ///idle上下文，运行在启动栈上，第一次调度时由run_first_task保存
+    idle_task_cx:TaskContext,
+}
+
+impl Processer {
+    pub fn new()->Self{
+        Processer { idle_task_cx: TaskContext::zero_init() }
+    }
+}
+
+lazy_static!{
+    ///单核环境只有一个hart
+    pub static ref PROCESSER:UPSafeCell<Processer> = UPSafeCell::new(Processer::new());
+}
+
+///获取idle上下文指针 用于__switch，指针在切换前获取，不持有借用
+pub fn get_idle_task_cx_ptr()->*mut TaskContext{
+    let mut processer = PROCESSER.lock();
+    &mut processer.idle_task_cx as *mut TaskContext
+}
+
+///idle循环 有READY任务就切换过去，否则wfi等待中断唤醒
+pub fn idle_loop()->!{
+    loop {
+        if !TASK_MANAER.run_ready_task_from_idle(){
+            wait_for_interrupt();
+        }
+    }
+}
+
+///开中断执行wfi等待中断 中断由__idle_trap处理后回到wfi之后
+///离开idle前关中断并恢复内核trap入口，任务的内核态仍然不开中断
+fn wait_for_interrupt(){
+    trace!("idle: wfi");
+    unsafe {
+        stvec::write(__idle_trap as usize, TrapMode::Direct);
+        sstatus::set_sie();
+        asm!("wfi");
+        sstatus::clear_sie();
+    }
+    set_kernel_forbid();
+}
+
+///idle开中断后的trap处理 只处理时钟中断
+#[no_mangle]
+extern "C" fn idle_trap_handler(){
+    match scause::read().cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer)=>{
+            //重设下一次时钟中断来清除STIP
+            set_next_timeInterupt();
+        }
+        cause=>panic!("UnSupport Idle Trap: {:?}",cause),
+    }
+}
@@ -0,0 +1,91 @@
+///
+/// 任务资源使用统计
+/// 时间以时钟tick计，在trap进出和任务切换时打时间戳
+
+use crate::config::CPU_CIRCLE;
+
+const USEC:usize=1_000_000;
+
+///getrusage返回给用户的结构 与user_lib保持一致
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct RUsage{
+    pub utime_us:usize,     //用户态时间（微秒）
+    pub stime_us:usize,     //内核态时间（微秒）
+    pub nvcsw:usize,        //主动让出次数
+    pub nivcsw:usize,       //被抢占次数
+    pub minflt:usize,       //缺页次数
+}
+
+///单个任务的资源使用
+#[derive(Clone, Default)]
+pub struct TaskUsage{
+    pub user_ticks:usize,           //用户态时间
+    pub system_ticks:usize,         //内核态时间
+    pub voluntary_switches:usize,   //主动让出次数
+    pub involuntary_switches:usize, //被抢占次数
+    pub page_faults:usize,          //缺页次数
+    pub children_user_ticks:usize,  //已回收子任务的用户态时间
+    pub children_system_ticks:usize,//已回收子任务的内核态时间
+    last_timestamp:usize,           //上一次进出内核或切换的时间
+}
+
+impl TaskUsage {
+    ///trap进入内核 之前的时间算用户态
+    pub fn trap_enter(&mut self,now:usize){
+        self.user_ticks+=now.saturating_sub(self.last_timestamp);
+        self.last_timestamp=now;
+    }
+
+    ///trap返回用户态 之前的时间算内核态
+    pub fn trap_exit(&mut self,now:usize){
+        self.system_ticks+=now.saturating_sub(self.last_timestamp);
+        self.last_timestamp=now;
+    }
+
+    ///切换出去 目前只在内核态切换，之前的时间算内核态
+    pub fn switch_out(&mut self,now:usize,voluntary:bool){
+        self.trap_exit(now);
+        if voluntary{
+            self.voluntary_switches+=1;
+        }else {
+            self.involuntary_switches+=1;
+        }
+    }
+
+    ///切换回来 不在cpu上的时间不计
+    pub fn switch_in(&mut self,now:usize){
+        self.last_timestamp=now;
+    }
+
+    ///本任务的统计
+    pub fn self_rusage(&self)->RUsage{
+        RUsage {
+            utime_us: ticks_to_us(self.user_ticks),
+            stime_us: ticks_to_us(self.system_ticks),
+            nvcsw: self.voluntary_switches,
+            nivcsw: self.involuntary_switches,
+            minflt: self.page_faults,
+        }
+    }
+
+    ///已回收子任务的统计 只累计时间
+    pub fn children_rusage(&self)->RUsage{
+        RUsage {
+            utime_us: ticks_to_us(self.children_user_ticks),
+            stime_us: ticks_to_us(self.children_system_ticks),
+            ..Default::default()
+        }
+    }
+
+    ///回收子任务，把子任务和它的子任务的时间累加进来
+    pub fn absorb_child(&mut self,child:&TaskUsage){
+        self.children_user_ticks+=child.user_ticks+child.children_user_ticks;
+        self.children_system_ticks+=child.system_ticks+child.children_system_ticks;
+    }
+}
+
+///tick转换为微秒
+pub fn ticks_to_us(ticks:usize)->usize{
+    (ticks as u128*USEC as u128/CPU_CIRCLE as u128) as usize
+}
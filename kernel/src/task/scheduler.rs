@@ -0,0 +1,268 @@
+///
+/// 可替换的调度器
+/// 调度器只按pid管理就绪任务，任务本身保存在TaskManager中
+/// 每个任务的调度状态保存在SchedEntity里，由TaskManager在调用时传入
+
+use alloc::boxed::Box;
+use alloc::collections::{BinaryHeap, VecDeque};
+use core::cmp::{Ordering, Reverse};
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use crate::config::*;
+use crate::sync::UPSafeCell;
+
+///调度实体 每个任务一个，保存各个调度算法需要的状态
+#[derive(Clone)]
+pub struct SchedEntity{
+    pub ticket:usize,       //权重
+    pub stride:usize,       //步长
+    pub pass:usize,         //行程
+    pub level:usize,        //MLFQ队列层级
+    pub slice_used:usize,   //MLFQ当前层级已用的时间片
+    pub epoch:usize,        //MLFQ优先级提升周期
+}
+
+impl SchedEntity {
+    pub fn new(ticket:usize)->Self{
+        SchedEntity { ticket, stride: BIG_INT / ticket, pass: 0, level: 0, slice_used: 0, epoch: 0 }
+    }
+
+    ///修改权重并重新计算步长 ticket范围由调用者检查
+    pub fn set_ticket(&mut self,ticket:usize){
+        self.ticket=ticket;
+        self.stride=BIG_INT / ticket;
+    }
+}
+
+///行程 允许回绕，按有符号差值比较
+///就绪任务之间的行程差不超过最大步长BIG_INT，远小于isize::MAX，所以比较是全序
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Pass(pub usize);
+
+impl Ord for Pass {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.0.wrapping_sub(other.0) as isize).cmp(&0)
+    }
+}
+
+impl PartialOrd for Pass {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+///调度器接口
+pub trait Scheduler: Send {
+    ///调度器名称
+    fn name(&self)->&'static str;
+    ///任务变为READY，加入就绪队列
+    fn enqueue(&mut self,pid:usize,entity:&mut SchedEntity);
+    ///取出下一个要运行的任务pid
+    fn pick_next(&mut self)->Option<usize>;
+    ///时钟中断，pid为当前运行任务 返回true表示需要抢占
+    fn on_tick(&mut self,pid:usize,entity:&mut SchedEntity)->bool;
+    ///任务阻塞或退出，从就绪队列移除
+    fn on_block(&mut self,pid:usize);
+}
+
+///Stride调度 就绪队列为按pass排序的小根堆
+pub struct StrideScheduler{
+    ready:BinaryHeap<Reverse<(Pass,usize)>>,//(pass,pid)
+    min_pass:Pass,  //最近一次选中的行程，即就绪任务行程的下界
+}
+
+impl StrideScheduler {
+    pub fn new()->Self{
+        StrideScheduler { ready: BinaryHeap::new(), min_pass: Pass(0) }
+    }
+}
+
+impl Scheduler for StrideScheduler {
+    fn name(&self)->&'static str {
+        "stride"
+    }
+
+    ///入队时累加行程，相当于为上一次运行计费
+    ///长时间阻塞或新建的任务行程落后时追到下界，避免独占cpu和行程差过大导致比较出错
+    fn enqueue(&mut self,pid:usize,entity:&mut SchedEntity) {
+        if Pass(entity.pass)<self.min_pass{
+            entity.pass=self.min_pass.0;
+        }
+        entity.pass=entity.pass.wrapping_add(entity.stride);
+        self.ready.push(Reverse((Pass(entity.pass),pid)));
+    }
+
+    fn pick_next(&mut self)->Option<usize> {
+        let Reverse((pass,pid))=self.ready.pop()?;
+        self.min_pass=pass;
+        Some(pid)
+    }
+
+    ///每个时间片都重新比较行程
+    fn on_tick(&mut self,_pid:usize,_entity:&mut SchedEntity)->bool {
+        true
+    }
+
+    fn on_block(&mut self,pid:usize) {
+        self.ready.retain(|Reverse((_,ready_pid))|*ready_pid!=pid);
+    }
+}
+
+///时间片轮转调度
+pub struct RoundRobinScheduler{
+    ready:VecDeque<usize>,
+}
+
+impl RoundRobinScheduler {
+    pub fn new()->Self{
+        RoundRobinScheduler { ready: VecDeque::new() }
+    }
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn name(&self)->&'static str {
+        "rr"
+    }
+
+    fn enqueue(&mut self,pid:usize,_entity:&mut SchedEntity) {
+        self.ready.push_back(pid);
+    }
+
+    fn pick_next(&mut self)->Option<usize> {
+        self.ready.pop_front()
+    }
+
+    ///时间片为一个时钟中断
+    fn on_tick(&mut self,_pid:usize,_entity:&mut SchedEntity)->bool {
+        true
+    }
+
+    fn on_block(&mut self,pid:usize) {
+        self.ready.retain(|ready_pid|*ready_pid!=pid);
+    }
+}
+
+///多级反馈队列 用完时间片降级，主动让出保持层级，周期性全部提升到最高层
+pub struct MlfqScheduler{
+    queues:[VecDeque<usize>;MLFQ_LEVELS],
+    ticks:usize,    //时钟中断计数
+    epoch:usize,    //提升周期，实体的epoch落后时重置为最高层
+}
+
+impl MlfqScheduler {
+    pub fn new()->Self{
+        MlfqScheduler { queues: Default::default(), ticks: 0, epoch: 0 }
+    }
+
+    ///发生过提升的实体回到最高层
+    fn refresh(&self,entity:&mut SchedEntity){
+        if entity.epoch!=self.epoch{
+            entity.epoch=self.epoch;
+            entity.level=0;
+            entity.slice_used=0;
+        }
+    }
+
+    ///把所有就绪任务提升到最高层，防止低层任务饥饿
+    fn boost(&mut self){
+        self.epoch+=1;
+        for level in 1..MLFQ_LEVELS{
+            while let Some(pid)=self.queues[level].pop_front(){
+                self.queues[0].push_back(pid);
+            }
+        }
+        debug!("MLFQ boost, epoch:{}",self.epoch);
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn name(&self)->&'static str {
+        "mlfq"
+    }
+
+    fn enqueue(&mut self,pid:usize,entity:&mut SchedEntity) {
+        self.refresh(entity);
+        self.queues[entity.level].push_back(pid);
+    }
+
+    fn pick_next(&mut self)->Option<usize> {
+        self.queues.iter_mut().find_map(|queue|queue.pop_front())
+    }
+
+    fn on_tick(&mut self,_pid:usize,entity:&mut SchedEntity)->bool {
+        self.ticks+=1;
+        if self.ticks%MLFQ_BOOST_INTERVAL==0{
+            self.boost();
+        }
+        self.refresh(entity);
+        entity.slice_used+=1;
+        if entity.slice_used<MLFQ_TIME_SLICE[entity.level]{
+            return false;
+        }
+        //时间片用完，降级
+        entity.slice_used=0;
+        if entity.level+1<MLFQ_LEVELS{
+            entity.level+=1;
+        }
+        true
+    }
+
+    fn on_block(&mut self,pid:usize) {
+        for queue in self.queues.iter_mut(){
+            queue.retain(|ready_pid|*ready_pid!=pid);
+        }
+    }
+}
+
+///调度策略 启动时通过bootargs的sched=选择
+#[derive(Clone, Copy, Debug)]
+pub enum SchedPolicy {
+    Stride,
+    RoundRobin,
+    Mlfq,
+}
+
+impl SchedPolicy {
+    ///按名称解析 stride|rr|mlfq
+    pub fn from_name(name:&str)->Option<Self>{
+        match name {
+            "stride"=>Some(SchedPolicy::Stride),
+            "rr"=>Some(SchedPolicy::RoundRobin),
+            "mlfq"=>Some(SchedPolicy::Mlfq),
+            _=>{
+                warn!("Unknown scheduler:{}, use default",name);
+                None
+            }
+        }
+    }
+
+    ///从内核命令行解析 sched=stride|rr|mlfq
+    pub fn from_bootargs(bootargs:&str)->Option<Self>{
+        let name=bootargs.split_whitespace().find_map(|arg|arg.strip_prefix("sched="))?;
+        Self::from_name(name)
+    }
+
+    ///创建对应的调度器
+    pub fn create_scheduler(&self)->Box<dyn Scheduler>{
+        match self {
+            SchedPolicy::Stride=>Box::new(StrideScheduler::new()),
+            SchedPolicy::RoundRobin=>Box::new(RoundRobinScheduler::new()),
+            SchedPolicy::Mlfq=>Box::new(MlfqScheduler::new()),
+        }
+    }
+}
+
+lazy_static!{
+    ///启动时选择的调度策略 必须在TASK_MANAER初始化前设置
+    pub static ref SCHED_POLICY:UPSafeCell<SchedPolicy> = UPSafeCell::new(
+        option_env!("BLUESTAR_SCHED").and_then(SchedPolicy::from_name).unwrap_or(SchedPolicy::Stride)
+    );
+}
+
+///根据内核命令行设置调度策略 没有sched=参数时使用编译时的BLUESTAR_SCHED，默认stride
+pub fn init_sched_policy(bootargs:Option<&str>){
+    if let Some(policy)=bootargs.and_then(SchedPolicy::from_bootargs){
+        *SCHED_POLICY.lock()=policy;
+    }
+    debug!("Scheduler policy:{:?}",*SCHED_POLICY.lock());
+}
@@ -1,13 +1,15 @@
 use core::arch::global_asm;
 use core::panicking::panic;
 
-use alloc::collections::vec_deque::VecDeque;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::collections::btree_map::Entry;
 use alloc::sync::Arc;
 use alloc::sync::Weak;
 use alloc::vec;
 use alloc::vec::Vec;
 use lazy_static::lazy_static;
-use log::error;
+use log::{error, warn};
 use log::trace;
 use riscv::register::sstatus;
 use riscv::register::sstatus::SPP;
@@ -18,6 +20,10 @@ use BlueosFS::{FileDescriptor, FileFlags};
 use crate::memory::*;
 use crate::sbi::shutdown;
 use crate::task::file_loader;
+use crate::task::{get_idle_task_cx_ptr, idle_loop};
+use crate::task::scheduler::{SchedEntity, Scheduler, SCHED_POLICY};
+use crate::task::TaskUsage;
+use crate::time::get_time_tick;
 use log::debug;
 use crate::trap::{app_entry_point, kernel_trap_handler};
 ///任务上下文
@@ -44,7 +50,7 @@ enum TaskStatus {
 
 
 ///进程id 需要实现回收 rail自动分配
-#[derive(Clone)]
+///drop时pid回收，句柄只能由TCB持有，不能复制
 pub struct  ProcessId(pub usize);
 
 ///进程id分配器 需要实现分配 [start,end)
@@ -54,18 +60,17 @@ pub struct ProcessIdAlloctor{
     id_pool:Vec<ProcessId>
 }
 
-#[derive(Clone)]
 pub struct TaskControlBlock{
         pub pid:ProcessId,                              //进程id
         pub memory_set:MapSet,                          //程序地址空间
         task_statut:TaskStatus,                         //程序运行状态
         task_context:TaskContext,                       //任务上下文
         trap_context_ppn:usize,                         //陷阱上下文物理帧
-        pass:usize,                                     //行程
-        stride:usize,                                   //步长
-        ticket:usize,                                   //权重
-        file_descriptor:Vec<Arc<FileDescriptor>>,       //文件描述符表
-       // parent:Weak<TaskControlBlock>,                  //父进程弱引用
+        sched:SchedEntity,                              //调度状态 行程/步长/权重等
+        usage:TaskUsage,                                //资源使用统计
+        file_descriptor:Vec<Option<Arc<FileDescriptor>>>,//文件描述符表 关闭的fd为None
+        parent:Option<usize>,                           //父任务pid init没有父任务
+        exited_children:Vec<(usize,TaskUsage)>,         //已经退出、还没有被wait的子任务pid和它的资源使用
        // childrens:Vec<Arc<TaskControlBlock>>            //子进程强引用
 }
 
@@ -80,8 +85,9 @@ pub struct TaskControlBlockInner{
 
 
 pub struct TaskManagerInner{
-    pub task_queen:VecDeque<TaskControlBlock>,//任务队列
-    pub current:usize//当前任务
+    pub task_map:BTreeMap<usize,TaskControlBlock>,//任务表 pid->任务
+    pub current:usize,//当前任务pid
+    scheduler:Box<dyn Scheduler>,//启动时选择的调度器
 }
 
 ///任务管理器
@@ -146,11 +152,12 @@ impl TaskControlBlock {
     /// 创建新任务
     /// app_id: 应用程序ID（从0开始，用于加载不同的ELF文件）
     /// kernel_stack_id: 内核栈ID（从1开始，用于分配不同的内核栈空间）
-    fn new(app_id: usize, kernel_stack_id: usize) -> Self {
+    /// 地址空间分配不到物理页帧时返回错误
+    fn new(app_id: usize, kernel_stack_id: usize) -> Result<Self,MemoryError> {
         debug!("Creating task for app_id: {}, kernel_stack_id: {}", app_id, kernel_stack_id);
         
-        let elf_data = file_loader(app_id);
-        let (mut memset, elf_entry, user_sp, kernel_sp) = MapSet::from_elf(kernel_stack_id, &elf_data);
+        let elf_file = file_loader(app_id);
+        let (mut memset, elf_entry, user_sp, kernel_sp) = MapSet::from_elf(kernel_stack_id, elf_file)?;
         let task_cx = TaskContext::return_trap_new(kernel_sp);
         let kernel_satp = KERNEL_SPACE.lock().table.satp_token();
         let trap_cx_ppn = memset.table
@@ -158,27 +165,30 @@ impl TaskControlBlock {
             .expect("trap ppn translate failed");
         
         // 初始化文件描述符表：0=stdin, 1=stdout
-        let mut file_descriptor_table: Vec<Arc<FileDescriptor>> = Vec::new();
-        file_descriptor_table.push(Arc::new(FileDescriptor::new(
+        let mut file_descriptor_table: Vec<Option<Arc<FileDescriptor>>> = Vec::new();
+        file_descriptor_table.push(Some(Arc::new(FileDescriptor::new(
             Arc::new(Stdin),
             FileFlags::read_only()
-        )));
-        file_descriptor_table.push(Arc::new(FileDescriptor::new(
+        ))));
+        file_descriptor_table.push(Some(Arc::new(FileDescriptor::new(
             Arc::new(Stdout),
             FileFlags::write_only()
-        )));
+        ))));
         
+        let pid=ProcessId_ALLOCTOR.lock().alloc_id().expect("No Process ID Can use");
+        //启动时加载的任务都是init的子任务
+        let parent=if pid.0==INIT_PID {None} else {Some(INIT_PID)};
         let task_control_block = TaskControlBlock {
-            pid:ProcessId_ALLOCTOR.lock().alloc_id().expect("No Process ID Can use"),
+            pid,
             memory_set: memset,
             task_statut: TaskStatus::Ready,
             task_context: task_cx,
             trap_context_ppn: trap_cx_ppn.0,
-            pass: 0,
-            stride: BIG_INT / TASK_TICKET,
-            ticket: TASK_TICKET,
+            sched: SchedEntity::new(TASK_TICKET),
+            usage: TaskUsage::default(),
             file_descriptor: file_descriptor_table,
-          //  parent:
+            parent,
+            exited_children: Vec::new(),
         };
         
         // 初始化 TrapContext
@@ -194,50 +204,163 @@ impl TaskControlBlock {
         }
         
         debug!("Task created successfully: entry={:#x}, user_sp={:#x}", elf_entry, user_sp.0);
-        task_control_block
+        Ok(task_control_block)
     }
 }
 
 
-impl TaskManager {//全局唯一
-    ///添加任务队列或者归队
-    pub fn add_task(self,task:TaskControlBlock){
-        self.task_que_inner.lock().task_queen.push_back(task);
+impl TaskManagerInner {
+    ///当前任务的可变引用
+    pub fn current_task(&mut self)->&mut TaskControlBlock{
+        self.task_map.get_mut(&self.current).expect("Current task not found!")
     }
-    ///从队列移除当前任务,应该由aplication的exit系统调用来执行 之后必须执行下一个任务 bug修复：应该同时移动指针到任意一个ready的任务
-    pub fn remove_current_task(&self){
+
+    ///pid对应任务的可变引用
+    pub fn task_mut(&mut self,pid:usize)->Option<&mut TaskControlBlock>{
+        self.task_map.get_mut(&pid)
+    }
+
+    ///任务变为READY并交给调度器
+    fn make_ready(&mut self,pid:usize){
+        let task=self.task_map.get_mut(&pid).expect("Ready task not found!");
+        task.task_statut=TaskStatus::Ready;
+        self.scheduler.enqueue(pid, &mut task.sched);
+    }
+
+    ///从调度器挑选下一个任务，标记为运行并设为当前任务 返回它的上下文指针
+    fn pick_and_run(&mut self)->Option<*mut TaskContext>{
+        let pid=self.scheduler.pick_next()?;
+        let task=self.task_map.get_mut(&pid).expect("Picked task not found!");
+        task.task_statut=TaskStatus::Runing;
+        task.usage.switch_in(get_time_tick());
+        let task_cx=&mut task.task_context as *mut TaskContext;
+        self.current=pid;
+        Some(task_cx)
+    }
+
+    ///从任务表移除任务并释放它的地址空间
+    fn remove_task(&mut self,pid:usize){
+        debug!("Removing task pid: {}, task count before removal: {}", pid, self.task_map.len());
+        self.scheduler.on_block(pid);
+        let mut task=self.task_map.remove(&pid).expect("Remove Task Control Block Failed!");
+        //MAP_SHARED映射写回文件
+        task.memory_set.sync_all_shared();
+        //资源使用留给父任务wait时累加，父任务已经不在时算孤儿，交给init
+        //自己还没有wait的子任务也交给init回收
+        task.usage.trap_exit(get_time_tick());
+        let parent=task.parent.filter(|ppid|self.task_map.contains_key(ppid)).unwrap_or(INIT_PID);
+        if let Some(parent_task)=self.task_mut(parent){
+            parent_task.exited_children.push((pid,core::mem::take(&mut task.usage)));
+        }
+        //父任务可能阻塞在wait里
+        self.wakeup(parent);
+        if !task.exited_children.is_empty(){
+            if let Some(init)=self.task_mut(INIT_PID){
+                init.exited_children.append(&mut task.exited_children);
+            }
+            self.wakeup(INIT_PID);
+        }
+        //退出任务的子任务交给init
+        for child in self.task_map.values_mut().filter(|child|child.parent==Some(pid)){
+            child.parent=Some(INIT_PID);
+        }
+    }
+
+    ///唤醒阻塞的任务 pid不存在或者没有阻塞时忽略
+    fn wakeup(&mut self,pid:usize){
+        if let Some(task)=self.task_map.get(&pid){
+            if let TaskStatus::Blocking=task.task_statut{
+                self.make_ready(pid);
+            }
+        }
+    }
+
+    ///空闲页帧低于水位时换出匿名页 在缺页等需要分配页帧的路径上调用
+    pub fn reclaim_frames(&mut self){
+        if !swap_enabled(){
+            return;
+        }
+        let mut reclaimed=0;
+        while reclaimed<SWAP_RECLAIM_BATCH && free_frame_count()<SWAP_LOW_WATERMARK{
+            if !self.swap_out_one(){
+                warn!("swap out failed, free frames:{}",free_frame_count());
+                break;
+            }
+            reclaimed+=1;
+        }
+    }
+
+    ///时钟算法换出一页 指针(pid,vpn)按任务和地址顺序推进
+    ///扫描两圈：第一圈清除访问位，第二圈一定能找到没有被访问的页
+    fn swap_out_one(&mut self)->bool{
+        let (hand_pid,hand_vpn)=swap_hand();
+        let pids:Vec<usize>=self.task_map.range(hand_pid..).chain(self.task_map.range(..hand_pid)).map(|(pid,_)|*pid).collect();
+        let round_len=pids.len();
+        for (index,pid) in pids.iter().cycle().take(round_len*2+1).enumerate(){
+            let from=if index==0 {hand_vpn} else {VirNumber(0)};
+            let memset=&mut self.task_map.get_mut(pid).expect("Swap task not found!").memory_set;
+            if let Some(victim)=memset.clock_select(from){
+                if !memset.swap_out(victim){
+                    return false;
+                }
+                set_swap_hand(*pid, VirNumber(victim.0+1));
+                return true;
+            }
+        }
+        false
+    }
+}
+
+
+impl TaskManager {//全局唯一
+    ///添加新任务并交给调度器
+    pub fn add_task(&self,task:TaskControlBlock){
         let mut inner=self.task_que_inner.lock();
-        
-        // 先保存要删除的任务索引
-        let task_to_remove = inner.current;
-        debug!("Removing task at index: {}, queue length before removal: {}", task_to_remove, inner.task_queen.len());
-        
-        // 删除任务
-        inner.task_queen.remove(task_to_remove).expect("Remove Task Control Block Failed!");
-        
-        // 删除后更新current指针
-        // VecDeque.remove(i) 会删除索引i的元素，后面的元素索引都会减1
-        // 删除后，如果还有任务，我们需要将current设置为一个有效的任务索引
-        if !inner.task_queen.is_empty() {
-            // 如果删除的是最后一个任务（task_to_remove == 原队列长度-1）
-            // 则删除后 task_to_remove >= 新队列长度，需要回绕到开头
-            if task_to_remove >= inner.task_queen.len() {
-                inner.current = 0;
-            } else {
-                // 否则，保持current在原位置
-                // 此时current指向的是原来task_to_remove+1位置的任务
-                inner.current = task_to_remove;
+        let pid=task.pid.0;
+        //pid句柄只在TCB里，重复说明pid被提前回收了
+        match inner.task_map.entry(pid) {
+            Entry::Vacant(entry)=>{
+                entry.insert(task);
             }
-            debug!("After removal: current set to {}, queue length: {}", inner.current, inner.task_queen.len());
+            Entry::Occupied(_)=>panic!("Duplicate pid:{} in task map!",pid),
         }
-        
-        drop(inner);
-        if self.task_queen_is_empty() {
-            panic!("Remove Last Task");
+        inner.make_ready(pid);
+    }
+    ///从任务表移除当前任务,应该由aplication的exit系统调用来执行 之后必须执行下一个任务
+    pub fn remove_current_task(&self){
+        let mut inner=self.task_que_inner.lock();
+        let pid=inner.current;
+        inner.remove_task(pid);
+        //任务表为空时由idle等待
+    }
+
+    ///物理内存耗尽时杀掉常驻页帧最多的任务 init只有在没有其他任务时才会被选中
+    ///选中当前任务时不会返回，调用者必须已经释放task_que_inner
+    pub fn oom_kill(&self){
+        let mut inner=self.task_que_inner.lock();
+        let victim=inner.task_map.iter()
+            .max_by_key(|(pid,task)|(**pid!=INIT_PID,task.memory_set.resident_frames()))
+            .map(|(pid,task)|(*pid,task.memory_set.resident_frames()));
+        let (pid,frames)=match victim {
+            Some(victim)=>victim,
+            None=>panic!("Out of memory with no task to kill!"),
+        };
+        error!("Out of memory, kill pid:{} resident frames:{} free frames:{}",pid,frames,free_frame_count());
+        if pid==inner.current{
+            drop(inner);
+            self.kail_current_task_and_run_next();
+        }else {
+            inner.remove_task(pid);
         }
     }
-    ///根据Stride挑选下个要运行的READY任务,挂起当前任务,把current设置为下个任务的index,然后运行下一个任务 Stride算法：增加运行任务的步长
-    pub fn suspend_and_run_task(&self){ //首先应该检查任务是否为空
+
+    ///挂起当前任务,由调度器挑选下个要运行的READY任务,把current设置为下个任务的pid,然后运行下一个任务
+    pub fn suspend_and_run_task(&self){
+        self.suspend_current(true);
+    }
+
+    ///挂起当前任务并调度 voluntary:主动让出还是被抢占
+    fn suspend_current(&self,voluntary:bool){ //首先应该检查任务是否为空
 
         //任务列表是否为空?
         if self.task_queen_is_empty(){
@@ -247,49 +370,37 @@ impl TaskManager {//全局唯一
 
         let mut inner  =self.task_que_inner.lock();
         let current=inner.current;
-        //标记当前任务为BLOCK
-        inner.task_queen[current].task_statut=TaskStatus::Ready;
-
-        let task_index=match inner.task_queen.
-        iter().
-        enumerate().
-        filter(|(_,block)|{if let TaskStatus::Ready=block.task_statut {true}else {
-            false
-        }}).
-        min_by_key(|(_,block)|{
-            block.pass
-        }){
-            Some((index,_))=>{
-                index//返回任务的下标索引
-            }
+        //运行中的任务挂起为READY，阻塞的任务保持阻塞等待唤醒
+        if let TaskStatus::Runing=inner.current_task().task_statut{
+            inner.make_ready(current);
+        }
+        let swaped_task_cx=&inner.current_task().task_context as *const TaskContext;
+
+        let need_swap_in=match inner.pick_and_run(){
+            Some(task_cx)=>task_cx,
             None=>{
-                error!("No task can select");
-                shutdown();
-                
+                //没有READY任务，切换到idle等待中断唤醒
+                debug!("No task ready, switch to idle");
+                inner.task_mut(current).expect("Current task not found!").usage.switch_out(get_time_tick(), voluntary);
+                drop(inner);
+                unsafe {
+                    __switch(swaped_task_cx, get_idle_task_cx_ptr());
+                }
+                return;
             }
         };
         
-        debug!("current:{} Next task:{}",inner.current,task_index);
+        debug!("current:{} Next task:{}",current,inner.current);
         
         //如果切换到同一个任务，直接返回 _switch耗费上下文资源
         //这可以防止在持有用户态锁时发生任务切换导致的死锁问题（全局锁）
-        if current == task_index {
-            // 重新标记为运行状态，增加步长
-            inner.task_queen[task_index].task_statut = TaskStatus::Runing;
-            inner.task_queen[task_index].pass += inner.task_queen[task_index].stride;
+        if current == inner.current {
             drop(inner);
             debug!("Same task, skip __switch");
             return; // 直接返回，不需要切换
         }
         
-        //标记这个任务为run
-        let swaped_task_cx=&inner.task_queen[current].task_context as *const TaskContext;
-        let task=&mut inner.task_queen[task_index];
-        task.task_statut=TaskStatus::Runing;
-        //增加步长
-        task.pass+=task.stride;
-        let need_swap_in = &mut task.task_context as *mut TaskContext;
-        inner.current=task_index;//更新任务指针
+        inner.task_mut(current).expect("Current task not found!").usage.switch_out(get_time_tick(), voluntary);
         drop(inner);//drop inner
         unsafe {
          __switch(swaped_task_cx , need_swap_in);
@@ -298,44 +409,179 @@ impl TaskManager {//全局唯一
         //任务从这里返回
     }
 
+    ///时钟中断 调度器决定是否抢占当前任务
+    pub fn on_tick(&self){
+        let mut inner=self.task_que_inner.lock();
+        let pid=inner.current;
+        let TaskManagerInner{task_map,scheduler,..}=&mut *inner;
+        let task=task_map.get_mut(&pid).expect("Current task not found!");
+        let need_preempt=scheduler.on_tick(pid, &mut task.sched);
+        drop(inner);
+        if need_preempt{
+            self.suspend_current(false);
+        }
+    }
+
+    ///阻塞当前任务并运行下一个任务，需要由wakeup_task唤醒
+    pub fn block_current_and_run_next(&self){
+        let mut inner=self.task_que_inner.lock();
+        let pid=inner.current;
+        inner.current_task().task_statut=TaskStatus::Blocking;
+        inner.scheduler.on_block(pid);
+        drop(inner);
+        self.suspend_and_run_task();
+    }
+
+    ///唤醒阻塞的任务 pid不存在或者没有阻塞时忽略
+    pub fn wakeup_task(&self,pid:usize){
+        self.task_que_inner.lock().wakeup(pid);
+    }
+
+    ///等待当前任务的任意一个子任务退出，返回它的pid 没有子任务返回None
+    ///子任务都还在运行时阻塞，子任务退出时唤醒 回收时子任务的时间累加到当前任务
+    pub fn wait_child(&self)->Option<usize>{
+        loop {
+            let mut inner=self.task_que_inner.lock();
+            let pid=inner.current;
+            let task=inner.current_task();
+            if let Some((child,usage))=task.exited_children.pop(){
+                task.usage.absorb_child(&usage);
+                return Some(child);
+            }
+            if !inner.task_map.values().any(|task|task.parent==Some(pid)){
+                return None;
+            }
+            drop(inner);
+            self.block_current_and_run_next();
+        }
+    }
+
+    ///从用户态陷入内核时调用 统计用户态时间
+    pub fn trap_enter(&self){
+        self.task_que_inner.lock().current_task().usage.trap_enter(get_time_tick());
+    }
+
+    ///返回用户态前调用 统计内核态时间
+    pub fn trap_exit(&self){
+        self.task_que_inner.lock().current_task().usage.trap_exit(get_time_tick());
+    }
+
+    ///当前任务缺页次数+1
+    pub fn record_page_fault(&self){
+        self.task_que_inner.lock().current_task().usage.page_faults+=1;
+    }
+
+    ///当前任务的资源使用快照 内核态时间统计到现在
+    pub fn get_current_usage(&self)->TaskUsage{
+        let mut inner=self.task_que_inner.lock();
+        let usage=&mut inner.current_task().usage;
+        usage.trap_exit(get_time_tick());
+        usage.clone()
+    }
+
+    ///当前任务pid
+    pub fn get_current_pid(&self)->usize{
+        self.task_que_inner.lock().current
+    }
+
+    ///所有任务页表占用的页帧数 包括内核地址空间
+    pub fn page_table_frames(&self)->usize{
+        let inner=self.task_que_inner.lock();
+        let user:usize=inner.task_map.values().map(|task|task.memory_set.table.table_frames()).sum();
+        user+KERNEL_SPACE.lock().table.table_frames()
+    }
+
+    ///任务地址空间的所有area pid不存在返回None
+    pub fn get_maps(&self,pid:usize)->Option<Vec<MapInfo>>{
+        let mut inner=self.task_que_inner.lock();
+        inner.task_mut(pid).map(|task|task.memory_set.area_infos())
+    }
+
+    ///获取任务权重 pid不存在返回None
+    pub fn get_ticket(&self,pid:usize)->Option<usize>{
+        let mut inner=self.task_que_inner.lock();
+        inner.task_mut(pid).map(|task|task.sched.ticket)
+    }
+
+    ///设置任务权重并重新计算步长 pid不存在返回false
+    pub fn set_ticket(&self,pid:usize,ticket:usize)->bool{
+        let mut inner=self.task_que_inner.lock();
+        match inner.task_mut(pid) {
+            Some(task)=>{
+                task.sched.set_ticket(ticket);
+                true
+            }
+            None=>false
+        }
+    }
+
     pub fn task_queen_is_empty(&self)->bool{
         let inner=self.task_que_inner.lock();
-        let result= inner.task_queen.is_empty();
+        let result= inner.task_map.is_empty();
         drop(inner);
         debug!("task queen empty?:{}",result);
         result
     }
 
 
-    ///运行第一个任务
+    ///运行第一个任务 当前启动栈上的上下文保存为idle上下文
     pub fn run_first_task(&self) -> ! {
       let mut inner=self.task_que_inner.lock();//记得drop
-      let curren_task_index=inner.current;
-      let task = &mut inner.task_queen[curren_task_index];
-      let task_cx_ptr = &mut task.task_context as *mut TaskContext;
-      let kernel_task_cx=TaskContext::zero_init();
-      //标记为running
-      task.task_statut=TaskStatus::Runing;
-      //增加步长
-      task.pass+=task.stride;
+      debug!("Scheduler:{}",inner.scheduler.name());
+      let task_cx_ptr=inner.pick_and_run().expect("No task to run!");
       drop(inner);//越早越好
       // 调用 __switch 切换到第一个任务
       // __switch 会：
-      // 1. 保存 _unused 的上下文（虽然我们不会再用到）
+      // 1. 把启动栈的上下文保存为idle上下文
       // 2. 恢复 next_task_cx_ptr 指向的上下文
       // 3. 跳转到 task.task_context.ra，即 app_entry_point
       unsafe {
-        __switch(&kernel_task_cx as *const TaskContext, task_cx_ptr);
+        __switch(get_idle_task_cx_ptr(), task_cx_ptr);
       }
-      
-      panic!("unreachable in run_first_task!");
+      //没有READY任务时会切换回这里
+      idle_loop();
+    }
+
+    ///由idle调用 有READY任务则切换过去并返回true，任务让出到idle后从这里返回
+    pub fn run_ready_task_from_idle(&self)->bool{
+        let mut inner=self.task_que_inner.lock();
+        let need_swap_in=match inner.pick_and_run(){
+            Some(task_cx)=>task_cx,
+            None=>return false,
+        };
+        drop(inner);
+        unsafe {
+            __switch(get_idle_task_cx_ptr(), need_swap_in);
+        }
+        true
+    }
+
+    ///当前任务退出并运行下一个任务，init退出时关机 调用栈顶必须为TrapHandler
+    pub fn exit_current_and_run_next(&self)->!{
+        let inner=self.task_que_inner.lock();
+        let pid=inner.current;
+        drop(inner);
+        if pid==INIT_PID{
+            error!("Init process exited! Shutting down...");
+            BlueosFS::sync();
+            shutdown();
+        }
+        self.remove_current_task();//移除当前任务块,当前任务块就不存在了
+        //退出任务的上下文不会再恢复，保存到临时位置
+        let mut unused_cx=TaskContext::zero_init();
+        let mut inner=self.task_que_inner.lock();
+        let need_swap_in=inner.pick_and_run().unwrap_or_else(get_idle_task_cx_ptr);
+        drop(inner);
+        unsafe {
+            __switch(&mut unused_cx as *mut TaskContext, need_swap_in);
+        }
+        panic!("unreachable in exit_current_and_run_next!");
     }
 
     ///获取当前任务的页表stap
     pub fn get_current_stap(&self)->usize{
         let mut inner= self.task_que_inner.lock();
-        let current_task:usize=inner.current;
-        let  task_memset = &mut inner.task_queen[current_task].memory_set;
+        let  task_memset = &mut inner.current_task().memory_set;
         let stap = task_memset.get_table().satp_token();
         drop(inner);
         stap
@@ -343,9 +589,8 @@ impl TaskManager {//全局唯一
 
     ///获取当前任务的陷阱上下文可变引用
     pub fn get_current_trapcx(&self)->&mut TrapContext{
-        let inner =self.task_que_inner.lock();
-        let curren_task_index=inner.current;
-        let task_trap_ppn = inner.task_queen[curren_task_index].trap_context_ppn;
+        let mut inner =self.task_que_inner.lock();
+        let task_trap_ppn = inner.current_task().trap_context_ppn;
         let origin_phyaddr =( task_trap_ppn*PAGE_SIZE) as *mut TrapContext;
         let trap_context =unsafe {
             &mut *origin_phyaddr
@@ -356,20 +601,47 @@ impl TaskManager {//全局唯一
 
     ///获取当前任务的文件描述符
     pub fn get_current_fd(&self, fd: usize) -> Option<Arc<FileDescriptor>> {
-        let inner = self.task_que_inner.lock();
-        let current_task = inner.current;
-        let fd_table = &inner.task_queen[current_task].file_descriptor;
-        let result = fd_table.get(fd).cloned();
+        let mut inner = self.task_que_inner.lock();
+        let fd_table = &inner.current_task().file_descriptor;
+        let result = fd_table.get(fd).cloned().flatten();
         drop(inner);
         result
     }
 
+    ///把文件描述符加入当前任务 返回最小可用的fd
+    pub fn alloc_current_fd(&self, file: Arc<FileDescriptor>) -> usize {
+        let mut inner = self.task_que_inner.lock();
+        let fd_table = &mut inner.current_task().file_descriptor;
+        match fd_table.iter().position(|slot| slot.is_none()) {
+            Some(fd) => {
+                fd_table[fd] = Some(file);
+                fd
+            }
+            None => {
+                fd_table.push(Some(file));
+                fd_table.len() - 1
+            }
+        }
+    }
+
+    ///关闭当前任务的文件描述符 fd不存在返回false
+    pub fn close_current_fd(&self, fd: usize) -> bool {
+        let mut inner = self.task_que_inner.lock();
+        let fd_table = &mut inner.current_task().file_descriptor;
+        match fd_table.get_mut(fd) {
+            Some(slot) if slot.is_some() => {
+                *slot = None;
+                true
+            }
+            _ => false
+        }
+    }
+
 
     ///kail当前任务，内核有权调用 调用栈顶必须为TrapHandler! 调用它的地方考虑是否直接return
     pub fn kail_current_task_and_run_next(&self){
-        self.remove_current_task();//删除对应任务块
-        self.suspend_and_run_task();//调度下一个stride最小的任务
         error!("Task Kailed!");
+        self.exit_current_and_run_next();//删除对应任务块 调度下一个任务
     }
 
 
@@ -397,26 +669,32 @@ lazy_static! {
         let app_count = crate::task::get_app_count();
         debug!("Found {} applications to load", app_count);
         
-        let mut task_deque = VecDeque::new();
+        let manager = TaskManager {
+            task_que_inner: UPSafeCell::new(TaskManagerInner {
+                task_map: BTreeMap::new(),
+                current: INIT_PID,  // 第一个加载的任务
+                scheduler: SCHED_POLICY.lock().create_scheduler(),
+            })
+        };
         
         // 加载所有应用程序
         for app_id in 0..app_count {
             debug!("Loading application {}...", app_id);
             // app_id 从 0 开始，kernel_stack_id 从 1 开始
-            let mut task = TaskControlBlock::new(app_id, app_id + 1);
             //task.task_statut=TaskStatus::Ready; 在new已经设置为ready
-            task_deque.push_back(task);
-            debug!("Application {} loaded successfully", app_id);
+            match TaskControlBlock::new(app_id, app_id + 1) {
+                Ok(task)=>{
+                    manager.add_task(task);
+                    debug!("Application {} loaded successfully", app_id);
+                }
+                //第一个应用是init，跳过的话下一个应用会拿到init的pid
+                Err(err) if app_id==0=>panic!("Init load failed: {:?}", err),
+                Err(err)=>error!("Application {} load failed: {:?}", app_id, err),
+            }
         }
         
-        debug!("All {} applications loaded into task queue", app_count);
-        
-        TaskManager {
-            task_que_inner: UPSafeCell::new(TaskManagerInner {
-                task_queen: task_deque,
-                current: 0  // 初始化为第一个任务
-            })
-        }
+        debug!("All {} applications loaded into task map", app_count);
+        manager
     };
 }
 ///返回单个app的内核栈地址（在内核地址空间）
@@ -116,6 +116,7 @@ pub fn set_kernel_forbid(){
 #[no_mangle]
 pub extern "C" fn app_entry_point() {
     set_kernel_trap_handler();
+    TASK_MANAER.trap_exit();//统计内核态时间
     let user_satp = TASK_MANAER.get_current_stap();
     let restore_va = __kernel_refume as usize - __kernel_trap as usize + TRAP_BOTTOM_ADDR;
     //error!("Resrore_va:{:#x}",restore_va);
@@ -140,12 +141,13 @@ use riscv::register::sepc;
 ///handler必须返回到trap里面去
 pub extern "C" fn kernel_trap_handler(){//内核专属trap（目前不应该被调用）
     set_kernel_forbid();
+    TASK_MANAER.trap_enter();//统计用户态时间
     let scauses = scause::read();
     let sepc_val = sepc::read();
     let stval_val = stval::read();
     let current_trapcx= TASK_MANAER.get_current_trapcx();
     let a1=current_trapcx.x[17];
-    let a2 =[current_trapcx.x[10],current_trapcx.x[11],current_trapcx.x[12]];
+    let a2 =[current_trapcx.x[10],current_trapcx.x[11],current_trapcx.x[12],current_trapcx.x[13],current_trapcx.x[14],current_trapcx.x[15]];
         match scauses.cause(){
         Trap::Exception(Exception::UserEnvCall)=>{
             debug!("pre sepc:{:#x}",current_trapcx.sepc_entry_point);
@@ -175,7 +177,7 @@ pub extern "C" fn kernel_trap_handler(){//内核专属trap（目前不应该被
             set_next_timeInterupt();
             //error!("timer interrupt");
              //print!("time");
-            TASK_MANAER.suspend_and_run_task();
+            TASK_MANAER.on_tick();//由调度器决定是否抢占
         }
         Trap::Interrupt(Interrupt::SupervisorExternal)=>{
             //外部中断，键盘等
@@ -11,6 +11,7 @@ use crate::{memory::{PageTable, VirAddr, VirNumRange, VirNumber}, task::TASK_MAN
 ///pagefault触发时的环境可能为内核，可能为用户态 内核态可能是在帮用户处理程序->合法,User态->合法
 pub fn PageFaultHandler(faultVAddr:VirAddr){
     debug!("Handle Fault Virtual Address:{:#x}",faultVAddr.0);
+    TASK_MANAER.record_page_fault();
     let contain_vpn:VirNumber=faultVAddr.floor_down();
     let tsak_satp=TASK_MANAER.get_current_stap();
     let mut map_layer:PageTable=PageTable::crate_table_from_satp(tsak_satp);//临时的页表视图
@@ -34,11 +35,19 @@ pub fn PageFaultHandler(faultVAddr:VirAddr){
 
 
     //是否有对应area
-    let inner=TASK_MANAER.task_que_inner.lock();
-    let current=inner.current;
-    drop(inner);
     let mut inner=TASK_MANAER.task_que_inner.lock();
-    let mut memset=&mut inner.task_queen[current].memory_set;
+    //空闲页帧不足时先换出其他匿名页
+    inner.reclaim_frames();
+    let memset=&mut inner.current_task().memory_set;
+    //栈溢出到guard page，杀掉
+    if memset.is_stack_guard(contain_vpn){
+        error!("user stack overflow at {:#x}, kill!",faultVAddr.0);
+        drop(inner);
+        TASK_MANAER.kail_current_task_and_run_next();
+        return;
+    }
+    //栈增长范围内的缺页先扩展栈area
+    memset.grow_stack(contain_vpn);
     //有areacontain并且都是mmap类型的area
     if !memset.AallArea_Iscontain_thisVpn(contain_vpn) || !memset.AllArea_NoDefaultType(VirNumRange(contain_vpn,contain_vpn)){
         //没有area包含mmap的地址，杀掉
@@ -54,7 +63,22 @@ pub fn PageFaultHandler(faultVAddr:VirAddr){
     //2.分配物理页帧挂载到对应的maparea下面
     //3.设置合法页表项
     //一部到位
-    memset.findarea_allocFrame_and_setPte(contain_vpn);
+    match memset.findarea_allocFrame_and_setPte(contain_vpn){
+        Ok(true)=>{}
+        Ok(false)=>{
+            //PROT_NONE的area不能访问
+            error!("area has no access permission, kill!");
+            drop(inner);
+            TASK_MANAER.kail_current_task_and_run_next();
+            return;
+        }
+        Err(_)=>{
+            //物理内存耗尽，杀掉最大的任务，当前任务存活的话返回用户态重新触发缺页
+            drop(inner);
+            TASK_MANAER.oom_kill();
+            return;
+        }
+    }
   
 
     
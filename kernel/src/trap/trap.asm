@@ -41,7 +41,13 @@ ld t1,36*8(sp)
 ld sp,35*8(sp)
 
 csrw satp,t0
+#ASID为0(没有私有ASID或者硬件不支持)时刷新整个TLB，否则TLB项按ASID区分不用刷新
+csrr t0,satp
+slli t0,t0,4
+srli t0,t0,48
+bnez t0,1f
 sfence.vma
+1:
 jr t1#跳转到traphandler
 
 
@@ -53,7 +59,13 @@ __kernel_refume: #a0 trap_context_addr a1:user satp
 
 #切换为用户地址空间
 csrw satp ,a1
+#ASID为0时刷新整个TLB 页表修改时已经按ASID刷新过
+csrr t0,satp
+slli t0,t0,4
+srli t0,t0,48
+bnez t0,2f
 sfence.vma #刷新页表
+2:
 
 #让sscratch指向所有任务通用的trapcontext
 csrw sscratch,a0 
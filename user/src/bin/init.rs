@@ -2,14 +2,23 @@
 #![no_main]
 
 use core::usize;
-use user_lib::sys_yield;
+use user_lib::sys_wait;
 use user_lib::{StdinBuffer, String, getchar, print, println};
 extern crate user_lib;
 
 
 #[no_mangle]
-pub fn main(){
+pub fn main()->usize{
     println!("BlueStarOS---------------------------------------------");
     println!("CopyRight -> Dirinkbottle 2025");
-    return;
+    //回收所有子任务，没有子任务时退出 init退出内核就会关机
+    loop {
+        let pid=sys_wait();
+        if pid<0{
+            break;
+        }
+        println!("init: task {} exited",pid);
+    }
+    println!("init: no task left, power off");
+    0
 }
@@ -0,0 +1,52 @@
+///用户堆 buddy分配器用完时通过brk向内核申请扩展，不再占用固定的.bss数组
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::NonNull;
+use buddy_system_allocator::{Heap, LockedHeap};
+use crate::syscall::sys_brk;
+
+const PAGE_SIZE:usize=4096;
+///每次至少扩展的大小
+const HEAP_GROW_SIZE:usize=16*PAGE_SIZE;
+
+pub struct BrkHeap(LockedHeap);
+
+impl BrkHeap {
+    pub const fn empty()->Self{
+        BrkHeap(LockedHeap::empty())
+    }
+}
+
+///通过brk扩展堆，新区域至少包含一个满足layout的对齐块 失败返回false
+fn grow(heap:&mut Heap,layout:&Layout)->bool{
+    //buddy块按自身大小对齐，扩展两倍块大小才能保证包含一个完整的对齐块
+    let block=layout.size().max(layout.align()).next_power_of_two();
+    let size=(block*2).max(HEAP_GROW_SIZE);
+    let size=(size+PAGE_SIZE-1)/PAGE_SIZE*PAGE_SIZE;
+    let old_brk=sys_brk(0) as usize;
+    let new_brk=sys_brk(old_brk+size) as usize;
+    if new_brk!=old_brk+size{
+        return false;
+    }
+    unsafe {
+        heap.add_to_heap(old_brk, new_brk);
+    }
+    true
+}
+
+unsafe impl GlobalAlloc for BrkHeap {
+    unsafe fn alloc(&self,layout:Layout)->*mut u8 {
+        let mut heap=self.0.lock();
+        loop {
+            if let Ok(ptr)=heap.alloc(layout){
+                return ptr.as_ptr();
+            }
+            if !grow(&mut heap, &layout){
+                return core::ptr::null_mut();
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self,ptr:*mut u8,layout:Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+}
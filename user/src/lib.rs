@@ -5,19 +5,16 @@ extern crate alloc;
 mod panic;
 mod syscall;
 mod console;
+mod heap;
 pub use alloc::string::String;
-use buddy_system_allocator::LockedHeap;
+use heap::BrkHeap;
 ///BlueStarOS标准用户库
-const USER_HEAP_SIZE:usize=40960;
-static mut USER_HEAP_SPACE:[usize;USER_HEAP_SIZE]=[0;USER_HEAP_SIZE];
+///用户堆 第一次分配时通过brk向内核申请
 #[global_allocator]
-static mut USER_HEAP_ALLOCTER:LockedHeap=LockedHeap::empty();
+static USER_HEAP_ALLOCTER:BrkHeap=BrkHeap::empty();
 #[link_section = ".text.entry"]
 #[no_mangle]
 pub extern "C" fn _start()->!{
-    unsafe {
-        USER_HEAP_ALLOCTER.lock().init(USER_HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
-    }
     let code=main();
     sys_exit(code);
     panic!("_start UnReachBle!");
@@ -39,8 +36,13 @@ pub fn readline(ptr:usize,len:usize)->isize{//返回读取的字符数量 目前
   syscall::sys_read(FD_TYPE_STDIN, ptr, len)
 }
 
+///在start处匿名映射可读写内存 成功返回0，失败返回-1
 pub fn map(start:usize,len:usize)->isize{
-  syscall::sys_map(start, len)
+  let offset=start%4096;
+  match syscall::sys_mmap(start-offset, len+offset, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, usize::MAX, 0) {
+    -1=>-1,
+    _=>0
+  }
 }
 
 pub fn unmap(start:usize,len:usize)->isize{
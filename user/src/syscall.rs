@@ -4,6 +4,87 @@ const SYS_EXIT:usize=3;//exit程序结束，运行下一个程序
 const SYS_YIELD:usize=4;//主动放弃一次cpu
 const SYS_MAP:usize=5;//SYSMAP
 const SYS_UNMAP:usize=6;//SYSUNMAP
+const SYS_REBOOT:usize=12;//关机/重启
+const REBOOT_CMD_POWER_OFF:usize=0;
+const REBOOT_CMD_RESTART:usize=1;
+const SYS_SETPRIORITY:usize=13;//设置优先级(ticket)
+const SYS_GETPRIORITY:usize=14;//获取优先级(ticket)
+///优先级系统调用中表示当前任务
+pub const PRIO_SELF:usize=usize::MAX;
+const SYS_GETRUSAGE:usize=15;//资源使用统计
+const SYS_BRK:usize=16;//调整program break
+const SYS_OPEN:usize=17;//打开文件
+const SYS_CLOSE:usize=18;//关闭文件
+const SYS_MSYNC:usize=19;//写回共享映射
+const SYS_MPROTECT:usize=20;//修改映射访问权限
+const SYS_MEMINFO:usize=21;//系统内存使用统计
+const SYS_MAPS:usize=22;//列出地址空间的area
+const SYS_WAIT:usize=23;//等待子任务退出
+/* mmap访问权限 */
+pub const PROT_NONE:usize=0;
+pub const PROT_READ:usize=1;
+pub const PROT_WRITE:usize=2;
+pub const PROT_EXEC:usize=4;
+/* mmap标志 */
+pub const MAP_SHARED:usize=0x01;
+pub const MAP_PRIVATE:usize=0x02;
+pub const MAP_FIXED:usize=0x10;
+pub const MAP_ANONYMOUS:usize=0x20;
+/* open标志 */
+pub const O_RDONLY:usize=0x0;
+pub const O_WRONLY:usize=0x1;
+pub const O_RDWR:usize=0x2;
+pub const O_CREAT:usize=0x40;
+pub const O_TRUNC:usize=0x200;
+pub const O_APPEND:usize=0x400;
+/* 错误码 与内核保持一致 内核访问用户内存失败时返回-EFAULT，其他错误返回-1 */
+pub const EFAULT:isize=14;//地址没有映射或者没有访问权限
+pub const RUSAGE_SELF:usize=0;
+pub const RUSAGE_CHILDREN:usize=usize::MAX;
+
+///资源使用统计 与内核保持一致
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RUsage{
+    pub utime_us:usize,     //用户态时间（微秒）
+    pub stime_us:usize,     //内核态时间（微秒）
+    pub nvcsw:usize,        //主动让出次数
+    pub nivcsw:usize,       //被抢占次数
+    pub minflt:usize,       //缺页次数
+}
+///系统内存使用 与内核保持一致 页帧相关的单位为页，堆为字节
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MemInfo{
+    pub frames_total:usize,     //物理页帧总数
+    pub frames_free:usize,      //空闲页帧
+    pub frames_peak:usize,      //已分配页帧的峰值
+    pub heap_total:usize,       //内核堆大小（字节）
+    pub heap_used:usize,        //内核堆已分配（字节）
+    pub heap_peak:usize,        //内核堆已分配的峰值（字节）
+    pub heap_failed:usize,      //内核堆分配失败次数
+    pub page_table_frames:usize,//所有地址空间页表占用的页帧
+    pub swap_used:usize,        //已用交换槽
+    pub swap_total:usize,       //交换槽总数
+}
+/* MapInfo的area类型 */
+pub const AREA_DEFAULT:usize=0;
+pub const AREA_MMAP:usize=1;
+pub const AREA_ELF:usize=2;
+pub const AREA_STACK:usize=3;
+///地址空间中的一个area 与内核保持一致
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MapInfo{
+    pub start:usize,        //起始地址
+    pub end:usize,          //结束地址（不包含）
+    pub prot:usize,         //PROT_READ/PROT_WRITE/PROT_EXEC
+    pub area_type:usize,    //AREA_*
+    pub resident:usize,     //常驻页帧数
+    pub swapped:usize,      //换出的页数
+    pub file_backed:usize,  //是否有后备文件
+    pub shared:usize,       //是否MAP_SHARED
+}
 ///syscall封装 3个参数版本
 pub fn sys_call(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -19,18 +100,59 @@ pub fn sys_call(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
-pub fn sys_map(startAddr:usize,len:usize)->isize{
-    sys_call(SYS_MAP,[startAddr,len,0])
+///syscall封装 6个参数版本
+pub fn sys_call6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
+            in("x17") id
+        );
+    }
+    ret
+}
+
+///mmap 返回映射地址，失败返回-1 匿名映射fd传usize::MAX
+pub fn sys_mmap(addr:usize,len:usize,prot:usize,flags:usize,fd:usize,offset:usize)->isize{
+    sys_call6(SYS_MAP,[addr,len,prot,flags,fd,offset])
+}
+
+///把[addr,addr+len)内的共享文件映射写回文件 范围内没有映射返回-EFAULT
+pub fn sys_msync(addr:usize,len:usize)->isize{
+    sys_call(SYS_MSYNC,[addr,len,0])
+}
+
+///修改[addr,addr+len)的访问权限 addr页对齐，不允许同时PROT_WRITE|PROT_EXEC 成功返回0，范围没有全部映射返回-EFAULT，其他失败返回-1
+pub fn sys_mprotect(addr:usize,len:usize,prot:usize)->isize{
+    sys_call(SYS_MPROTECT,[addr,len,prot])
+}
+
+///打开文件 path必须以\0结尾 返回文件描述符，path不可读返回-EFAULT，其他失败返回-1
+pub fn sys_open(path:&str,flags:usize)->isize{
+    sys_call(SYS_OPEN,[path.as_ptr() as usize,flags,0])
+}
+
+///关闭文件描述符
+pub fn sys_close(fd:usize)->isize{
+    sys_call(SYS_CLOSE,[fd,0,0])
 }
 
 pub fn sys_unmap(startAddr:usize,len:usize)->isize{
     sys_call(SYS_UNMAP,[startAddr,len,0])
 }
 
+///读文件描述符 返回读到的字节数，buffer不可写返回-EFAULT，其他失败返回-1
 pub fn sys_read(fd_type:usize,buffer_ptr:usize,buffer_len:usize)->isize{
     sys_call(SYS_READ, [buffer_ptr,fd_type,buffer_len])
 }
 
+///写文件描述符 返回写入的字节数，buffer不可读返回-EFAULT，其他失败返回-1
 pub fn sys_write(fd_type:usize,buffer_ptr:usize,buffer_len:usize)->isize{
     sys_call(SYS_WRITE, [buffer_ptr,fd_type,buffer_len])
 }
@@ -49,3 +171,48 @@ pub fn sys_yield(){
     sys_call(SYS_YIELD, [0;3]);
 }
 
+///等待任意一个子任务退出 返回子任务pid，没有子任务返回-1
+pub fn sys_wait()->isize{
+    sys_call(SYS_WAIT, [0;3])
+}
+
+
+///关机 成功时不返回
+pub fn sys_poweroff()->isize{
+    sys_call(SYS_REBOOT, [REBOOT_CMD_POWER_OFF,0,0])
+}
+
+///重启 成功时不返回
+pub fn sys_reboot()->isize{
+    sys_call(SYS_REBOOT, [REBOOT_CMD_RESTART,0,0])
+}
+
+///设置任务优先级(ticket) 非init任务只能降低自己的优先级 成功返回0
+pub fn sys_setpriority(pid:usize,ticket:usize)->isize{
+    sys_call(SYS_SETPRIORITY, [pid,ticket,0])
+}
+
+///获取任务优先级(ticket) 失败返回-1
+pub fn sys_getpriority(pid:usize)->isize{
+    sys_call(SYS_GETPRIORITY, [pid,0,0])
+}
+
+///获取资源使用统计 who:RUSAGE_SELF或RUSAGE_CHILDREN 成功返回0，who非法返回-1，usage不可写返回-EFAULT
+pub fn sys_getrusage(who:usize,usage:&mut RUsage)->isize{
+    sys_call(SYS_GETRUSAGE, [who,usage as *mut RUsage as usize,0])
+}
+
+///获取系统内存使用统计 成功返回0，info不可写返回-EFAULT
+pub fn sys_meminfo(info:&mut MemInfo)->isize{
+    sys_call(SYS_MEMINFO, [info as *mut MemInfo as usize,0,0])
+}
+
+///列出任务pid(PRIO_SELF为当前任务)的area 最多填满maps，返回area总数，pid不存在返回-1，maps不可写返回-EFAULT
+pub fn sys_maps(pid:usize,maps:&mut [MapInfo])->isize{
+    sys_call(SYS_MAPS, [pid,maps.as_mut_ptr() as usize,maps.len()])
+}
+
+///调整program break new_brk为0时查询 返回新的brk，失败时返回原来的brk
+pub fn sys_brk(new_brk:usize)->isize{
+    sys_call(SYS_BRK, [new_brk,0,0])
+}